@@ -1,5 +1,9 @@
+#![allow(non_snake_case)]
+
+use base64::Engine;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Read, Write};
 use std::path::{Component, Path, PathBuf};
@@ -7,15 +11,81 @@ use std::process::{Command, ExitCode, Stdio};
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
-const PROTOCOL_VERSION: i32 = 1;
+const PROTOCOL_VERSION: i32 = 2;
 const COMMANDS: &[&str] = &[
     "worktree.ensure",
     "worktree.remove",
+    "worktree.list",
+    "worktree.prune",
+    "worktree.move",
+    "worktree.lock",
+    "worktree.unlock",
+    "worktree.repair",
+    "worktree.inspect",
     "patch.apply",
+    "patch.generate",
+    "patch.validate",
     "git.commit_all",
+    "git.status",
+    "git.log",
+    "git.diff",
+    "git.show",
+    "git.branch.list",
+    "git.branch.delete",
+    "git.branch.rename",
+    "git.fetch",
+    "git.push",
+    "git.merge_base",
+    "git.cherry_pick",
+    "git.reset",
+    "git.stash",
+    "git.stash_pop",
+    "git.rebase",
+    "git.tag",
+    "git.config.get",
+    "git.config.set",
+    "git.rev_parse",
+    "git.ls_files",
+    "git.clean",
+    "git.ls_remote",
+    "git.describe",
+    "git.check_ignore",
+    "git.update_ref",
+    "git.merge",
+    "git.submodule.update",
+    "git.submodule.list",
+    "git.format_patch",
+    "git.am",
+    "git.remote.list",
+    "git.remote.add",
+    "git.reflog",
+    "git.archive",
+    "git.count_objects",
+    "git.gc",
+    "git.init",
+    "git.bisect",
+    "git.worktree.head",
+    "git.diff.cached",
+    "git.symbolic_ref",
+    "git.notes",
+    "git.bundle",
+    "git.credential",
+    "fs.read",
+    "fs.write",
+    "fs.atomic_write",
+    "fs.list",
+    "fs.stat",
+    "fs.delete",
+    "fs.copy",
+    "fs.hash",
     "verify.run",
+    "verify.clean",
+    "verify.compare",
+    "verify.safe_name",
+    "verify.output_path",
     "protocol.version",
     "repo.info",
+    "batch",
 ];
 
 fn now_iso() -> String {
@@ -24,22 +94,248 @@ fn now_iso() -> String {
         .unwrap_or_else(|_| "1970-01-01T00:00:00Z".to_string())
 }
 
-fn read_stdin_json<T: for<'de> Deserialize<'de>>() -> Result<T, String> {
-    let mut buf = String::new();
-    io::stdin()
-        .read_to_string(&mut buf)
-        .map_err(|e| format!("failed to read stdin: {e}"))?;
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug)]
+enum KernelError {
+    Io(io::Error),
+    GitFailed {
+        command: String,
+        exit_code: i32,
+        stderr: String,
+    },
+    /// `git update-ref` rejected the update because the ref no longer holds `oldValue`.
+    RefCasFailed {
+        ref_name: String,
+        exit_code: i32,
+        stderr: String,
+    },
+    InvalidInput(String),
+    PathTraversal(String),
+    OwnershipViolation(Vec<String>),
+    NotFound(String),
+    AlreadyExists(String),
+    Timeout {
+        command: String,
+        timeout_secs: u64,
+    },
+}
+
+impl KernelError {
+    /// Stable machine-readable name of the variant.
+    fn kind(&self) -> &'static str {
+        match self {
+            KernelError::Io(_) => "io",
+            KernelError::GitFailed { .. } | KernelError::RefCasFailed { .. } => "gitFailed",
+            KernelError::InvalidInput(_) => "invalidInput",
+            KernelError::PathTraversal(_) => "pathTraversal",
+            KernelError::OwnershipViolation(_) => "ownershipViolation",
+            KernelError::NotFound(_) => "notFound",
+            KernelError::AlreadyExists(_) => "alreadyExists",
+            KernelError::Timeout { .. } => "timeout",
+        }
+    }
+
+    /// Structured context for callers; `{}` when the message says it all.
+    fn details(&self) -> serde_json::Value {
+        match self {
+            KernelError::Io(e) => serde_json::json!({ "ioKind": format!("{:?}", e.kind()) }),
+            KernelError::GitFailed {
+                command,
+                exit_code,
+                stderr,
+            } => serde_json::json!({
+                "command": command,
+                "exitCode": exit_code,
+                "stderr": stderr,
+            }),
+            KernelError::RefCasFailed {
+                ref_name,
+                exit_code,
+                stderr,
+            } => serde_json::json!({
+                "command": "git update-ref",
+                "exitCode": exit_code,
+                "stderr": stderr,
+                "casFailure": true,
+                "ref": ref_name,
+            }),
+            KernelError::OwnershipViolation(violations) => {
+                serde_json::json!({ "violations": violations })
+            }
+            KernelError::Timeout {
+                command,
+                timeout_secs,
+            } => serde_json::json!({ "command": command, "timeoutSecs": timeout_secs }),
+            _ => serde_json::json!({}),
+        }
+    }
+
+    fn to_response(&self) -> KernelResponse<()> {
+        KernelResponse {
+            ok: false,
+            result: None,
+            error: Some(ErrorBody {
+                kind: self.kind(),
+                message: self.to_string(),
+                details: self.details(),
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for KernelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KernelError::Io(e) => write!(f, "{e}"),
+            KernelError::GitFailed {
+                command,
+                exit_code,
+                stderr,
+            } => {
+                if stderr.is_empty() {
+                    write!(f, "{command} failed (exit {exit_code})")
+                } else {
+                    write!(f, "{stderr}")
+                }
+            }
+            KernelError::RefCasFailed {
+                ref_name, stderr, ..
+            } => write!(f, "{REF_CAS_FAILURE}: {ref_name}: {stderr}"),
+            KernelError::Timeout {
+                command,
+                timeout_secs,
+            } => write!(f, "{command} timed out after {timeout_secs}s"),
+            KernelError::OwnershipViolation(violations) => write!(
+                f,
+                "patch ownership check failed:\n- {}",
+                violations.join("\n- ")
+            ),
+            KernelError::InvalidInput(msg)
+            | KernelError::PathTraversal(msg)
+            | KernelError::NotFound(msg)
+            | KernelError::AlreadyExists(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for KernelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KernelError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KernelError {
+    fn from(e: io::Error) -> Self {
+        KernelError::Io(e)
+    }
+}
+
+/// Envelope for everything written to stdout: `{ ok, result }` or `{ ok, error }`.
+#[derive(Serialize)]
+struct KernelResponse<T: Serialize> {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorBody>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    kind: &'static str,
+    message: String,
+    details: serde_json::Value,
+}
+
+/// Adapter for `map_err` that keeps the io error kind but prefixes `context` to the message.
+fn io_err(context: impl std::fmt::Display) -> impl FnOnce(io::Error) -> KernelError {
+    move |e| KernelError::Io(io::Error::new(e.kind(), format!("{context}: {e}")))
+}
+
+/// Where JSON input is read from and the response envelope is written to.
+#[derive(Debug, Clone, PartialEq)]
+enum IoMode {
+    Stdio,
+    File(PathBuf),
+}
+
+fn read_input_json<T: for<'de> Deserialize<'de>>(mode: &IoMode) -> Result<T, KernelError> {
+    let (buf, source) = match mode {
+        IoMode::Stdio => {
+            let mut buf = String::new();
+            io::stdin()
+                .read_to_string(&mut buf)
+                .map_err(io_err("failed to read stdin"))?;
+            (buf, "stdin".to_string())
+        }
+        IoMode::File(path) => {
+            let buf = fs::read_to_string(path).map_err(io_err(format!(
+                "failed to read input file {}",
+                path.display()
+            )))?;
+            (buf, path.display().to_string())
+        }
+    };
     let trimmed = buf.trim();
     if trimmed.is_empty() {
-        return Err("missing JSON input on stdin".to_string());
+        return Err(KernelError::InvalidInput(format!(
+            "missing JSON input on {source}"
+        )));
     }
-    serde_json::from_str(trimmed).map_err(|e| format!("invalid JSON input: {e}"))
+    serde_json::from_str(trimmed)
+        .map_err(|e| KernelError::InvalidInput(format!("invalid JSON input: {e}")))
 }
 
-fn write_stdout_json<T: Serialize>(value: &T) -> Result<(), String> {
-    let out = serde_json::to_string(value).map_err(|e| format!("failed to serialize JSON: {e}"))?;
-    print!("{out}");
-    Ok(())
+#[derive(Debug, Clone, Copy, Default)]
+struct OutputConfig {
+    pretty: bool,
+}
+
+thread_local! {
+    /// Set once from the global CLI flags before dispatch.
+    static OUTPUT_CONFIG: std::cell::Cell<OutputConfig> = const { std::cell::Cell::new(OutputConfig { pretty: false }) };
+    /// `--dry-run`: commands outside `READ_ONLY_COMMANDS` are reported instead of run.
+    static DRY_RUN: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn render_json<T: Serialize>(value: &T) -> Result<String, KernelError> {
+    let res = if OUTPUT_CONFIG.with(|c| c.get().pretty) {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    res.map_err(|e| io_err("failed to serialize JSON")(e.into()))
+}
+
+fn write_response<T: Serialize>(
+    mode: &IoMode,
+    response: &KernelResponse<T>,
+) -> Result<(), KernelError> {
+    let out = render_json(response)?;
+    match mode {
+        IoMode::Stdio => {
+            print!("{out}");
+            Ok(())
+        }
+        IoMode::File(path) => fs_atomic_write(path, out.as_bytes()),
+    }
+}
+
+fn write_output_json<T: Serialize>(mode: &IoMode, value: &T) -> Result<(), KernelError> {
+    write_response(
+        mode,
+        &KernelResponse {
+            ok: true,
+            result: Some(value),
+            error: None,
+        },
+    )
 }
 
 #[derive(Debug)]
@@ -50,24 +346,284 @@ struct CmdOut {
     stderr: String,
 }
 
-fn run_cmd(program: &str, args: &[String], cwd: Option<&Path>) -> Result<CmdOut, String> {
+fn run_cmd(program: &str, args: &[String], cwd: Option<&Path>) -> Result<CmdOut, KernelError> {
+    let mut out = run_cmd_untrimmed(program, args, cwd)?;
+    out.stdout.truncate(out.stdout.trim_end().len());
+    out.stderr.truncate(out.stderr.trim_end().len());
+    Ok(out)
+}
+
+/// Like `run_cmd` but keeps stdout byte-for-byte (e.g. file contents read via git).
+fn run_cmd_untrimmed(
+    program: &str,
+    args: &[String],
+    cwd: Option<&Path>,
+) -> Result<CmdOut, KernelError> {
+    run_cmd_with_stdin(program, args, cwd, None)
+}
+
+/// Runs a command, optionally feeding `stdin`; output is left untrimmed.
+fn run_cmd_with_stdin(
+    program: &str,
+    args: &[String],
+    cwd: Option<&Path>,
+    stdin: Option<&[u8]>,
+) -> Result<CmdOut, KernelError> {
+    run_cmd_with_timeout(program, args, cwd, stdin, &[], command_timeout())
+}
+
+/// The single place child processes are spawned for captured output. With a `timeout`
+/// the child runs in its own process group so the whole tree can be killed.
+fn run_cmd_with_timeout(
+    program: &str,
+    args: &[String],
+    cwd: Option<&Path>,
+    stdin: Option<&[u8]>,
+    env: &[(String, String)],
+    timeout: Option<std::time::Duration>,
+) -> Result<CmdOut, KernelError> {
     let mut cmd = Command::new(program);
-    cmd.args(args);
+    cmd.args(args)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
     if let Some(dir) = cwd {
         cmd.current_dir(dir);
     }
-    let output = cmd.output().map_err(|e| format!("{program} failed: {e}"))?;
-    let status = output.status.code().unwrap_or(1);
+    if timeout.is_some() {
+        isolate_process_group(&mut cmd);
+    }
+    let mut child = cmd.spawn().map_err(io_err(format!("{program} failed")))?;
+
+    // Feed stdin and drain both pipes on separate threads so a chatty child can't deadlock.
+    let writer = stdin.map(|input| {
+        let mut child_stdin = child.stdin.take();
+        let input = input.to_vec();
+        std::thread::spawn(move || {
+            if let Some(w) = child_stdin.as_mut() {
+                let _ = w.write_all(&input);
+            }
+        })
+    });
+    let read_all = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut p) = pipe {
+                let _ = p.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = read_all(
+        child
+            .stdout
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+    let stderr = read_all(
+        child
+            .stderr
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+
+    let status = wait_with_timeout(&mut child, timeout, None)
+        .map_err(io_err(format!("{program} failed")))?;
+    if let Some(w) = writer {
+        let _ = w.join();
+    }
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    let Waited::Exited(status) = status else {
+        return Err(KernelError::Timeout {
+            command: std::iter::once(program)
+                .chain(args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" "),
+            timeout_secs: timeout.map(|t| t.as_secs()).unwrap_or_default(),
+        });
+    };
     Ok(CmdOut {
-        ok: output.status.success(),
-        status,
-        stdout: String::from_utf8_lossy(&output.stdout).trim_end().to_string(),
-        stderr: String::from_utf8_lossy(&output.stderr).trim_end().to_string(),
+        ok: status.success(),
+        status: status.code().unwrap_or(1),
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+    })
+}
+
+static COMMAND_TIMEOUT: std::sync::OnceLock<std::time::Duration> = std::sync::OnceLock::new();
+
+/// Default timeout for spawned commands, from the global `--timeout-secs` flag.
+fn command_timeout() -> Option<std::time::Duration> {
+    COMMAND_TIMEOUT.get().copied()
+}
+
+#[cfg(unix)]
+fn isolate_process_group(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn isolate_process_group(_cmd: &mut Command) {}
+
+/// Kills `child` and everything it spawned (its process group on unix, `/T` on Windows).
+fn kill_process_tree(child: &mut std::process::Child) {
+    let pid = child.id().to_string();
+    let _ = if cfg!(windows) {
+        Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid])
+            .output()
+    } else {
+        Command::new("kill")
+            .args(["-KILL", "--", &format!("-{pid}")])
+            .output()
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+enum Waited {
+    Exited(std::process::ExitStatus),
+    TimedOut,
+    Cancelled,
+}
+
+/// Waits for `child`, killing its process tree when `timeout` elapses or `cancel` is set.
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: Option<std::time::Duration>,
+    cancel: Option<&std::sync::atomic::AtomicBool>,
+) -> io::Result<Waited> {
+    if timeout.is_none() && cancel.is_none() {
+        return child.wait().map(Waited::Exited);
+    }
+    let deadline = timeout.map(|t| std::time::Instant::now() + t);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Waited::Exited(status));
+        }
+        if deadline.is_some_and(|d| std::time::Instant::now() >= d) {
+            kill_process_tree(child);
+            return Ok(Waited::TimedOut);
+        }
+        if cancel.is_some_and(|c| c.load(std::sync::atomic::Ordering::SeqCst)) {
+            kill_process_tree(child);
+            return Ok(Waited::Cancelled);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+}
+
+static GIT_BINARY: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+fn git_binary_from(env: Option<std::ffi::OsString>) -> String {
+    env.filter(|v| !v.is_empty())
+        .map(|v| v.to_string_lossy().to_string())
+        .unwrap_or_else(|| "git".to_string())
+}
+
+/// The git executable to run: `ECC_GIT_PATH` or the config file's `gitBinary` (read once),
+/// else `git` from `PATH`.
+fn git_binary() -> &'static str {
+    GIT_BINARY.get_or_init(|| {
+        git_binary_from(env_or_config(
+            std::env::var_os("ECC_GIT_PATH"),
+            &kernel_config().gitBinary,
+        ))
+    })
+}
+
+/// Per-installation defaults from `--config-file`. Environment variables and CLI flags
+/// take precedence over these values.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct KernelConfig {
+    #[serde(default)]
+    gitBinary: Option<String>,
+    #[serde(default)]
+    auditLogPath: Option<String>,
+    #[serde(default)]
+    defaultTimeoutSecs: Option<u64>,
+    /// Default `maxOutputBytes` for `verify.run` commands that do not set one.
+    #[serde(default)]
+    maxOutputBytes: Option<u64>,
+    #[serde(default)]
+    allowedRoots: Vec<String>,
+}
+
+static KERNEL_CONFIG: std::sync::OnceLock<KernelConfig> = std::sync::OnceLock::new();
+
+fn kernel_config() -> &'static KernelConfig {
+    KERNEL_CONFIG.get_or_init(KernelConfig::default)
+}
+
+fn load_kernel_config(path: &Path) -> Result<KernelConfig, KernelError> {
+    let text = fs::read_to_string(path).map_err(io_err(format!(
+        "failed to read config file {}",
+        path.display()
+    )))?;
+    serde_json::from_str(&text).map_err(|e| {
+        KernelError::InvalidInput(format!("invalid config file {}: {e}", path.display()))
     })
 }
 
-fn run_git(args: &[String], cwd: Option<&Path>) -> Result<CmdOut, String> {
-    run_cmd("git", args, cwd)
+/// The environment value when set and non-empty, else the config file value.
+fn env_or_config(
+    env: Option<std::ffi::OsString>,
+    config: &Option<String>,
+) -> Option<std::ffi::OsString> {
+    env.filter(|v| !v.is_empty())
+        .or_else(|| config.as_ref().map(Into::into))
+}
+
+fn run_git(args: &[String], cwd: Option<&Path>) -> Result<CmdOut, KernelError> {
+    run_cmd(git_binary(), args, cwd)
+}
+
+/// Like `run_git` with extra environment variables (e.g. `GIT_AUTHOR_*`); output is untrimmed.
+fn run_git_with_env(args: &[String], env: &[(String, String)]) -> Result<CmdOut, KernelError> {
+    run_cmd_with_timeout(git_binary(), args, None, None, env, command_timeout())
+}
+
+/// Builds `git -C <dir> <args...>` argument vectors.
+fn git_args(dir: &Path, args: &[&str]) -> Vec<String> {
+    let mut v = vec!["-C".to_string(), dir.display().to_string()];
+    v.extend(args.iter().map(|a| a.to_string()));
+    v
+}
+
+/// Rejects a user-supplied positional git argument that git would parse as an option
+/// (e.g. `--upload-pack=<cmd>` as a remote).
+fn reject_option_like(what: &str, value: &str) -> Result<(), KernelError> {
+    if value.starts_with('-') {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid {what}: {value:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// Runs git and turns a non-zero exit into an error (stderr, or `<what> failed`).
+fn run_git_checked(args: &[String], what: &str) -> Result<CmdOut, KernelError> {
+    let out = run_git(args, None)?;
+    if !out.ok {
+        return Err(git_failed(what, &out));
+    }
+    Ok(out)
+}
+
+fn git_failed(command: &str, out: &CmdOut) -> KernelError {
+    KernelError::GitFailed {
+        command: command.to_string(),
+        exit_code: out.status,
+        stderr: out.stderr.trim_end().to_string(),
+    }
 }
 
 fn normalize_worktree_path(path: &Path) -> PathBuf {
@@ -100,24 +656,62 @@ fn normalize_worktree_path(path: &Path) -> PathBuf {
     pb
 }
 
-fn abs_path(path: &Path) -> Result<PathBuf, String> {
+fn abs_path(path: &Path) -> Result<PathBuf, KernelError> {
     if path.is_absolute() {
         Ok(normalize_worktree_path(path))
     } else {
-        let cwd = std::env::current_dir().map_err(|e| format!("failed to get cwd: {e}"))?;
+        let cwd = std::env::current_dir().map_err(io_err("failed to get cwd"))?;
         Ok(normalize_worktree_path(&cwd.join(path)))
     }
 }
 
-fn assert_external_worktree_path(repo_root: &Path, worktree_path: &Path) -> Result<(), String> {
-    let repo = abs_path(repo_root)?;
-    let wt = abs_path(worktree_path)?;
+/// Like `normalize_worktree_path`, but with `resolve_symlinks` the deepest existing ancestor is
+/// canonicalized so `/tmp/wt -> /real/wt` and `/real/wt` compare equal. The part of the path
+/// that does not exist yet is kept lexically normalized.
+fn canonical_worktree_path(path: &Path, resolve_symlinks: bool) -> Result<PathBuf, String> {
+    let lexical = normalize_worktree_path(path);
+    if !resolve_symlinks {
+        return Ok(lexical);
+    }
+    let mut existing = lexical.as_path();
+    let mut tail = Vec::new();
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(mut real) => {
+                real.extend(tail.iter().rev());
+                return Ok(real);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        tail.push(name);
+                        existing = parent;
+                    }
+                    _ => return Ok(lexical),
+                }
+            }
+            Err(e) => return Err(format!("failed to resolve {}: {e}", existing.display())),
+        }
+    }
+}
+
+fn assert_external_worktree_path(
+    repo_root: &Path,
+    worktree_path: &Path,
+    resolve_symlinks: bool,
+) -> Result<(), KernelError> {
+    let resolve = |p: &Path| {
+        canonical_worktree_path(&abs_path(p)?, resolve_symlinks)
+            .map_err(|e| KernelError::Io(io::Error::other(e)))
+    };
+    let repo = resolve(repo_root)?;
+    let wt = resolve(worktree_path)?;
     if wt.starts_with(&repo) {
-        return Err(format!(
+        return Err(KernelError::PathTraversal(format!(
             "Refusing to create worktree inside repo root (would recurse): repoRoot={} worktreePath={}",
             repo.display(),
             wt.display()
-        ));
+        )));
     }
     Ok(())
 }
@@ -153,7 +747,7 @@ fn branch_exists(repo_root: &Path, branch: &str) -> bool {
     }
 }
 
-fn ensure_branch_at(repo_root: &Path, branch: &str, base_sha: &str) -> Result<(), String> {
+fn ensure_branch_at(repo_root: &Path, branch: &str, base_sha: &str) -> Result<(), KernelError> {
     if branch_exists(repo_root, branch) {
         return Ok(());
     }
@@ -166,69 +760,165 @@ fn ensure_branch_at(repo_root: &Path, branch: &str, base_sha: &str) -> Result<()
     ];
     let out = run_git(&args, None)?;
     if !out.ok {
-        return Err(out.stderr.is_empty().then(|| format!("git branch failed")).unwrap_or(out.stderr));
+        return Err(git_failed("git branch", &out));
     }
     Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema, Default)]
 struct WorktreeEnsureIn {
     repoRoot: String,
     worktreePath: String,
     branch: String,
     baseSha: String,
+    /// When `branch` is missing locally but `<remoteName>/<branch>` exists, branch from it.
+    #[serde(default)]
+    remoteName: Option<String>,
+    /// Set the new branch's upstream to the remote branch (`--track`).
+    #[serde(default)]
+    trackRemote: bool,
+    /// Check out `baseSha` with a detached HEAD; `branch` must be empty.
+    #[serde(default)]
+    detach: bool,
+    /// Cone-mode sparse-checkout directories; re-applied to an existing worktree if changed.
+    #[serde(default)]
+    sparsePatterns: Option<Vec<String>>,
+    /// Resolve symlinks before checking that the worktree lies outside the repo.
+    #[serde(default)]
+    resolveSymlinks: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct WorktreeEnsureOut {
     worktreePath: String,
+    /// HEAD SHA of the worktree after the call.
+    head: String,
+    /// True when this call ran `git sparse-checkout set`.
+    sparsePatternsApplied: bool,
 }
 
-fn worktree_ensure(input: WorktreeEnsureIn) -> Result<WorktreeEnsureOut, String> {
+fn worktree_ensure(input: WorktreeEnsureIn) -> Result<WorktreeEnsureOut, KernelError> {
     let repo_root = PathBuf::from(input.repoRoot);
     let worktree_path = PathBuf::from(input.worktreePath);
-    assert_external_worktree_path(&repo_root, &worktree_path)?;
-    ensure_branch_at(&repo_root, &input.branch, &input.baseSha)?;
+    assert_external_worktree_path(&repo_root, &worktree_path, input.resolveSymlinks)?;
+    if input.detach && !input.branch.is_empty() {
+        return Err(KernelError::InvalidInput(
+            "detach and branch are mutually exclusive".to_string(),
+        ));
+    }
+    reject_option_like("branch", &input.branch)?;
+    reject_option_like("baseSha", &input.baseSha)?;
+    if !input.detach {
+        let remote_ref = input
+            .remoteName
+            .as_ref()
+            .map(|remote| format!("refs/remotes/{remote}/{}", input.branch))
+            .filter(|r| {
+                !branch_exists(&repo_root, &input.branch)
+                    && run_git(
+                        &git_args(&repo_root, &["show-ref", "--verify", "--quiet", r]),
+                        None,
+                    )
+                    .is_ok_and(|out| out.ok)
+            });
+        match remote_ref {
+            Some(remote_ref) => {
+                let track = if input.trackRemote {
+                    "--track"
+                } else {
+                    "--no-track"
+                };
+                run_git_checked(
+                    &git_args(&repo_root, &["branch", track, &input.branch, &remote_ref]),
+                    "git branch",
+                )?;
+            }
+            None => ensure_branch_at(&repo_root, &input.branch, &input.baseSha)?,
+        }
+    }
 
     if worktree_path.exists() {
         if !is_git_worktree(&worktree_path) {
-            return Err(format!(
+            return Err(KernelError::AlreadyExists(format!(
                 "Worktree path exists but is not a git worktree: {}",
                 worktree_path.display()
+            )));
+        }
+    } else {
+        if let Some(parent) = worktree_path.parent() {
+            fs::create_dir_all(parent).map_err(io_err("failed to create worktree parent dir"))?;
+        }
+
+        let mut args = vec![
+            "-C".to_string(),
+            repo_root.display().to_string(),
+            "worktree".to_string(),
+            "add".to_string(),
+        ];
+        if input.detach {
+            args.push("--detach".to_string());
+            args.push(worktree_path.display().to_string());
+            args.push(input.baseSha);
+        } else {
+            args.push(worktree_path.display().to_string());
+            args.push(input.branch);
+        }
+        let out = run_git(&args, None)?;
+        if !out.ok {
+            return Err(git_failed(
+                &format!("git worktree add {}", worktree_path.display()),
+                &out,
             ));
         }
-        return Ok(WorktreeEnsureOut {
-            worktreePath: worktree_path.display().to_string(),
-        });
     }
 
-    if let Some(parent) = worktree_path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("failed to create worktree parent dir: {e}"))?;
-    }
+    let sparse_patterns = input.sparsePatterns.unwrap_or_default();
+    let sparse_applied =
+        !sparse_patterns.is_empty() && apply_sparse_patterns(&worktree_path, &sparse_patterns)?;
 
-    let args = vec![
-        "-C".to_string(),
-        repo_root.display().to_string(),
-        "worktree".to_string(),
-        "add".to_string(),
-        worktree_path.display().to_string(),
-        input.branch,
-    ];
-    let out = run_git(&args, None)?;
-    if !out.ok {
-        return Err(if out.stderr.is_empty() {
-            format!("git worktree add failed: {}", worktree_path.display())
-        } else {
-            out.stderr
-        });
-    }
+    let head = run_git_checked(
+        &git_args(&worktree_path, &["rev-parse", "HEAD"]),
+        "git rev-parse HEAD",
+    )?;
     Ok(WorktreeEnsureOut {
         worktreePath: worktree_path.display().to_string(),
+        head: head.stdout,
+        sparsePatternsApplied: sparse_applied,
     })
 }
 
-#[derive(Deserialize)]
+/// Sets cone-mode sparse-checkout patterns unless they already match; returns whether
+/// `sparse-checkout set` ran.
+fn apply_sparse_patterns(worktree: &Path, patterns: &[String]) -> Result<bool, KernelError> {
+    let normalize = |p: &str| p.trim_matches('/').to_string();
+    let wanted: BTreeSet<String> = patterns.iter().map(|p| normalize(p)).collect();
+    let cone = run_git(
+        &git_args(worktree, &["config", "--bool", "core.sparseCheckoutCone"]),
+        None,
+    )?;
+    if cone.stdout == "true" {
+        let current = run_git_checked(
+            &git_args(worktree, &["sparse-checkout", "list"]),
+            "git sparse-checkout list",
+        )?;
+        let current: BTreeSet<String> = current.stdout.lines().map(normalize).collect();
+        if current == wanted {
+            return Ok(false);
+        }
+    } else {
+        run_git_checked(
+            &git_args(worktree, &["sparse-checkout", "init", "--cone"]),
+            "git sparse-checkout init",
+        )?;
+    }
+
+    let mut args = git_args(worktree, &["sparse-checkout", "set", "--"]);
+    args.extend(patterns.iter().cloned());
+    run_git_checked(&args, "git sparse-checkout set")?;
+    Ok(true)
+}
+
+#[derive(Deserialize, JsonSchema)]
 struct WorktreeRemoveIn {
     repoRoot: String,
     worktreePath: String,
@@ -236,12 +926,12 @@ struct WorktreeRemoveIn {
     force: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct WorktreeRemoveOut {
     ok: bool,
 }
 
-fn worktree_remove(input: WorktreeRemoveIn) -> Result<WorktreeRemoveOut, String> {
+fn worktree_remove(input: WorktreeRemoveIn) -> Result<WorktreeRemoveOut, KernelError> {
     let repo_root = PathBuf::from(input.repoRoot);
     let worktree_path = PathBuf::from(input.worktreePath);
     let mut args = vec![
@@ -256,63 +946,623 @@ fn worktree_remove(input: WorktreeRemoveIn) -> Result<WorktreeRemoveOut, String>
     args.push(worktree_path.display().to_string());
     let out = run_git(&args, None)?;
     if !out.ok {
-        return Err(if out.stderr.is_empty() {
-            format!("git worktree remove failed: {}", worktree_path.display())
-        } else {
-            out.stderr
-        });
+        return Err(git_failed(
+            &format!("git worktree remove {}", worktree_path.display()),
+            &out,
+        ));
     }
     Ok(WorktreeRemoveOut { ok: true })
 }
 
-fn normalize_repo_path(p: &str) -> Option<String> {
-    let posix = p.replace('\\', "/");
-    if posix.starts_with('/') {
-        return None;
+#[derive(Deserialize, JsonSchema)]
+struct WorktreePruneIn {
+    repoRoot: String,
+    #[serde(default)]
+    dryRun: bool,
+    #[serde(default)]
+    expire: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WorktreePruneOut {
+    prunedPaths: Vec<String>,
+    ok: bool,
+}
+
+fn worktree_prune(input: WorktreePruneIn) -> Result<WorktreePruneOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut args = git_args(&repo_root, &["worktree", "prune", "--verbose"]);
+    if input.dryRun {
+        args.push("--dry-run".to_string());
     }
-    let bytes = posix.as_bytes();
-    if bytes.len() >= 3 {
-        let c0 = bytes[0];
-        let c1 = bytes[1];
-        let c2 = bytes[2];
-        if (c0 as char).is_ascii_alphabetic() && c1 == b':' && c2 == b'/' {
-            return None;
-        }
+    if let Some(expire) = input.expire {
+        args.push("--expire".to_string());
+        args.push(expire);
     }
+    let out = run_git_checked(&args, "git worktree prune")?;
 
-    let mut stack: Vec<&str> = Vec::new();
-    for part in posix.split('/') {
-        if part.is_empty() || part == "." {
-            continue;
-        }
-        if part == ".." {
-            if stack.is_empty() {
-                return None;
-            }
-            stack.pop();
-            continue;
-        }
-        stack.push(part);
+    // git reports pruned entries on stderr as `Removing worktrees/<name>: <reason>`.
+    let pruned_paths = out
+        .stdout
+        .lines()
+        .chain(out.stderr.lines())
+        .filter_map(|l| l.strip_prefix("Removing "))
+        .filter(|rest| rest.starts_with("worktrees/"))
+        .map(|rest| {
+            rest.split_once(':')
+                .map(|(p, _)| p)
+                .unwrap_or(rest)
+                .to_string()
+        })
+        .collect();
+    Ok(WorktreePruneOut {
+        prunedPaths: pruned_paths,
+        ok: true,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WorktreeMoveIn {
+    repoRoot: String,
+    worktreePath: String,
+    newPath: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WorktreeMoveOut {
+    oldPath: String,
+    newPath: String,
+}
+
+fn worktree_move(input: WorktreeMoveIn) -> Result<WorktreeMoveOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let old_path = PathBuf::from(input.worktreePath);
+    let new_path = PathBuf::from(input.newPath);
+    assert_external_worktree_path(&repo_root, &old_path, false)?;
+    assert_external_worktree_path(&repo_root, &new_path, false)?;
+    let old_path = abs_path(&old_path)?;
+    let new_path = abs_path(&new_path)?;
+
+    if new_path.exists() {
+        return Err(KernelError::AlreadyExists(format!(
+            "Refusing to move worktree: destination already exists: {}",
+            new_path.display()
+        )));
     }
-    if stack.is_empty() {
-        return None;
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent).map_err(io_err("failed to create worktree parent dir"))?;
     }
-    Some(stack.join("/"))
+
+    let mut args = git_args(&repo_root, &["worktree", "move"]);
+    args.push(old_path.display().to_string());
+    args.push(new_path.display().to_string());
+    run_git_checked(&args, "git worktree move")?;
+
+    Ok(WorktreeMoveOut {
+        oldPath: old_path.display().to_string(),
+        newPath: new_path.display().to_string(),
+    })
 }
 
-fn touched_files_from_unified_diff(patch_path: &Path) -> Result<Vec<(String, bool)>, String> {
-    let f = File::open(patch_path)
-        .map_err(|e| format!("failed to open patch for parsing: {}: {e}", patch_path.display()))?;
-    let reader = BufReader::new(f);
+#[derive(Deserialize, JsonSchema)]
+struct WorktreeListIn {
+    repoRoot: String,
+}
 
-    let mut files: Vec<(String, bool)> = Vec::new();
-    let mut seen: BTreeSet<String> = BTreeSet::new();
+#[derive(Serialize, Default, JsonSchema)]
+struct WorktreeInfo {
+    path: String,
+    branch: Option<String>,
+    head: String,
+    isMain: bool,
+    isLocked: bool,
+    isBare: bool,
+    /// git no longer finds the worktree where it was registered (e.g. it was moved).
+    isPrunable: bool,
+    lockReason: Option<String>,
+    isDetached: bool,
+}
 
-    for line in reader.lines() {
-        let line = line.map_err(|e| format!("failed reading patch: {e}"))?;
-        if !line.starts_with("diff --git ") {
+#[derive(Serialize, JsonSchema)]
+struct WorktreeListOut {
+    worktrees: Vec<WorktreeInfo>,
+}
+
+fn parse_worktree_porcelain(text: &str) -> Vec<WorktreeInfo> {
+    // Stanzas are separated by blank lines; the first one is always the main worktree.
+    let mut worktrees: Vec<WorktreeInfo> = Vec::new();
+    let mut cur: Option<WorktreeInfo> = None;
+    for line in text.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "worktree" => {
+                if let Some(wt) = cur.take() {
+                    worktrees.push(wt);
+                }
+                cur = Some(WorktreeInfo {
+                    path: value.to_string(),
+                    isMain: worktrees.is_empty(),
+                    ..Default::default()
+                });
+            }
+            "HEAD" => {
+                if let Some(wt) = cur.as_mut() {
+                    wt.head = value.to_string();
+                }
+            }
+            "branch" => {
+                if let Some(wt) = cur.as_mut() {
+                    wt.branch = Some(
+                        value
+                            .strip_prefix("refs/heads/")
+                            .unwrap_or(value)
+                            .to_string(),
+                    );
+                }
+            }
+            "bare" => {
+                if let Some(wt) = cur.as_mut() {
+                    wt.isBare = true;
+                }
+            }
+            "locked" => {
+                if let Some(wt) = cur.as_mut() {
+                    wt.isLocked = true;
+                    wt.lockReason = Some(value.to_string()).filter(|r| !r.is_empty());
+                }
+            }
+            "detached" => {
+                if let Some(wt) = cur.as_mut() {
+                    wt.isDetached = true;
+                }
+            }
+            "prunable" => {
+                if let Some(wt) = cur.as_mut() {
+                    wt.isPrunable = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(wt) = cur.take() {
+        worktrees.push(wt);
+    }
+    worktrees
+}
+
+fn worktree_list(input: WorktreeListIn) -> Result<WorktreeListOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let out = run_git_checked(
+        &git_args(&repo_root, &["worktree", "list", "--porcelain"]),
+        "git worktree list",
+    )?;
+    Ok(WorktreeListOut {
+        worktrees: parse_worktree_porcelain(&out.stdout),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WorktreeInspectIn {
+    repoRoot: String,
+    worktreePath: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WorktreeInspectOut {
+    path: String,
+    branch: Option<String>,
+    head: String,
+    isLocked: bool,
+    lockReason: Option<String>,
+    isBare: bool,
+    isDetached: bool,
+    /// Registered with git but the directory is gone.
+    isMissing: bool,
+}
+
+fn worktree_inspect(input: WorktreeInspectIn) -> Result<WorktreeInspectOut, KernelError> {
+    let wt = find_worktree(Path::new(&input.repoRoot), Path::new(&input.worktreePath))?
+        .ok_or_else(|| {
+            KernelError::NotFound(format!("worktree not found: {}", input.worktreePath))
+        })?;
+    Ok(WorktreeInspectOut {
+        isMissing: !Path::new(&wt.path).exists(),
+        path: wt.path,
+        branch: wt.branch,
+        head: wt.head,
+        isLocked: wt.isLocked,
+        lockReason: wt.lockReason,
+        isBare: wt.isBare,
+        isDetached: wt.isDetached,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitWorktreeHeadIn {
+    repoRoot: String,
+    worktreePath: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitWorktreeHeadOut {
+    sha: String,
+    /// `None` on a detached HEAD.
+    branch: Option<String>,
+    isDetached: bool,
+}
+
+/// HEAD of one worktree, read directly from it rather than via `worktree.list`.
+fn git_worktree_head(input: GitWorktreeHeadIn) -> Result<GitWorktreeHeadOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let worktree_path = PathBuf::from(input.worktreePath);
+    if !worktree_path.is_dir() {
+        return Err(KernelError::NotFound(format!(
+            "worktree not found: {}",
+            worktree_path.display()
+        )));
+    }
+    let common_dir = |dir: &Path| {
+        run_git_checked(
+            &git_args(
+                dir,
+                &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+            ),
+            "git rev-parse --git-common-dir",
+        )
+        .map(|out| PathBuf::from(out.stdout))
+    };
+    if !same_path(&common_dir(&worktree_path)?, &common_dir(&repo_root)?) {
+        return Err(KernelError::NotFound(format!(
+            "not a worktree of {}: {}",
+            repo_root.display(),
+            worktree_path.display()
+        )));
+    }
+
+    let sha = run_git_checked(
+        &git_args(&worktree_path, &["rev-parse", "HEAD"]),
+        "git rev-parse HEAD",
+    )?
+    .stdout;
+    let branch = run_git_checked(
+        &git_args(&worktree_path, &["branch", "--show-current"]),
+        "git branch --show-current",
+    )?
+    .stdout;
+    Ok(GitWorktreeHeadOut {
+        sha,
+        isDetached: branch.is_empty(),
+        branch: Some(branch).filter(|b| !b.is_empty()),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WorktreeRepairIn {
+    repoRoot: String,
+    /// Current locations of moved worktrees. When empty, stale registrations are repaired
+    /// in place (useful after the main repository itself moved).
+    #[serde(default)]
+    worktreePaths: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WorktreeRepairOut {
+    repairedPaths: Vec<String>,
+    /// Paths git could not repair, plus registrations that are still stale afterwards.
+    failedPaths: Vec<String>,
+}
+
+fn worktree_repair(input: WorktreeRepairIn) -> Result<WorktreeRepairOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    for path in &input.worktreePaths {
+        reject_option_like("worktree path", path)?;
+    }
+    let stale = || -> Result<Vec<String>, KernelError> {
+        let out = worktree_list(WorktreeListIn {
+            repoRoot: repo_root.display().to_string(),
+        })?;
+        Ok(out
+            .worktrees
+            .into_iter()
+            .filter(|wt| wt.isPrunable)
+            .map(|wt| wt.path)
+            .collect())
+    };
+    let mut repaired_paths = Vec::new();
+    let mut failed_paths = Vec::new();
+    let stale_before = stale()?;
+    if input.worktreePaths.is_empty() {
+        if stale_before.is_empty() {
+            return Ok(WorktreeRepairOut {
+                repairedPaths: repaired_paths,
+                failedPaths: failed_paths,
+            });
+        }
+        // Repair what git can fix on its own, then see which entries are still stale.
+        run_git(&git_args(&repo_root, &["worktree", "repair"]), None)?;
+        let stale_after = stale()?;
+        for path in stale_before {
+            if stale_after.contains(&path) {
+                failed_paths.push(path);
+            } else {
+                repaired_paths.push(path);
+            }
+        }
+    } else {
+        // git exits non-zero when it reports an error for any of the given paths.
+        for path in input.worktreePaths {
+            let out = run_git(&git_args(&repo_root, &["worktree", "repair", &path]), None)?;
+            if out.ok {
+                repaired_paths.push(path);
+            } else {
+                failed_paths.push(path);
+            }
+        }
+        // Registrations that were stale and still are were not fixed by any given path.
+        let stale_after = stale()?;
+        for path in stale_before {
+            if stale_after.contains(&path) && !failed_paths.contains(&path) {
+                failed_paths.push(path);
+            }
+        }
+    }
+
+    Ok(WorktreeRepairOut {
+        repairedPaths: repaired_paths,
+        failedPaths: failed_paths,
+    })
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    if let (Ok(ca), Ok(cb)) = (fs::canonicalize(a), fs::canonicalize(b)) {
+        return ca == cb;
+    }
+    match (abs_path(a), abs_path(b)) {
+        (Ok(na), Ok(nb)) => na == nb,
+        _ => false,
+    }
+}
+
+fn find_worktree(
+    repo_root: &Path,
+    worktree_path: &Path,
+) -> Result<Option<WorktreeInfo>, KernelError> {
+    let out = worktree_list(WorktreeListIn {
+        repoRoot: repo_root.display().to_string(),
+    })?;
+    Ok(out
+        .worktrees
+        .into_iter()
+        .find(|wt| same_path(Path::new(&wt.path), worktree_path)))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WorktreeLockIn {
+    repoRoot: String,
+    worktreePath: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WorktreeLockOut {
+    ok: bool,
+    alreadyLocked: bool,
+}
+
+fn worktree_lock(input: WorktreeLockIn) -> Result<WorktreeLockOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let worktree_path = PathBuf::from(input.worktreePath);
+    let wt = find_worktree(&repo_root, &worktree_path)?.ok_or_else(|| {
+        KernelError::NotFound(format!(
+            "not a registered worktree: {}",
+            worktree_path.display()
+        ))
+    })?;
+    if wt.isLocked {
+        return Ok(WorktreeLockOut {
+            ok: true,
+            alreadyLocked: true,
+        });
+    }
+
+    let mut args = git_args(&repo_root, &["worktree", "lock"]);
+    if let Some(reason) = input.reason {
+        args.push("--reason".to_string());
+        args.push(reason);
+    }
+    args.push(worktree_path.display().to_string());
+    run_git_checked(&args, "git worktree lock")?;
+    Ok(WorktreeLockOut {
+        ok: true,
+        alreadyLocked: false,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WorktreeUnlockIn {
+    repoRoot: String,
+    worktreePath: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WorktreeUnlockOut {
+    ok: bool,
+    wasLocked: bool,
+}
+
+fn worktree_unlock(input: WorktreeUnlockIn) -> Result<WorktreeUnlockOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let worktree_path = PathBuf::from(input.worktreePath);
+    let wt = find_worktree(&repo_root, &worktree_path)?.ok_or_else(|| {
+        KernelError::NotFound(format!(
+            "not a registered worktree: {}",
+            worktree_path.display()
+        ))
+    })?;
+    if !wt.isLocked {
+        return Ok(WorktreeUnlockOut {
+            ok: true,
+            wasLocked: false,
+        });
+    }
+
+    let mut args = git_args(&repo_root, &["worktree", "unlock"]);
+    args.push(worktree_path.display().to_string());
+    run_git_checked(&args, "git worktree unlock")?;
+    Ok(WorktreeUnlockOut {
+        ok: true,
+        wasLocked: true,
+    })
+}
+
+fn normalize_repo_path(p: &str) -> Option<String> {
+    let posix = p.replace('\\', "/");
+    if posix.starts_with('/') {
+        return None;
+    }
+    let bytes = posix.as_bytes();
+    if bytes.len() >= 3 {
+        let c0 = bytes[0];
+        let c1 = bytes[1];
+        let c2 = bytes[2];
+        if (c0 as char).is_ascii_alphabetic() && c1 == b':' && c2 == b'/' {
+            return None;
+        }
+    }
+
+    let mut stack: Vec<&str> = Vec::new();
+    for part in posix.split('/') {
+        if part.is_empty() || part == "." {
+            continue;
+        }
+        if part == ".." {
+            if stack.is_empty() {
+                return None;
+            }
+            stack.pop();
+            continue;
+        }
+        stack.push(part);
+    }
+    if stack.is_empty() {
+        return None;
+    }
+    Some(stack.join("/"))
+}
+
+/// Drops `n` leading `/`-separated components, like `git apply -p<n>`.
+fn strip_path_components(path: &str, n: u32) -> Option<&str> {
+    let mut rest = path;
+    for _ in 0..n {
+        rest = rest.split_once('/')?.1;
+    }
+    Some(rest)
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum FileChangeStatus {
+    Added,
+    #[default]
+    Modified,
+    Deleted,
+    Renamed {
+        from: String,
+    },
+    Copied {
+        from: String,
+    },
+    ModeChange,
+}
+
+#[derive(Serialize, Clone, Debug, JsonSchema, Default)]
+struct TouchedFile {
+    path: String,
+    invalid: bool,
+    status: FileChangeStatus,
+    /// Source path of a rename; the rename touches it too, so it is ownership-checked as well.
+    origPath: Option<String>,
+    /// Set for `Binary files ... differ` and `GIT binary patch` entries.
+    isBinary: bool,
+}
+
+fn touched_paths(touched: Vec<TouchedFile>) -> Vec<String> {
+    touched
+        .into_iter()
+        .filter(|t| !t.invalid)
+        .map(|t| t.path)
+        .collect()
+}
+
+fn touched_files_from_unified_diff(
+    patch_path: &Path,
+    strip: u32,
+) -> Result<Vec<TouchedFile>, KernelError> {
+    let f = File::open(patch_path).map_err(io_err(format!(
+        "failed to open patch for parsing: {}",
+        patch_path.display()
+    )))?;
+    let reader = BufReader::new(f);
+
+    let mut files: Vec<TouchedFile> = Vec::new();
+    let mut seen: BTreeSet<String> = BTreeSet::new();
+    // Index into `files` of the entry whose extended header lines we are reading, if any.
+    let mut current: Option<usize> = None;
+    let mut mode_changed = false;
+    let mut has_content = false;
+
+    let finish = |files: &mut Vec<TouchedFile>,
+                  current: Option<usize>,
+                  mode_changed: bool,
+                  has_content: bool| {
+        if let Some(i) = current {
+            if mode_changed && !has_content && files[i].status == FileChangeStatus::Modified {
+                files[i].status = FileChangeStatus::ModeChange;
+            }
+        }
+    };
+
+    for line in reader.lines() {
+        let line = line.map_err(io_err("failed reading patch"))?;
+        if !line.starts_with("diff --git ") {
+            let Some(i) = current else {
+                continue;
+            };
+            if line.starts_with("@@ ") {
+                has_content = true;
+                continue;
+            }
+            if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                has_content = true;
+                files[i].isBinary = true;
+                continue;
+            }
+            if line.starts_with("new file mode ") {
+                files[i].status = FileChangeStatus::Added;
+            } else if line.starts_with("deleted file mode ") {
+                files[i].status = FileChangeStatus::Deleted;
+            } else if let Some(from) = line.strip_prefix("rename from ") {
+                files[i].status = FileChangeStatus::Renamed {
+                    from: from.to_string(),
+                };
+                // Kept raw when it does not normalize; the ownership check reports it as invalid.
+                files[i].origPath =
+                    Some(normalize_repo_path(from).unwrap_or_else(|| from.to_string()));
+            } else if let Some(from) = line.strip_prefix("copy from ") {
+                files[i].status = FileChangeStatus::Copied {
+                    from: from.to_string(),
+                };
+            } else if line.starts_with("old mode ") || line.starts_with("new mode ") {
+                mode_changed = true;
+            }
             continue;
         }
+        finish(&mut files, current, mode_changed, has_content);
+        current = None;
+        mode_changed = false;
+        has_content = false;
+
         // Typical: diff --git a/foo/bar b/foo/bar
         let rest = line.trim_start_matches("diff --git ").trim();
         let mut it = rest.split_whitespace();
@@ -321,202 +1571,650 @@ fn touched_files_from_unified_diff(patch_path: &Path) -> Result<Vec<(String, boo
         if a.is_none() || b.is_none() {
             continue;
         }
-        let a_path = a.unwrap().strip_prefix("a/").unwrap_or(a.unwrap());
-        let b_path = b.unwrap().strip_prefix("b/").unwrap_or(b.unwrap());
-
-        let file = if b_path == "/dev/null" { a_path } else { b_path };
-        match normalize_repo_path(file) {
-            Some(n) => {
-                if seen.contains(&n) {
-                    continue;
+        let (a, b) = (a.unwrap(), b.unwrap());
+        let file = if b == "/dev/null" { a } else { b };
+        let (path, invalid) = match strip_path_components(file, strip) {
+            None => (file.to_string(), true),
+            Some(stripped) => match normalize_repo_path(stripped) {
+                Some(n) => {
+                    if seen.contains(&n) {
+                        continue;
+                    }
+                    seen.insert(n.clone());
+                    (n, false)
                 }
-                seen.insert(n.clone());
-                files.push((n, false));
-            }
-            None => {
-                files.push((file.to_string(), true));
-            }
-        }
+                None => (stripped.to_string(), true),
+            },
+        };
+        files.push(TouchedFile {
+            path,
+            invalid,
+            status: FileChangeStatus::Modified,
+            origPath: None,
+            isBinary: false,
+        });
+        current = Some(files.len() - 1);
     }
+    finish(&mut files, current, mode_changed, has_content);
     Ok(files)
 }
 
-fn ensure_owned(touched_files: &[(String, bool)], allowed_prefixes: &[String]) -> Result<(), String> {
-    let mut allowed: Vec<String> = allowed_prefixes
+/// Whether `path` lies under `prefix`. A prefix containing `*` is a glob whose
+/// segments must match the leading segments of `path` (`*` never crosses `/`).
+fn path_matches_prefix(path: &str, prefix: &str) -> bool {
+    let base = prefix.trim_end_matches('/');
+    if !base.contains('*') {
+        return path == base || path.starts_with(prefix);
+    }
+    let pattern: Vec<&str> = base.split('/').collect();
+    let segments: Vec<&str> = path.split('/').collect();
+    segments.len() >= pattern.len()
+        && pattern
+            .iter()
+            .zip(segments.iter())
+            .all(|(p, seg)| glob_match(p, seg))
+}
+
+fn ownership_violations(
+    touched_files: &[TouchedFile],
+    allowed_prefixes: &[String],
+) -> Result<Vec<String>, KernelError> {
+    // Entries starting with `!` exclude paths that would otherwise be allowed.
+    let (negated, positive): (Vec<String>, Vec<String>) = allowed_prefixes
         .iter()
         .map(|p| p.replace('\\', "/"))
         .filter(|p| !p.trim().is_empty())
-        .map(|p| if p.ends_with('/') { p } else { format!("{p}/") })
-        .collect();
-    allowed.sort();
-    allowed.dedup();
+        .partition(|p| p.starts_with('!'));
+    let normalize = |entries: Vec<String>| {
+        let mut out: Vec<String> = entries
+            .into_iter()
+            .map(|p| if p.ends_with('/') { p } else { format!("{p}/") })
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    };
+    let allowed = normalize(positive);
+    let excluded = normalize(
+        negated
+            .iter()
+            .map(|p| p[1..].to_string())
+            .filter(|p| !p.trim().is_empty())
+            .collect(),
+    );
 
     if allowed.is_empty() {
-        return Err("allowedPathPrefixes is empty".to_string());
+        return Err(KernelError::InvalidInput(if excluded.is_empty() {
+            "allowedPathPrefixes is empty".to_string()
+        } else {
+            "allowedPathPrefixes has only negation entries".to_string()
+        }));
     }
 
     let mut violations: Vec<String> = Vec::new();
-    for (path, invalid) in touched_files.iter() {
+    for TouchedFile {
+        path,
+        invalid,
+        origPath,
+        ..
+    } in touched_files.iter()
+    {
         if *invalid {
             violations.push(format!("invalid path in patch: {path}"));
             continue;
         }
-        let mut ok = false;
-        for prefix in allowed.iter() {
-            let base = prefix.trim_end_matches('/');
-            if path == base || path.starts_with(prefix) {
-                ok = true;
-                break;
+        for path in std::iter::once(path).chain(origPath) {
+            if normalize_repo_path(path).as_deref() != Some(path.as_str()) {
+                violations.push(format!("invalid path in patch: {path}"));
+                continue;
+            }
+            if excluded
+                .iter()
+                .any(|prefix| path_matches_prefix(path, prefix))
+            {
+                violations.push(format!("excluded path: {path}"));
+                continue;
+            }
+            let ok = allowed
+                .iter()
+                .any(|prefix| path_matches_prefix(path, prefix));
+            if !ok {
+                violations.push(format!("unauthorized path: {path}"));
             }
-        }
-        if !ok {
-            violations.push(format!("unauthorized path: {path}"));
         }
     }
+    Ok(violations)
+}
 
+fn ensure_owned(
+    touched_files: &[TouchedFile],
+    allowed_prefixes: &[String],
+) -> Result<(), KernelError> {
+    let violations = ownership_violations(touched_files, allowed_prefixes)?;
     if !violations.is_empty() {
-        return Err(format!(
-            "patch ownership check failed:\n- {}",
-            violations.join("\n- ")
-        ));
+        return Err(KernelError::OwnershipViolation(violations));
     }
     Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct PatchApplyIn {
     worktreePath: String,
     patchPath: String,
     allowedPathPrefixes: Vec<String>,
+    #[serde(default)]
+    threeWay: bool,
+    #[serde(default)]
+    whitespace: Option<String>,
+    #[serde(default)]
+    stripLeadingComponents: Option<u32>,
+    /// When false, patches touching binary files are refused before anything is applied.
+    #[serde(default = "default_true")]
+    allowBinary: bool,
 }
 
-#[derive(Serialize)]
+impl Default for PatchApplyIn {
+    fn default() -> Self {
+        PatchApplyIn {
+            worktreePath: String::new(),
+            patchPath: String::new(),
+            allowedPathPrefixes: Vec::new(),
+            threeWay: false,
+            whitespace: None,
+            stripLeadingComponents: None,
+            allowBinary: true,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
 struct PatchApplyOut {
-    touchedFiles: Vec<String>,
+    touchedFiles: Vec<TouchedFile>,
+    conflicts: Vec<String>,
+}
+
+/// Files `git apply --3way` left with conflict markers.
+fn parse_apply_conflicts(text: &str) -> Vec<String> {
+    // Newer git prints `Applied patch to '<file>' with conflicts.`, older `Applied patch <file> with conflicts.`
+    let mut files: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let Some(rest) = line.trim().strip_prefix("Applied patch ") else {
+            continue;
+        };
+        let Some(file) = rest.strip_suffix(" with conflicts.") else {
+            continue;
+        };
+        let file = file.strip_prefix("to ").unwrap_or(file);
+        let file = file
+            .strip_prefix('\'')
+            .and_then(|f| f.strip_suffix('\''))
+            .unwrap_or(file);
+        if !files.iter().any(|f| f == file) {
+            files.push(file.to_string());
+        }
+    }
+    files
+}
+
+/// `git apply` flags shared by `patch.apply` and `patch.validate`.
+fn git_apply_options(
+    strip: u32,
+    three_way: bool,
+    whitespace: Option<&str>,
+) -> Result<Vec<String>, KernelError> {
+    let mut args: Vec<String> = vec![format!("-p{strip}")];
+    if three_way {
+        args.push("--3way".to_string());
+    }
+    if let Some(action) = whitespace {
+        if !["nowarn", "warn", "fix", "error", "error-all"].contains(&action) {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid whitespace action: {action} (expected nowarn|warn|fix|error|error-all)"
+            )));
+        }
+        args.push(format!("--whitespace={action}"));
+    }
+    Ok(args)
+}
+
+fn refuse_binary(touched: &[TouchedFile]) -> Result<(), KernelError> {
+    let binary: Vec<&str> = touched
+        .iter()
+        .filter(|t| t.isBinary)
+        .map(|t| t.path.as_str())
+        .collect();
+    if !binary.is_empty() {
+        return Err(KernelError::InvalidInput(format!(
+            "binary patches are not allowed: {}",
+            binary.join(", ")
+        )));
+    }
+    Ok(())
 }
 
-fn patch_apply(input: PatchApplyIn) -> Result<PatchApplyOut, String> {
+fn patch_apply(input: PatchApplyIn) -> Result<PatchApplyOut, KernelError> {
     let worktree_path = PathBuf::from(input.worktreePath);
     let patch_path = PathBuf::from(input.patchPath);
 
-    let patch_text = fs::read_to_string(&patch_path)
-        .map_err(|e| format!("failed to read patch file: {}: {e}", patch_path.display()))?;
+    let patch_text = fs::read_to_string(&patch_path).map_err(io_err(format!(
+        "failed to read patch file: {}",
+        patch_path.display()
+    )))?;
     let trimmed = patch_text.trim();
     if trimmed.is_empty() {
         return Ok(PatchApplyOut {
             touchedFiles: Vec::new(),
+            conflicts: Vec::new(),
         });
     }
 
-    let touched = touched_files_from_unified_diff(&patch_path)?;
+    let strip = input.stripLeadingComponents.unwrap_or(1);
+    let touched = touched_files_from_unified_diff(&patch_path, strip)?;
     if touched.is_empty() {
-        return Err("patch has content but no \"diff --git\" headers (not a unified diff?)".to_string());
+        return Err(KernelError::InvalidInput(
+            "patch has content but no \"diff --git\" headers (not a unified diff?)".to_string(),
+        ));
     }
     ensure_owned(&touched, &input.allowedPathPrefixes)?;
+    if !input.allowBinary {
+        refuse_binary(&touched)?;
+    }
+
+    let extra_args = git_apply_options(strip, input.threeWay, input.whitespace.as_deref())?;
 
-    let args_check = vec![
+    let mut args_check = vec![
         "-C".to_string(),
         worktree_path.display().to_string(),
         "apply".to_string(),
         "--check".to_string(),
-        patch_path.display().to_string(),
     ];
+    args_check.extend(extra_args.iter().cloned());
+    args_check.push(patch_path.display().to_string());
     let out = run_git(&args_check, None)?;
     if !out.ok {
-        return Err(if out.stderr.is_empty() {
-            "git apply --check failed".to_string()
-        } else {
-            out.stderr
-        });
+        return Err(git_failed("git apply --check", &out));
     }
 
-    let args_apply = vec![
+    let mut args_apply = vec![
         "-C".to_string(),
         worktree_path.display().to_string(),
         "apply".to_string(),
-        patch_path.display().to_string(),
     ];
+    args_apply.extend(extra_args);
+    args_apply.push(patch_path.display().to_string());
     let out2 = run_git(&args_apply, None)?;
-    if !out2.ok {
-        return Err(if out2.stderr.is_empty() {
-            "git apply failed".to_string()
-        } else {
-            out2.stderr
-        });
+
+    // A three-way apply that leaves conflict markers exits non-zero but has still applied.
+    let conflicts = if input.threeWay {
+        parse_apply_conflicts(&format!("{}\n{}", out2.stdout, out2.stderr))
+    } else {
+        Vec::new()
+    };
+    if !out2.ok && conflicts.is_empty() {
+        return Err(git_failed("git apply", &out2));
     }
 
     Ok(PatchApplyOut {
-        touchedFiles: touched
-            .into_iter()
-            .filter(|(_, invalid)| !*invalid)
-            .map(|(p, _)| p)
-            .collect(),
+        touchedFiles: touched.into_iter().filter(|t| !t.invalid).collect(),
+        conflicts,
     })
 }
 
-#[derive(Deserialize)]
-struct CommitAllIn {
-    repoRoot: String,
-    message: String,
+#[derive(Deserialize, JsonSchema)]
+struct PatchGenerateIn {
+    worktreePath: String,
+    #[serde(default)]
+    base: Option<String>,
+    #[serde(default)]
+    staged: bool,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    contextLines: Option<u32>,
+    outputPath: String,
 }
 
-#[derive(Serialize)]
-struct CommitAllOut {
-    sha: String,
+#[derive(Serialize, JsonSchema)]
+struct PatchGenerateOut {
+    patchPath: String,
+    touchedFiles: Vec<String>,
+    hasChanges: bool,
 }
 
-fn commit_all(input: CommitAllIn) -> Result<CommitAllOut, String> {
-    let repo_root = PathBuf::from(input.repoRoot);
+fn patch_generate(input: PatchGenerateIn) -> Result<PatchGenerateOut, KernelError> {
+    let worktree_path = PathBuf::from(input.worktreePath);
+    let output_path = PathBuf::from(input.outputPath);
 
-    let out_add = run_git(
-        &vec![
-            "-C".to_string(),
-            repo_root.display().to_string(),
-            "add".to_string(),
-            "-A".to_string(),
-        ],
-        None,
-    )?;
-    if !out_add.ok {
-        return Err(out_add.stderr.is_empty().then(|| "git add failed".to_string()).unwrap_or(out_add.stderr));
+    let mut args = git_args(
+        &worktree_path,
+        &["diff", "--no-color", "--no-ext-diff", "--binary"],
+    );
+    if input.staged {
+        args.push("--cached".to_string());
     }
+    if let Some(n) = input.contextLines {
+        args.push(format!("--unified={n}"));
+    }
+    match input.base.as_deref().map(str::trim) {
+        Some(base) if !base.is_empty() => {
+            if !is_valid_object_name(base) {
+                return Err(KernelError::InvalidInput(format!("invalid base: {base:?}")));
+            }
+            args.push(base.to_string());
+        }
+        _ => args.push("HEAD".to_string()),
+    }
+    args.push("--".to_string());
+    args.extend(input.paths);
 
-    let out_commit = run_git(
-        &vec![
-            "-C".to_string(),
-            repo_root.display().to_string(),
-            "commit".to_string(),
-            "-m".to_string(),
-            input.message,
-        ],
+    let out = run_cmd_untrimmed(git_binary(), &args, None)?;
+    if !out.ok {
+        return Err(git_failed("git diff", &out));
+    }
+    fs_atomic_write(&output_path, out.stdout.as_bytes())?;
+
+    let touched = touched_files_from_unified_diff(&output_path, 1)?;
+    Ok(PatchGenerateOut {
+        patchPath: output_path.display().to_string(),
+        hasChanges: !out.stdout.trim().is_empty(),
+        touchedFiles: touched_paths(touched),
+    })
+}
+
+/// Takes the same apply options as `PatchApplyIn`, so a patch validates exactly when
+/// `patch.apply` with those options would accept it.
+#[derive(Deserialize, JsonSchema)]
+struct PatchValidateIn {
+    worktreePath: String,
+    patchPath: String,
+    allowedPathPrefixes: Vec<String>,
+    #[serde(default)]
+    threeWay: bool,
+    #[serde(default)]
+    whitespace: Option<String>,
+    #[serde(default)]
+    stripLeadingComponents: Option<u32>,
+    #[serde(default = "default_true")]
+    allowBinary: bool,
+}
+
+impl Default for PatchValidateIn {
+    fn default() -> Self {
+        PatchValidateIn {
+            worktreePath: String::new(),
+            patchPath: String::new(),
+            allowedPathPrefixes: Vec::new(),
+            threeWay: false,
+            whitespace: None,
+            stripLeadingComponents: None,
+            allowBinary: true,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct PatchValidateOut {
+    ownershipOk: bool,
+    applyOk: bool,
+    touchedFiles: Vec<String>,
+    violations: Vec<String>,
+    applyError: Option<String>,
+}
+
+fn patch_validate(input: PatchValidateIn) -> Result<PatchValidateOut, KernelError> {
+    let worktree_path = PathBuf::from(input.worktreePath);
+    let patch_path = PathBuf::from(input.patchPath);
+
+    let patch_text = fs::read_to_string(&patch_path).map_err(io_err(format!(
+        "failed to read patch file: {}",
+        patch_path.display()
+    )))?;
+    if patch_text.trim().is_empty() {
+        return Ok(PatchValidateOut {
+            ownershipOk: true,
+            applyOk: true,
+            touchedFiles: Vec::new(),
+            violations: Vec::new(),
+            applyError: None,
+        });
+    }
+
+    let strip = input.stripLeadingComponents.unwrap_or(1);
+    let touched = touched_files_from_unified_diff(&patch_path, strip)?;
+    if touched.is_empty() {
+        return Err(KernelError::InvalidInput(
+            "patch has content but no \"diff --git\" headers (not a unified diff?)".to_string(),
+        ));
+    }
+    let violations = ownership_violations(&touched, &input.allowedPathPrefixes)?;
+    let mut args = git_args(&worktree_path, &["apply", "--check"]);
+    args.extend(git_apply_options(
+        strip,
+        input.threeWay,
+        input.whitespace.as_deref(),
+    )?);
+    args.push(patch_path.display().to_string());
+
+    // Run the apply check regardless of ownership so callers get both answers at once.
+    let out = run_git(&args, None)?;
+    let binary_refused = if input.allowBinary {
+        Ok(())
+    } else {
+        refuse_binary(&touched)
+    };
+    let apply_error = if let Err(e) = binary_refused {
+        Some(e.to_string())
+    } else if out.ok {
+        None
+    } else if out.stderr.is_empty() {
+        Some("git apply --check failed".to_string())
+    } else {
+        Some(out.stderr)
+    };
+
+    Ok(PatchValidateOut {
+        ownershipOk: violations.is_empty(),
+        applyOk: apply_error.is_none(),
+        touchedFiles: touched_paths(touched),
+        violations,
+        applyError: apply_error,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitIdentity {
+    name: String,
+    email: String,
+    /// RFC 3339 timestamp, e.g. `2024-01-02T03:04:05Z`.
+    #[serde(default)]
+    date: Option<String>,
+}
+
+/// `GIT_<role>_NAME`/`_EMAIL`/`_DATE` variables for `role` `AUTHOR` or `COMMITTER`.
+fn git_identity_env(role: &str, id: &GitIdentity) -> Result<Vec<(String, String)>, KernelError> {
+    let mut env = vec![
+        (format!("GIT_{role}_NAME"), id.name.clone()),
+        (format!("GIT_{role}_EMAIL"), id.email.clone()),
+    ];
+    if let Some(date) = &id.date {
+        let t = OffsetDateTime::parse(date, &Rfc3339).map_err(|e| {
+            KernelError::InvalidInput(format!(
+                "invalid {} date (expected RFC 3339): {date}: {e}",
+                role.to_lowercase()
+            ))
+        })?;
+        // git's internal "<unix seconds> <+hhmm>" form avoids any date-parsing ambiguity.
+        let offset = t.offset();
+        let sign = if offset.is_negative() { '-' } else { '+' };
+        let date = format!(
+            "{} {sign}{:02}{:02}",
+            t.unix_timestamp(),
+            offset.whole_hours().unsigned_abs(),
+            offset.minutes_past_hour().unsigned_abs()
+        );
+        env.push((format!("GIT_{role}_DATE"), date));
+    }
+    Ok(env)
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct CommitTrailer {
+    token: String,
+    value: String,
+}
+
+/// Appends `Token: value` trailers after a blank line, rejecting malformed tokens.
+fn message_with_trailers(message: &str, trailers: &[CommitTrailer]) -> Result<String, KernelError> {
+    if trailers.is_empty() {
+        return Ok(message.to_string());
+    }
+    let mut out = message.trim_end().to_string();
+    out.push('\n');
+    for t in trailers {
+        if t.token.is_empty() || t.token.contains(|c: char| c == ':' || c.is_whitespace()) {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid trailer token: {:?}",
+                t.token
+            )));
+        }
+        if t.value.contains('\n') {
+            return Err(KernelError::InvalidInput(format!(
+                "trailer value must be a single line: {}",
+                t.token
+            )));
+        }
+        out.push_str(&format!("\n{}: {}", t.token, t.value.trim()));
+    }
+    Ok(out)
+}
+
+#[derive(Deserialize, JsonSchema, Default)]
+struct CommitAllIn {
+    repoRoot: String,
+    message: String,
+    #[serde(default)]
+    author: Option<GitIdentity>,
+    #[serde(default)]
+    committer: Option<GitIdentity>,
+    /// Pass `--allow-empty` so a clean worktree still produces a commit.
+    #[serde(default)]
+    allowEmpty: bool,
+    /// Pass `--no-verify` to skip pre-commit and commit-msg hooks.
+    #[serde(default)]
+    noVerify: bool,
+    /// Replace the last commit (`--amend`) instead of adding a new one.
+    #[serde(default)]
+    amend: bool,
+    /// e.g. `Co-authored-by`, `Signed-off-by`; appended after a blank line.
+    #[serde(default)]
+    trailers: Vec<CommitTrailer>,
+    /// Stage only these paths instead of `git add -A`; an empty list stages nothing and
+    /// commits what is already in the index.
+    #[serde(default)]
+    paths: Option<Vec<String>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct CommitAllOut {
+    sha: String,
+    /// HEAD before the commit; `None` on an unborn branch.
+    previousSha: Option<String>,
+    /// Root tree of the new commit (`HEAD^{tree}`).
+    treeSha: String,
+}
+
+fn commit_all(input: CommitAllIn) -> Result<CommitAllOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut env = Vec::new();
+    if let Some(author) = &input.author {
+        env.extend(git_identity_env("AUTHOR", author)?);
+    }
+    if let Some(committer) = &input.committer {
+        env.extend(git_identity_env("COMMITTER", committer)?);
+    }
+    let message = message_with_trailers(&input.message, &input.trailers)?;
+
+    let out_prev = run_git(
+        &git_args(&repo_root, &["rev-parse", "--verify", "--quiet", "HEAD"]),
         None,
     )?;
+    let previous_sha = out_prev.ok.then(|| out_prev.stdout.trim().to_string());
+    if input.amend && previous_sha.is_none() {
+        return Err(KernelError::NotFound("no commits to amend".to_string()));
+    }
+
+    let add_args: Option<Vec<String>> = match &input.paths {
+        None => Some(git_args(&repo_root, &["add", "-A"])),
+        Some(paths) if paths.is_empty() => None,
+        Some(paths) => {
+            if let Some(bad) = paths.iter().find(|p| {
+                Path::new(p)
+                    .components()
+                    .any(|c| matches!(c, Component::ParentDir))
+            }) {
+                return Err(KernelError::PathTraversal(format!(
+                    "path must not contain '..': {bad}"
+                )));
+            }
+            let mut args = git_args(&repo_root, &["add", "--"]);
+            args.extend(paths.iter().cloned());
+            Some(args)
+        }
+    };
+    if let Some(add_args) = add_args {
+        let out_add = run_git(&add_args, None)?;
+        if !out_add.ok {
+            return Err(git_failed("git add", &out_add));
+        }
+    }
+
+    let mut commit_args = vec![
+        "-C".to_string(),
+        repo_root.display().to_string(),
+        "commit".to_string(),
+        "-m".to_string(),
+        message,
+    ];
+    if input.allowEmpty {
+        commit_args.push("--allow-empty".to_string());
+    }
+    if input.noVerify {
+        commit_args.push("--no-verify".to_string());
+    }
+    if input.amend {
+        commit_args.push("--amend".to_string());
+    }
+    let out_commit = run_git_with_env(&commit_args, &env)?;
     if !out_commit.ok {
-        return Err(out_commit.stderr.is_empty().then(|| "git commit failed".to_string()).unwrap_or(out_commit.stderr));
+        return Err(git_failed("git commit", &out_commit));
     }
 
     let out_sha = run_git(
-        &vec![
+        &[
             "-C".to_string(),
             repo_root.display().to_string(),
             "rev-parse".to_string(),
             "HEAD".to_string(),
+            "HEAD^{tree}".to_string(),
         ],
         None,
     )?;
     if !out_sha.ok {
-        return Err(out_sha.stderr.is_empty().then(|| "git rev-parse HEAD failed".to_string()).unwrap_or(out_sha.stderr));
+        return Err(git_failed("git rev-parse HEAD", &out_sha));
     }
+    let mut shas = out_sha.stdout.lines().map(|l| l.trim().to_string());
 
     Ok(CommitAllOut {
-        sha: out_sha.stdout.trim().to_string(),
+        sha: shas.next().unwrap_or_default(),
+        previousSha: previous_sha,
+        treeSha: shas.next().unwrap_or_default(),
     })
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct ProtocolVersionIn {}
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct ProtocolVersionOut {
     version: i32,
     protocol: i32,
@@ -524,7 +2222,7 @@ struct ProtocolVersionOut {
     commands: Vec<String>,
 }
 
-fn protocol_version(_input: ProtocolVersionIn) -> Result<ProtocolVersionOut, String> {
+fn protocol_version(_input: ProtocolVersionIn) -> Result<ProtocolVersionOut, KernelError> {
     Ok(ProtocolVersionOut {
         version: 1,
         protocol: PROTOCOL_VERSION,
@@ -533,12 +2231,12 @@ fn protocol_version(_input: ProtocolVersionIn) -> Result<ProtocolVersionOut, Str
     })
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 struct RepoInfoIn {
     cwd: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct RepoInfoOut {
     version: i32,
     repoRoot: Option<String>,
@@ -547,11 +2245,11 @@ struct RepoInfoOut {
     clean: bool,
 }
 
-fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, String> {
+fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, KernelError> {
     let cwd = PathBuf::from(input.cwd);
 
     let out_root = run_git(
-        &vec![
+        &[
             "-C".to_string(),
             cwd.display().to_string(),
             "rev-parse".to_string(),
@@ -573,7 +2271,7 @@ fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, String> {
     let repo_root = out_root.stdout.trim().to_string();
 
     let out_branch = run_git(
-        &vec![
+        &[
             "-C".to_string(),
             repo_root.clone(),
             "rev-parse".to_string(),
@@ -583,15 +2281,11 @@ fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, String> {
         None,
     )?;
     if !out_branch.ok {
-        return Err(out_branch
-            .stderr
-            .is_empty()
-            .then(|| "git rev-parse --abbrev-ref HEAD failed".to_string())
-            .unwrap_or(out_branch.stderr));
+        return Err(git_failed("git rev-parse --abbrev-ref HEAD", &out_branch));
     }
 
     let out_sha = run_git(
-        &vec![
+        &[
             "-C".to_string(),
             repo_root.clone(),
             "rev-parse".to_string(),
@@ -600,15 +2294,11 @@ fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, String> {
         None,
     )?;
     if !out_sha.ok {
-        return Err(out_sha
-            .stderr
-            .is_empty()
-            .then(|| "git rev-parse HEAD failed".to_string())
-            .unwrap_or(out_sha.stderr));
+        return Err(git_failed("git rev-parse HEAD", &out_sha));
     }
 
     let out_status = run_git(
-        &vec![
+        &[
             "-C".to_string(),
             repo_root.clone(),
             "status".to_string(),
@@ -618,11 +2308,7 @@ fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, String> {
         None,
     )?;
     if !out_status.ok {
-        return Err(out_status
-            .stderr
-            .is_empty()
-            .then(|| "git status --porcelain failed".to_string())
-            .unwrap_or(out_status.stderr));
+        return Err(git_failed("git status --porcelain", &out_status));
     }
 
     Ok(RepoInfoOut {
@@ -634,326 +2320,11033 @@ fn repo_info(input: RepoInfoIn) -> Result<RepoInfoOut, String> {
     })
 }
 
-#[derive(Deserialize)]
-struct VerifyCmdIn {
-    name: String,
-    command: String,
-}
-
-#[derive(Deserialize)]
-struct VerifyRunIn {
-    worktreePath: String,
-    outDir: String,
-    commands: Vec<VerifyCmdIn>,
+#[derive(Deserialize, JsonSchema)]
+struct GitStatusIn {
+    repoRoot: String,
+    /// Only `false` is accepted: entries are always parsed porcelain v2, never short-format text.
+    #[serde(default)]
+    short: bool,
+    #[serde(default = "default_true")]
+    branch: bool,
+    #[serde(default, alias = "unoTracked")]
+    untrackedFiles: Option<String>,
 }
 
-#[derive(Serialize)]
-struct VerifyCmdOut {
-    name: String,
-    command: String,
-    ok: bool,
-    exitCode: i32,
-    outputPath: String,
+#[derive(Serialize, JsonSchema)]
+struct StatusEntry {
+    indexStatus: char,
+    worktreeStatus: char,
+    path: String,
+    origPath: Option<String>,
 }
 
-#[derive(Serialize)]
-struct VerifySummaryOut {
-    version: i32,
-    ranAt: String,
-    commands: Vec<VerifyCmdOut>,
-    ok: bool,
+#[derive(Serialize, Default, JsonSchema)]
+struct GitStatusOut {
+    branch: Option<String>,
+    ahead: Option<i32>,
+    behind: Option<i32>,
+    entries: Vec<StatusEntry>,
 }
 
-fn safe_name(name: &str) -> String {
-    let mut out = String::new();
-    let mut last_was_dash = false;
-    for ch in name.trim().to_lowercase().chars() {
-        let allowed = ch.is_ascii_alphanumeric() || ch == '.' || ch == '_' || ch == '-';
-        if allowed {
-            out.push(ch);
-            last_was_dash = false;
-        } else if !last_was_dash {
-            out.push('-');
-            last_was_dash = true;
+fn parse_status_porcelain_v2(text: &str) -> GitStatusOut {
+    // Records are NUL-terminated (`-z`); rename records carry the original path as the next record.
+    let mut out = GitStatusOut::default();
+    let xy = |s: &str| {
+        let mut c = s.chars();
+        (c.next().unwrap_or('.'), c.next().unwrap_or('.'))
+    };
+    let mut records = text.split('\0');
+    while let Some(rec) = records.next() {
+        if let Some(header) = rec.strip_prefix("# ") {
+            let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+            match key {
+                "branch.head" if value != "(detached)" => out.branch = Some(value.to_string()),
+                "branch.ab" => {
+                    let mut it = value.split_whitespace();
+                    out.ahead = it
+                        .next()
+                        .and_then(|a| a.trim_start_matches('+').parse().ok());
+                    out.behind = it
+                        .next()
+                        .and_then(|b| b.trim_start_matches('-').parse().ok());
+                }
+                _ => {}
+            }
+        } else if let Some(rest) = rec.strip_prefix("1 ") {
+            let f: Vec<&str> = rest.splitn(8, ' ').collect();
+            if f.len() == 8 {
+                let (x, y) = xy(f[0]);
+                out.entries.push(StatusEntry {
+                    indexStatus: x,
+                    worktreeStatus: y,
+                    path: f[7].to_string(),
+                    origPath: None,
+                });
+            }
+        } else if let Some(rest) = rec.strip_prefix("2 ") {
+            let f: Vec<&str> = rest.splitn(9, ' ').collect();
+            if f.len() == 9 {
+                let (x, y) = xy(f[0]);
+                out.entries.push(StatusEntry {
+                    indexStatus: x,
+                    worktreeStatus: y,
+                    path: f[8].to_string(),
+                    origPath: records.next().map(|p| p.to_string()),
+                });
+            }
+        } else if let Some(rest) = rec.strip_prefix("u ") {
+            let f: Vec<&str> = rest.splitn(10, ' ').collect();
+            if f.len() == 10 {
+                let (x, y) = xy(f[0]);
+                out.entries.push(StatusEntry {
+                    indexStatus: x,
+                    worktreeStatus: y,
+                    path: f[9].to_string(),
+                    origPath: None,
+                });
+            }
+        } else if let Some(path) = rec.strip_prefix("? ") {
+            out.entries.push(StatusEntry {
+                indexStatus: '?',
+                worktreeStatus: '?',
+                path: path.to_string(),
+                origPath: None,
+            });
         }
     }
-    let out = out.trim_matches('-').to_string();
-    if out.is_empty() {
-        "command".to_string()
-    } else {
-        out
-    }
+    out
 }
 
-fn run_shell_command_to_file(command: &str, cwd: &Path, output_path: &Path) -> Result<i32, String> {
-    let file = File::create(output_path)
-        .map_err(|e| format!("failed to create output file {}: {e}", output_path.display()))?;
-    let file_err = file
-        .try_clone()
-        .map_err(|e| format!("failed to clone output file handle: {e}"))?;
-
-    let mut cmd;
-    if cfg!(windows) {
-        cmd = Command::new("cmd");
-        cmd.arg("/C").arg(command);
-    } else {
-        cmd = Command::new("sh");
-        cmd.arg("-lc").arg(command);
+fn git_status(input: GitStatusIn) -> Result<GitStatusOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.short {
+        return Err(KernelError::InvalidInput(
+            "short is not supported: git.status always returns parsed porcelain v2 entries"
+                .to_string(),
+        ));
     }
-    let status = cmd
-        .current_dir(cwd)
-        .stdin(Stdio::null())
-        .stdout(Stdio::from(file))
-        .stderr(Stdio::from(file_err))
-        .status()
-        .map_err(|e| format!("failed to run command: {e}"))?;
+    let untracked = input.untrackedFiles.unwrap_or_else(|| "all".to_string());
+    if !matches!(untracked.as_str(), "no" | "normal" | "all") {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid untrackedFiles mode: {untracked} (expected no|normal|all)"
+        )));
+    }
+    let mut args = git_args(&repo_root, &["status", "--porcelain=v2", "-z"]);
+    if input.branch {
+        args.push("--branch".to_string());
+    }
+    args.push(format!("--untracked-files={untracked}"));
+    let out = run_git_checked(&args, "git status")?;
+    Ok(parse_status_porcelain_v2(&out.stdout))
+}
 
-    Ok(status.code().unwrap_or(1))
+#[derive(Deserialize, JsonSchema)]
+struct GitLogIn {
+    repoRoot: String,
+    #[serde(default, rename = "ref")]
+    ref_: String,
+    #[serde(default)]
+    maxCount: Option<u32>,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    follow: bool,
 }
 
-fn verify_run(input: VerifyRunIn) -> Result<VerifySummaryOut, String> {
-    let worktree = PathBuf::from(input.worktreePath);
-    let out_dir = PathBuf::from(input.outDir);
-    fs::create_dir_all(&out_dir)
-        .map_err(|e| format!("failed to create verify outDir {}: {e}", out_dir.display()))?;
+#[derive(Serialize, JsonSchema)]
+struct CommitInfo {
+    sha: String,
+    parentShas: Vec<String>,
+    authorName: String,
+    authorEmail: String,
+    authorDate: String,
+    committerName: String,
+    committerEmail: String,
+    committerDate: String,
+    subject: String,
+    body: String,
+}
 
-    let mut results: Vec<VerifyCmdOut> = Vec::new();
-    let mut all_ok = true;
+#[derive(Serialize, JsonSchema)]
+struct GitLogOut {
+    commits: Vec<CommitInfo>,
+}
 
-    for c in input.commands.iter() {
-        let name_safe = safe_name(&c.name);
-        let output_path = out_dir.join(format!("{name_safe}.txt"));
+// Fields are NUL-separated and records end with an ASCII record separator, so bodies may span lines.
+const LOG_FORMAT: &str =
+    "format:%H%x00%P%x00%an%x00%ae%x00%aI%x00%cn%x00%ce%x00%cI%x00%s%x00%b%x1e";
 
-        let exit_code = run_shell_command_to_file(&c.command, &worktree, &output_path)?;
-        let ok = exit_code == 0;
-        if !ok {
-            all_ok = false;
+fn parse_log_records(text: &str) -> Vec<CommitInfo> {
+    let mut commits = Vec::new();
+    for rec in text.split('\x1e') {
+        let rec = rec.trim_start_matches('\n');
+        if rec.is_empty() {
+            continue;
         }
-
-        results.push(VerifyCmdOut {
-            name: c.name.clone(),
-            command: c.command.clone(),
-            ok,
-            exitCode: exit_code,
-            outputPath: output_path.display().to_string(),
+        let f: Vec<&str> = rec.splitn(10, '\0').collect();
+        if f.len() != 10 {
+            continue;
+        }
+        commits.push(CommitInfo {
+            sha: f[0].to_string(),
+            parentShas: f[1].split_whitespace().map(|p| p.to_string()).collect(),
+            authorName: f[2].to_string(),
+            authorEmail: f[3].to_string(),
+            authorDate: f[4].to_string(),
+            committerName: f[5].to_string(),
+            committerEmail: f[6].to_string(),
+            committerDate: f[7].to_string(),
+            subject: f[8].to_string(),
+            body: f[9].trim_end().to_string(),
         });
     }
+    commits
+}
 
-    let summary = VerifySummaryOut {
-        version: 1,
-        ranAt: now_iso(),
-        commands: results,
-        ok: all_ok,
-    };
+fn git_log(input: GitLogIn) -> Result<GitLogOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.maxCount == Some(0) {
+        return Err(KernelError::InvalidInput(
+            "maxCount must be greater than 0".to_string(),
+        ));
+    }
+    if input.follow && input.paths.len() != 1 {
+        return Err(KernelError::InvalidInput(
+            "follow requires exactly one path".to_string(),
+        ));
+    }
+    if input.ref_.starts_with('-') {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid ref: {:?}",
+            input.ref_
+        )));
+    }
 
-    // Write evidence file.
-    let summary_path = out_dir.join("summary.json");
-    let mut f = File::create(&summary_path)
-        .map_err(|e| format!("failed to write {}: {e}", summary_path.display()))?;
-    let json = serde_json::to_string_pretty(&summary)
-        .map_err(|e| format!("failed to serialize verify summary: {e}"))?;
-    f.write_all(json.as_bytes())
-        .and_then(|_| f.write_all(b"\n"))
-        .map_err(|e| format!("failed to write verify summary: {e}"))?;
+    let mut args = git_args(&repo_root, &["log", &format!("--format={LOG_FORMAT}")]);
+    if let Some(n) = input.maxCount {
+        args.push(format!("--max-count={n}"));
+    }
+    if input.follow {
+        args.push("--follow".to_string());
+    }
+    args.push(if input.ref_.trim().is_empty() {
+        "HEAD".to_string()
+    } else {
+        input.ref_
+    });
+    args.push("--".to_string());
+    args.extend(input.paths);
+    let out = run_git_checked(&args, "git log")?;
+    Ok(GitLogOut {
+        commits: parse_log_records(&out.stdout),
+    })
+}
 
-    Ok(summary)
+#[derive(Deserialize, JsonSchema)]
+struct GitDiffIn {
+    repoRoot: String,
+    base: String,
+    #[serde(default)]
+    head: Option<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    stat: bool,
+    #[serde(default)]
+    nameOnly: bool,
+    #[serde(default)]
+    unified: Option<u32>,
 }
 
-fn usage() {
-    let cmds = COMMANDS.join("\n  ");
-    eprintln!(
-        r#"ecc-kernel
+#[derive(Serialize, JsonSchema)]
+struct FileStat {
+    path: String,
+    insertions: u64,
+    deletions: u64,
+}
 
-Usage:
-  ecc-kernel <command>   (JSON input on stdin; JSON output on stdout)
+#[derive(Serialize, JsonSchema)]
+struct GitDiffOut {
+    patch: Option<String>,
+    stats: Option<Vec<FileStat>>,
+    files: Option<Vec<String>>,
+}
 
-Commands:
-  {cmds}
-"#
-        ,
-        cmds = cmds
-    );
+fn parse_numstat(text: &str) -> Vec<FileStat> {
+    // `<insertions>\t<deletions>\t<path>`; binary files report `-` for both counts.
+    text.lines()
+        .filter_map(|l| {
+            let mut it = l.splitn(3, '\t');
+            let ins = it.next()?;
+            let del = it.next()?;
+            let path = it.next()?;
+            Some(FileStat {
+                path: path.to_string(),
+                insertions: ins.parse().unwrap_or(0),
+                deletions: del.parse().unwrap_or(0),
+            })
+        })
+        .collect()
 }
 
-fn real_main() -> Result<(), String> {
-  let mut args = std::env::args();
-  let _ = args.next();
-  let cmd = match args.next() {
-    Some(c) => c,
-    None => {
-      usage();
-      return Err("missing command".to_string());
+fn git_diff(input: GitDiffIn) -> Result<GitDiffOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.stat && input.nameOnly {
+        return Err(KernelError::InvalidInput(
+            "stat and nameOnly are mutually exclusive".to_string(),
+        ));
     }
-  };
-
-  if cmd == "--help" || cmd == "-h" {
-    usage();
-    return Ok(());
-  }
-
-  if cmd == "--version" || cmd == "-V" {
-    println!("ecc-kernel {}", env!("CARGO_PKG_VERSION"));
-    return Ok(());
-  }
-
-  let result = match cmd.as_str() {
-    "worktree.ensure" => {
-      let input: WorktreeEnsureIn = read_stdin_json()?;
-      let out = worktree_ensure(input)?;
-      write_stdout_json(&out)
+    if input.base.trim().is_empty() {
+        return Err(KernelError::InvalidInput("base is required".to_string()));
     }
-    "worktree.remove" => {
-      let input: WorktreeRemoveIn = read_stdin_json()?;
-      let out = worktree_remove(input)?;
-      write_stdout_json(&out)
+    if let Some(rev) = std::iter::once(&input.base)
+        .chain(input.head.as_ref())
+        .find(|r| r.starts_with('-'))
+    {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid revision: {rev:?}"
+        )));
     }
-    "patch.apply" => {
-      let input: PatchApplyIn = read_stdin_json()?;
-      let out = patch_apply(input)?;
-      write_stdout_json(&out)
+
+    let mut args = git_args(&repo_root, &["diff", "--no-color", "--no-ext-diff"]);
+    if input.stat {
+        args.push("--numstat".to_string());
+    } else if input.nameOnly {
+        args.push("--name-only".to_string());
+    } else if let Some(n) = input.unified {
+        args.push(format!("--unified={n}"));
     }
-    "git.commit_all" => {
-      let input: CommitAllIn = read_stdin_json()?;
-      let out = commit_all(input)?;
-      write_stdout_json(&out)
+    args.push(input.base);
+    if let Some(head) = input.head {
+        args.push(head);
     }
-    "protocol.version" => {
-      let input: ProtocolVersionIn = read_stdin_json()?;
-      let out = protocol_version(input)?;
-      write_stdout_json(&out)
+    args.push("--".to_string());
+    args.extend(input.paths);
+    // Untrimmed: trailing blank context lines belong to the patch.
+    let out = run_cmd_untrimmed(git_binary(), &args, None)?;
+    if !out.ok {
+        return Err(git_failed("git diff", &out));
     }
-    "repo.info" => {
-      let input: RepoInfoIn = read_stdin_json()?;
-      let out = repo_info(input)?;
-      write_stdout_json(&out)
+
+    if input.stat {
+        return Ok(GitDiffOut {
+            patch: None,
+            stats: Some(parse_numstat(&out.stdout)),
+            files: None,
+        });
     }
-    "verify.run" => {
-      let input: VerifyRunIn = read_stdin_json()?;
-      let out = verify_run(input)?;
-      write_stdout_json(&out)
+    if input.nameOnly {
+        return Ok(GitDiffOut {
+            patch: None,
+            stats: None,
+            files: Some(out.stdout.lines().map(|l| l.to_string()).collect()),
+        });
     }
-    _ => Err(format!("unknown command: {cmd}")),
-  };
-
-  result
+    Ok(GitDiffOut {
+        patch: Some(out.stdout),
+        stats: None,
+        files: None,
+    })
 }
 
-fn main() -> ExitCode {
-  match real_main() {
-    Ok(()) => ExitCode::from(0),
-    Err(err) => {
-      if err != "missing command" {
-        eprintln!("{err}");
-      }
-      ExitCode::from(1)
-    }
-  }
+#[derive(Deserialize, JsonSchema)]
+struct GitDiffCachedIn {
+    repoRoot: String,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    stat: bool,
+    #[serde(default)]
+    nameOnly: bool,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Serialize, JsonSchema)]
+struct GitDiffCachedOut {
+    patch: Option<String>,
+    stats: Option<Vec<FileStat>>,
+    files: Option<Vec<String>>,
+    /// The index matches HEAD (within `paths`, when given).
+    isEmpty: bool,
+}
 
-    fn tmp_dir(prefix: &str) -> PathBuf {
-        let mut dir = std::env::temp_dir();
-        dir.push(format!(
-            "{}-{}-{}",
-            prefix,
-            std::process::id(),
-            OffsetDateTime::now_utc().unix_timestamp_nanos()
+/// Staged changes (`git diff --cached`), the counterpart of `git.diff` for the index.
+fn git_diff_cached(input: GitDiffCachedIn) -> Result<GitDiffCachedOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.stat && input.nameOnly {
+        return Err(KernelError::InvalidInput(
+            "stat and nameOnly are mutually exclusive".to_string(),
         ));
-        dir
     }
 
-    fn git(repo: &Path, args: &[&str]) -> Result<CmdOut, String> {
-        let mut v: Vec<String> = Vec::new();
-        v.push("-C".to_string());
-        v.push(repo.display().to_string());
-        for a in args {
+    let mut args = git_args(
+        &repo_root,
+        &["diff", "--cached", "--no-color", "--no-ext-diff"],
+    );
+    if input.stat {
+        args.push("--numstat".to_string());
+    } else if input.nameOnly {
+        args.push("--name-only".to_string());
+    }
+    args.push("--".to_string());
+    args.extend(input.paths);
+    // Untrimmed: trailing blank context lines belong to the patch.
+    let out = run_cmd_untrimmed(git_binary(), &args, None)?;
+    if !out.ok {
+        return Err(git_failed("git diff --cached", &out));
+    }
+    let is_empty = out.stdout.is_empty();
+
+    if input.stat {
+        return Ok(GitDiffCachedOut {
+            patch: None,
+            stats: Some(parse_numstat(&out.stdout)),
+            files: None,
+            isEmpty: is_empty,
+        });
+    }
+    if input.nameOnly {
+        return Ok(GitDiffCachedOut {
+            patch: None,
+            stats: None,
+            files: Some(out.stdout.lines().map(|l| l.to_string()).collect()),
+            isEmpty: is_empty,
+        });
+    }
+    Ok(GitDiffCachedOut {
+        patch: Some(out.stdout),
+        stats: None,
+        files: None,
+        isEmpty: is_empty,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitShowIn {
+    repoRoot: String,
+    object: String,
+    #[serde(default)]
+    format: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitShowOut {
+    content: String,
+    objectType: String,
+}
+
+fn is_valid_ref_name(name: &str) -> bool {
+    // Subset of git-check-ref-format(1), plus trailing `~<n>` / `^<n>` revision suffixes.
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '~' || c == '^');
+    !base.is_empty()
+        && !base.starts_with('-')
+        && !base.starts_with('/')
+        && !base.ends_with('/')
+        && !base.ends_with('.')
+        && !base.ends_with(".lock")
+        && !base.contains("..")
+        && !base.contains("@{")
+        && !base.contains("//")
+        && !base.chars().any(|c| {
+            c.is_control()
+                || c.is_whitespace()
+                || matches!(c, '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+        })
+}
+
+fn is_valid_object_name(object: &str) -> bool {
+    // `<rev>` or `<rev>:<path>`, where `<rev>` is an (abbreviated) SHA or a ref name.
+    let (rev, path) = match object.split_once(':') {
+        Some((rev, path)) => (rev, Some(path)),
+        None => (object, None),
+    };
+    let is_sha = (4..=40).contains(&rev.len())
+        && rev
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase());
+    let rev_ok = is_sha || is_valid_ref_name(rev);
+    let path_ok = path.is_none_or(|p| !p.is_empty() && !p.contains(['\0', '\n']));
+    rev_ok && path_ok
+}
+
+fn git_show(input: GitShowIn) -> Result<GitShowOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if !is_valid_object_name(&input.object) {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid object name: {:?}",
+            input.object
+        )));
+    }
+
+    let out_type = run_git_checked(
+        &git_args(&repo_root, &["cat-file", "-t", &input.object]),
+        "git cat-file -t",
+    )?;
+
+    let mut args = git_args(&repo_root, &["show", "--no-color"]);
+    if !input.format.trim().is_empty() {
+        args.push(format!("--format={}", input.format.trim()));
+    }
+    args.push(input.object);
+    let out = run_cmd_untrimmed(git_binary(), &args, None)?;
+    if !out.ok {
+        return Err(git_failed("git show", &out));
+    }
+    Ok(GitShowOut {
+        content: out.stdout,
+        objectType: out_type.stdout.trim().to_string(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitBranchListIn {
+    repoRoot: String,
+    #[serde(default)]
+    remote: bool,
+    #[serde(default)]
+    all: bool,
+    #[serde(default)]
+    contains: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct BranchInfo {
+    name: String,
+    isCurrent: bool,
+    isRemote: bool,
+    upstream: Option<String>,
+    head: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitBranchListOut {
+    branches: Vec<BranchInfo>,
+}
+
+fn git_branch_list(input: GitBranchListIn) -> Result<GitBranchListOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut args = git_args(
+        &repo_root,
+        &[
+            "branch",
+            "--format=%(HEAD)%00%(refname)%00%(upstream:short)%00%(objectname)%00%(symref)",
+        ],
+    );
+    if input.all {
+        args.push("--all".to_string());
+    } else if input.remote {
+        args.push("--remotes".to_string());
+    }
+    if let Some(sha) = input.contains {
+        reject_option_like("contains", &sha)?;
+        args.push("--contains".to_string());
+        args.push(sha);
+    }
+    let out = run_git_checked(&args, "git branch")?;
+
+    let mut branches = Vec::new();
+    for line in out.stdout.lines() {
+        let f: Vec<&str> = line.split('\0').collect();
+        // Skip symbolic refs such as `origin/HEAD`.
+        if f.len() != 5 || !f[4].is_empty() {
+            continue;
+        }
+        let (name, is_remote) = if let Some(n) = f[1].strip_prefix("refs/heads/") {
+            (n, false)
+        } else if let Some(n) = f[1].strip_prefix("refs/remotes/") {
+            (n, true)
+        } else {
+            continue;
+        };
+        branches.push(BranchInfo {
+            name: name.to_string(),
+            isCurrent: f[0] == "*",
+            isRemote: is_remote,
+            upstream: (!f[2].is_empty()).then(|| f[2].to_string()),
+            head: f[3].to_string(),
+        });
+    }
+    Ok(GitBranchListOut { branches })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitBranchDeleteIn {
+    repoRoot: String,
+    branch: String,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    deleteRemoteTracking: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitBranchDeleteOut {
+    ok: bool,
+    deletedRef: String,
+}
+
+fn ref_exists(repo_root: &Path, full_ref: &str) -> bool {
+    match run_git(
+        &git_args(repo_root, &["show-ref", "--verify", "--quiet", full_ref]),
+        None,
+    ) {
+        Ok(out) => out.ok,
+        Err(_) => false,
+    }
+}
+
+fn git_branch_delete(input: GitBranchDeleteIn) -> Result<GitBranchDeleteOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let branch = input.branch;
+    if !branch_exists(&repo_root, &branch) {
+        return Err(KernelError::NotFound(format!("branch not found: {branch}")));
+    }
+
+    // Resolve the tracking remote before the branch config disappears with the branch.
+    let remote = run_git(
+        &git_args(
+            &repo_root,
+            &["config", "--get", &format!("branch.{branch}.remote")],
+        ),
+        None,
+    )
+    .ok()
+    .filter(|o| o.ok && !o.stdout.trim().is_empty() && o.stdout.trim() != ".")
+    .map(|o| o.stdout.trim().to_string())
+    .unwrap_or_else(|| "origin".to_string());
+
+    let flag = if input.force { "-D" } else { "-d" };
+    run_git_checked(
+        &git_args(&repo_root, &["branch", flag, &branch]),
+        "git branch delete",
+    )?;
+
+    if input.deleteRemoteTracking {
+        let tracking = format!("{remote}/{branch}");
+        if ref_exists(&repo_root, &format!("refs/remotes/{tracking}")) {
+            run_git_checked(
+                &git_args(&repo_root, &["branch", "-d", "-r", &tracking]),
+                "git branch -d -r",
+            )?;
+        }
+    }
+
+    Ok(GitBranchDeleteOut {
+        ok: true,
+        deletedRef: format!("refs/heads/{branch}"),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitBranchRenameIn {
+    repoRoot: String,
+    oldName: String,
+    newName: String,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitBranchRenameOut {
+    oldName: String,
+    newName: String,
+    activeWorktrees: Vec<String>,
+}
+
+fn git_branch_rename(input: GitBranchRenameIn) -> Result<GitBranchRenameOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    reject_option_like("branch name", &input.oldName)?;
+    reject_option_like("branch name", &input.newName)?;
+    if !branch_exists(&repo_root, &input.oldName) {
+        return Err(KernelError::NotFound(format!(
+            "branch not found: {}",
+            input.oldName
+        )));
+    }
+    if !input.force && branch_exists(&repo_root, &input.newName) {
+        return Err(KernelError::AlreadyExists(format!(
+            "branch already exists: {} (pass force to overwrite)",
+            input.newName
+        )));
+    }
+
+    let active_worktrees: Vec<String> = worktree_list(WorktreeListIn {
+        repoRoot: repo_root.display().to_string(),
+    })?
+    .worktrees
+    .into_iter()
+    .filter(|wt| wt.branch.as_deref() == Some(input.oldName.as_str()))
+    .map(|wt| wt.path)
+    .collect();
+
+    let mut args = git_args(&repo_root, &["branch", "--move"]);
+    if input.force {
+        args.push("--force".to_string());
+    }
+    args.push(input.oldName.clone());
+    args.push(input.newName.clone());
+    run_git_checked(&args, "git branch --move")?;
+
+    Ok(GitBranchRenameOut {
+        oldName: input.oldName,
+        newName: input.newName,
+        activeWorktrees: active_worktrees,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitFetchIn {
+    repoRoot: String,
+    #[serde(default)]
+    remote: Option<String>,
+    #[serde(default)]
+    refspecs: Vec<String>,
+    #[serde(default)]
+    prune: bool,
+    #[serde(default)]
+    depth: Option<u32>,
+    #[serde(default)]
+    tags: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct UpdatedRef {
+    refName: String,
+    oldSha: String,
+    newSha: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitFetchOut {
+    updatedRefs: Vec<UpdatedRef>,
+}
+
+fn rev_parse_quiet(repo_root: &Path, rev: &str) -> String {
+    match run_git(
+        &git_args(repo_root, &["rev-parse", "--verify", "--quiet", rev]),
+        None,
+    ) {
+        Ok(out) if out.ok => out.stdout.trim().to_string(),
+        _ => String::new(),
+    }
+}
+
+fn parse_fetch_updates(repo_root: &Path, text: &str) -> Vec<UpdatedRef> {
+    // Verbose ref lines look like `   <old>..<new>  <from> -> <to>` (or `...` for forced updates),
+    // ` * [new branch]  <from> -> <to>`, or ` - [deleted]  (none) -> <to>`.
+    let mut refs = Vec::new();
+    for line in text.lines() {
+        let Some((left, right)) = line.split_once(" -> ") else {
+            continue;
+        };
+        let to = right.split_whitespace().next().unwrap_or("");
+        let left = left.trim_start();
+        let flag = left.chars().next().unwrap_or(' ');
+        if to.is_empty() || flag == '=' {
+            continue;
+        }
+        let summary = left.trim_start_matches(['+', '-', '*', '!', 't', ' ']);
+        if let Some((old, new)) = summary
+            .split_whitespace()
+            .next()
+            .and_then(|s| s.split_once("...").or_else(|| s.split_once("..")))
+        {
+            refs.push(UpdatedRef {
+                refName: to.to_string(),
+                oldSha: rev_parse_quiet(repo_root, old),
+                newSha: rev_parse_quiet(repo_root, new),
+            });
+        } else if summary.starts_with("[new ") {
+            refs.push(UpdatedRef {
+                refName: to.to_string(),
+                oldSha: String::new(),
+                newSha: rev_parse_quiet(repo_root, to),
+            });
+        } else if summary.starts_with("[deleted]") {
+            refs.push(UpdatedRef {
+                refName: to.to_string(),
+                oldSha: String::new(),
+                newSha: String::new(),
+            });
+        }
+    }
+    refs
+}
+
+fn git_fetch(input: GitFetchIn) -> Result<GitFetchOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.depth == Some(0) {
+        return Err(KernelError::InvalidInput(
+            "depth must be at least 1".to_string(),
+        ));
+    }
+
+    let mut args = git_args(&repo_root, &["fetch", "--verbose", "--no-progress"]);
+    if input.prune {
+        args.push("--prune".to_string());
+    }
+    if input.tags {
+        args.push("--tags".to_string());
+    }
+    if let Some(depth) = input.depth {
+        args.push(format!("--depth={depth}"));
+    }
+    let remote = input.remote.unwrap_or_else(|| "origin".to_string());
+    reject_option_like("remote", &remote)?;
+    for refspec in &input.refspecs {
+        reject_option_like("refspec", refspec)?;
+    }
+    args.push(remote);
+    args.extend(input.refspecs);
+    let out = run_git_checked(&args, "git fetch")?;
+
+    Ok(GitFetchOut {
+        updatedRefs: parse_fetch_updates(&repo_root, &format!("{}\n{}", out.stdout, out.stderr)),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitPushIn {
+    repoRoot: String,
+    #[serde(default)]
+    remote: Option<String>,
+    branch: String,
+    #[serde(default)]
+    setUpstream: bool,
+    #[serde(default)]
+    forceWithLease: bool,
+    #[serde(default)]
+    forceWithLeaseExpect: Option<String>,
+    #[serde(default)]
+    dryRun: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitPushOut {
+    remote: String,
+    localRef: String,
+    remoteRef: String,
+    ok: bool,
+}
+
+fn git_push(input: GitPushIn) -> Result<GitPushOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.branch.trim().is_empty() {
+        return Err(KernelError::InvalidInput("branch is required".to_string()));
+    }
+    let remote = input.remote.unwrap_or_else(|| "origin".to_string());
+    reject_option_like("remote", &remote)?;
+    reject_option_like("branch", &input.branch)?;
+
+    let mut args = git_args(&repo_root, &["push", "--porcelain"]);
+    if let Some(expect) = input.forceWithLeaseExpect {
+        args.push(format!("--force-with-lease={}:{expect}", input.branch));
+    } else if input.forceWithLease {
+        args.push("--force-with-lease".to_string());
+    }
+    if input.setUpstream {
+        args.push("--set-upstream".to_string());
+    }
+    if input.dryRun {
+        args.push("--dry-run".to_string());
+    }
+    args.push(remote.clone());
+    args.push(input.branch.clone());
+    let out = run_git(&args, None)?;
+
+    // Porcelain ref lines: `<flag>\t<from>:<to>\t<summary>`; `!` marks a rejected ref.
+    let ref_line = out.stdout.lines().find_map(|l| {
+        let mut it = l.splitn(3, '\t');
+        let flag = it.next()?;
+        let (from, to) = it.next()?.split_once(':')?;
+        Some((flag.trim().to_string(), from.to_string(), to.to_string()))
+    });
+    let Some((flag, local_ref, remote_ref)) = ref_line else {
+        return Err(git_failed("git push", &out));
+    };
+    Ok(GitPushOut {
+        remote,
+        localRef: local_ref,
+        remoteRef: remote_ref,
+        ok: out.ok && flag != "!",
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitMergeBaseIn {
+    repoRoot: String,
+    commit1: String,
+    commit2: String,
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitMergeBaseOut {
+    bases: Vec<String>,
+}
+
+fn git_merge_base(input: GitMergeBaseIn) -> Result<GitMergeBaseOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    reject_option_like("commit1", &input.commit1)?;
+    reject_option_like("commit2", &input.commit2)?;
+    let mut args = git_args(&repo_root, &["merge-base"]);
+    if input.all {
+        args.push("--all".to_string());
+    }
+    args.push(input.commit1);
+    args.push(input.commit2);
+    let out = run_git(&args, None)?;
+    // Exit code 1 with no output means the histories are unrelated.
+    if out.status == 1 && out.stderr.is_empty() {
+        return Ok(GitMergeBaseOut { bases: Vec::new() });
+    }
+    if !out.ok {
+        return Err(git_failed("git merge-base", &out));
+    }
+    Ok(GitMergeBaseOut {
+        bases: out.stdout.lines().map(|l| l.trim().to_string()).collect(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitCherryPickIn {
+    repoRoot: String,
+    commits: Vec<String>,
+    #[serde(default)]
+    noCommit: bool,
+    #[serde(default)]
+    mainline: Option<u32>,
+    #[serde(default)]
+    allowEmpty: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitCherryPickOut {
+    newCommits: Vec<String>,
+}
+
+fn conflict_files(repo_root: &Path, output: &str) -> Vec<String> {
+    // Prefer the index's view of unmerged paths; fall back to git's `CONFLICT (...)` messages.
+    let mut files: Vec<String> = match run_git(
+        &git_args(repo_root, &["diff", "--name-only", "--diff-filter=U"]),
+        None,
+    ) {
+        Ok(out) if out.ok => out.stdout.lines().map(|l| l.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    if files.is_empty() {
+        files = output
+            .lines()
+            .filter(|l| l.starts_with("CONFLICT ("))
+            .filter_map(|l| l.rsplit_once(" in ").map(|(_, f)| f.trim().to_string()))
+            .collect();
+    }
+    files
+}
+
+fn git_cherry_pick(input: GitCherryPickIn) -> Result<GitCherryPickOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.commits.is_empty() {
+        return Err(KernelError::InvalidInput("commits is empty".to_string()));
+    }
+    for commit in &input.commits {
+        reject_option_like("commit", commit)?;
+    }
+    let before = run_git_checked(
+        &git_args(&repo_root, &["rev-parse", "HEAD"]),
+        "git rev-parse HEAD",
+    )?
+    .stdout
+    .trim()
+    .to_string();
+
+    let mut args = git_args(&repo_root, &["cherry-pick"]);
+    if input.noCommit {
+        args.push("--no-commit".to_string());
+    }
+    if let Some(n) = input.mainline {
+        args.push("--mainline".to_string());
+        args.push(n.to_string());
+    }
+    if input.allowEmpty {
+        args.push("--allow-empty".to_string());
+    }
+    args.extend(input.commits);
+    let out = run_git(&args, None)?;
+    if !out.ok {
+        let conflicts = conflict_files(&repo_root, &format!("{}\n{}", out.stdout, out.stderr));
+        if !conflicts.is_empty() {
+            return Err(KernelError::GitFailed {
+                command: "git cherry-pick".to_string(),
+                exit_code: out.status,
+                stderr: format!(
+                    "cherry-pick stopped with conflicts (resolve, or run git cherry-pick --abort):\n- {}",
+                    conflicts.join("\n- ")
+                ),
+            });
+        }
+        return Err(git_failed("git cherry-pick", &out));
+    }
+    if input.noCommit {
+        return Ok(GitCherryPickOut {
+            newCommits: Vec::new(),
+        });
+    }
+
+    let out_new = run_git_checked(
+        &git_args(
+            &repo_root,
+            &["rev-list", "--reverse", &format!("{before}..HEAD")],
+        ),
+        "git rev-list",
+    )?;
+    Ok(GitCherryPickOut {
+        newCommits: out_new
+            .stdout
+            .lines()
+            .map(|l| l.trim().to_string())
+            .collect(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitResetIn {
+    repoRoot: String,
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    mode: Option<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitResetOut {
+    previousHead: String,
+    newHead: String,
+}
+
+fn git_reset(input: GitResetIn) -> Result<GitResetOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if let Some(mode) = input.mode.as_deref() {
+        if !matches!(mode, "soft" | "mixed" | "hard" | "keep") {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid reset mode: {mode} (expected soft|mixed|hard|keep)"
+            )));
+        }
+        if !input.paths.is_empty() {
+            return Err(KernelError::InvalidInput(
+                "mode cannot be combined with paths".to_string(),
+            ));
+        }
+    }
+    let target = if input.target.trim().is_empty() {
+        "HEAD".to_string()
+    } else {
+        input.target
+    };
+    reject_option_like("reset target", &target)?;
+
+    let head_args = git_args(&repo_root, &["rev-parse", "HEAD"]);
+    let previous = run_git_checked(&head_args, "git rev-parse HEAD")?
+        .stdout
+        .trim()
+        .to_string();
+
+    let mut args = git_args(&repo_root, &["reset", "--quiet"]);
+    if input.paths.is_empty() {
+        args.push(format!("--{}", input.mode.as_deref().unwrap_or("mixed")));
+        args.push(target);
+    } else {
+        args.push(target);
+        args.push("--".to_string());
+        args.extend(input.paths);
+    }
+    run_git_checked(&args, "git reset")?;
+
+    let new_head = run_git_checked(&head_args, "git rev-parse HEAD")?
+        .stdout
+        .trim()
+        .to_string();
+    Ok(GitResetOut {
+        previousHead: previous,
+        newHead: new_head,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitStashIn {
+    repoRoot: String,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    includeUntracked: bool,
+    #[serde(default)]
+    keepIndex: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitStashOut {
+    stashRef: String,
+}
+
+fn git_stash(input: GitStashIn) -> Result<GitStashOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut args = git_args(&repo_root, &["stash", "push"]);
+    if input.includeUntracked {
+        args.push("--include-untracked".to_string());
+    }
+    if input.keepIndex {
+        args.push("--keep-index".to_string());
+    }
+    if let Some(message) = input.message {
+        args.push("--message".to_string());
+        args.push(message);
+    }
+    let out = run_git_checked(&args, "git stash push")?;
+    if !out.stdout.starts_with("Saved working directory") {
+        return Err(KernelError::InvalidInput(if out.stdout.is_empty() {
+            "git stash push saved nothing".to_string()
+        } else {
+            out.stdout
+        }));
+    }
+    Ok(GitStashOut {
+        stashRef: "stash@{0}".to_string(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitStashPopIn {
+    repoRoot: String,
+    #[serde(default)]
+    index: Option<u32>,
+    #[serde(default, alias = "reinstate_index")]
+    reinstateIndex: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitStashPopOut {
+    ok: bool,
+    conflicts: Vec<String>,
+}
+
+fn git_stash_pop(input: GitStashPopIn) -> Result<GitStashPopOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut args = git_args(&repo_root, &["stash", "pop"]);
+    if input.reinstateIndex {
+        args.push("--index".to_string());
+    }
+    if let Some(n) = input.index {
+        args.push(format!("stash@{{{n}}}"));
+    }
+    let out = run_git(&args, None)?;
+    if !out.ok {
+        // On conflict git keeps the stash entry and leaves markers in the worktree.
+        let conflicts = conflict_files(&repo_root, &format!("{}\n{}", out.stdout, out.stderr));
+        if !conflicts.is_empty() {
+            return Ok(GitStashPopOut {
+                ok: false,
+                conflicts,
+            });
+        }
+        return Err(git_failed("git stash pop", &out));
+    }
+    Ok(GitStashPopOut {
+        ok: true,
+        conflicts: Vec::new(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitRebaseIn {
+    repoRoot: String,
+    #[serde(default)]
+    upstream: String,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    onto: Option<String>,
+    #[serde(default)]
+    interactive: bool,
+    #[serde(default)]
+    autosquash: bool,
+    #[serde(default)]
+    operation: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitRebaseOut {
+    newHead: String,
+    conflictFiles: Vec<String>,
+    inProgress: bool,
+}
+
+fn git_path_exists(repo_root: &Path, name: &str) -> bool {
+    // Resolves paths inside the (per-worktree) git dir, e.g. `rebase-merge`.
+    match run_git(
+        &git_args(repo_root, &["rev-parse", "--git-path", name]),
+        None,
+    ) {
+        Ok(out) if out.ok => {
+            let p = PathBuf::from(out.stdout.trim());
+            if p.is_absolute() {
+                p
+            } else {
+                repo_root.join(p)
+            }
+            .exists()
+        }
+        _ => false,
+    }
+}
+
+fn rebase_in_progress(repo_root: &Path) -> bool {
+    git_path_exists(repo_root, "rebase-merge") || git_path_exists(repo_root, "rebase-apply")
+}
+
+fn git_rebase(input: GitRebaseIn) -> Result<GitRebaseOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.interactive {
+        return Err(KernelError::InvalidInput(
+            "interactive rebase is not supported (no TTY)".to_string(),
+        ));
+    }
+
+    // Never block on an editor: keep commit messages as-is and accept the autosquash todo list.
+    let mut args = git_args(
+        &repo_root,
+        &[
+            "-c",
+            "core.editor=true",
+            "-c",
+            "sequence.editor=true",
+            "rebase",
+        ],
+    );
+    match input.operation.as_deref() {
+        Some(op @ ("continue" | "abort" | "skip")) => args.push(format!("--{op}")),
+        Some(op) => {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid rebase operation: {op} (expected continue|abort|skip)"
+            )))
+        }
+        None => {
+            if input.upstream.trim().is_empty() {
+                return Err(KernelError::InvalidInput(
+                    "upstream is required".to_string(),
+                ));
+            }
+            reject_option_like("upstream", &input.upstream)?;
+            for (what, value) in [("onto", &input.onto), ("branch", &input.branch)] {
+                if let Some(value) = value {
+                    reject_option_like(what, value)?;
+                }
+            }
+            if input.autosquash {
+                // Older gits only honour --autosquash together with --interactive.
+                args.push("--interactive".to_string());
+                args.push("--autosquash".to_string());
+            }
+            if let Some(onto) = input.onto {
+                args.push("--onto".to_string());
+                args.push(onto);
+            }
+            args.push(input.upstream);
+            if let Some(branch) = input.branch {
+                args.push(branch);
+            }
+        }
+    }
+    let out = run_git(&args, None)?;
+    let in_progress = rebase_in_progress(&repo_root);
+    if !out.ok && !in_progress {
+        return Err(git_failed("git rebase", &out));
+    }
+    let conflict_files = if in_progress {
+        conflict_files(&repo_root, &format!("{}\n{}", out.stdout, out.stderr))
+    } else {
+        Vec::new()
+    };
+
+    let new_head = run_git_checked(
+        &git_args(&repo_root, &["rev-parse", "HEAD"]),
+        "git rev-parse HEAD",
+    )?
+    .stdout
+    .trim()
+    .to_string();
+    Ok(GitRebaseOut {
+        newHead: new_head,
+        conflictFiles: conflict_files,
+        inProgress: in_progress,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitTagIn {
+    repoRoot: String,
+    #[serde(default)]
+    operation: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    target: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    delete: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitTagOut {
+    tags: Vec<String>,
+    sha: Option<String>,
+}
+
+fn git_tag(input: GitTagIn) -> Result<GitTagOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let operation = match (input.operation.as_str(), input.delete) {
+        ("" | "delete", true) => "delete",
+        (op, true) => {
+            return Err(KernelError::InvalidInput(format!(
+                "delete conflicts with operation: {op}"
+            )))
+        }
+        (op, false) => op,
+    };
+    let name = || -> Result<String, KernelError> {
+        let name = input
+            .name
+            .clone()
+            .filter(|n| !n.trim().is_empty())
+            .ok_or_else(|| {
+                KernelError::InvalidInput(format!("name is required for {operation}"))
+            })?;
+        reject_option_like("tag name", &name)?;
+        Ok(name)
+    };
+
+    match operation {
+        "list" => {
+            let out = run_git_checked(
+                &git_args(&repo_root, &["tag", "--list", "--sort=-version:refname"]),
+                "git tag --list",
+            )?;
+            Ok(GitTagOut {
+                tags: out.stdout.lines().map(|l| l.to_string()).collect(),
+                sha: None,
+            })
+        }
+        "create" => {
+            let name = name()?;
+            let mut args = git_args(&repo_root, &["tag"]);
+            if input.force {
+                args.push("--force".to_string());
+            }
+            if let Some(message) = input.message {
+                args.push("--annotate".to_string());
+                args.push("--message".to_string());
+                args.push(message);
+            }
+            args.push(name.clone());
+            if let Some(target) = input.target {
+                reject_option_like("tag target", &target)?;
+                args.push(target);
+            }
+            run_git_checked(&args, "git tag")?;
+            let sha = run_git_checked(
+                &git_args(&repo_root, &["rev-parse", &format!("refs/tags/{name}")]),
+                "git rev-parse",
+            )?;
+            Ok(GitTagOut {
+                tags: vec![name],
+                sha: Some(sha.stdout.trim().to_string()),
+            })
+        }
+        "delete" => {
+            let name = name()?;
+            // Release tags are conventionally `v*`; make removing them an explicit decision.
+            if name.starts_with('v') && !input.force {
+                return Err(KernelError::InvalidInput(format!(
+                    "refusing to delete release tag {name} without force"
+                )));
+            }
+            run_git_checked(
+                &git_args(&repo_root, &["tag", "--delete", &name]),
+                "git tag --delete",
+            )?;
+            Ok(GitTagOut {
+                tags: vec![name],
+                sha: None,
+            })
+        }
+        other => Err(KernelError::InvalidInput(format!(
+            "invalid tag operation: {other} (expected create|list|delete)"
+        ))),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitConfigGetIn {
+    repoRoot: String,
+    key: String,
+    #[serde(default)]
+    global: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitConfigGetOut {
+    value: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitConfigSetIn {
+    repoRoot: String,
+    key: String,
+    value: String,
+    #[serde(default)]
+    global: bool,
+    #[serde(default)]
+    add: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitConfigSetOut {
+    ok: bool,
+}
+
+fn validate_config_key(key: &str) -> Result<(), KernelError> {
+    if key.trim().is_empty() {
+        return Err(KernelError::InvalidInput("config key is empty".to_string()));
+    }
+    if key.contains(['\n', '\r']) {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid config key (contains newline): {key:?}"
+        )));
+    }
+    Ok(())
+}
+
+fn config_scope(global: bool) -> &'static str {
+    if global {
+        "--global"
+    } else {
+        "--local"
+    }
+}
+
+fn git_config_get(input: GitConfigGetIn) -> Result<GitConfigGetOut, KernelError> {
+    run_git_config_get(input, &[])
+}
+
+fn run_git_config_get(
+    input: GitConfigGetIn,
+    env: &[(String, String)],
+) -> Result<GitConfigGetOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    validate_config_key(&input.key)?;
+    let out = run_git_with_env(
+        &git_args(
+            &repo_root,
+            &["config", config_scope(input.global), "--get", &input.key],
+        ),
+        env,
+    )?;
+    // Exit code 1 means the key is not set.
+    if out.status == 1 {
+        return Ok(GitConfigGetOut { value: None });
+    }
+    if !out.ok {
+        return Err(git_failed("git config --get", &out));
+    }
+    Ok(GitConfigGetOut {
+        value: Some(out.stdout.trim_end().to_string()),
+    })
+}
+
+fn git_config_set(input: GitConfigSetIn) -> Result<GitConfigSetOut, KernelError> {
+    run_git_config_set(input, &[])
+}
+
+fn run_git_config_set(
+    input: GitConfigSetIn,
+    env: &[(String, String)],
+) -> Result<GitConfigSetOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    validate_config_key(&input.key)?;
+    let mut args = git_args(&repo_root, &["config", config_scope(input.global)]);
+    if input.add {
+        args.push("--add".to_string());
+    }
+    args.push(input.key);
+    args.push(input.value);
+    let out = run_git_with_env(&args, env)?;
+    if !out.ok {
+        return Err(git_failed("git config", &out));
+    }
+    Ok(GitConfigSetOut { ok: true })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitRevParseIn {
+    repoRoot: String,
+    #[serde(rename = "ref")]
+    ref_: String,
+    #[serde(default)]
+    verify: bool,
+    #[serde(default)]
+    abbreviate: Option<u32>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitRevParseOut {
+    sha: String,
+    objectType: String,
+}
+
+fn git_rev_parse(input: GitRevParseIn) -> Result<GitRevParseOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.ref_.trim().is_empty() {
+        return Err(KernelError::InvalidInput("ref is empty".to_string()));
+    }
+    let mut args = git_args(&repo_root, &["rev-parse"]);
+    if input.verify {
+        args.push("--verify".to_string());
+    }
+    if let Some(n) = input.abbreviate {
+        args.push(format!("--short={n}"));
+    }
+    // `--end-of-options` keeps refs like `-x` from being read as flags.
+    args.push("--end-of-options".to_string());
+    args.push(input.ref_);
+    let out = run_git_checked(&args, "git rev-parse")?;
+    let sha = out.stdout.trim().to_string();
+
+    let out_type = run_git_checked(
+        &git_args(&repo_root, &["cat-file", "-t", &sha]),
+        "git cat-file -t",
+    )?;
+    Ok(GitRevParseOut {
+        sha,
+        objectType: out_type.stdout.trim().to_string(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitLsFilesIn {
+    repoRoot: String,
+    #[serde(default)]
+    worktreePath: Option<String>,
+    #[serde(default)]
+    cached: bool,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    modified: bool,
+    #[serde(default)]
+    others: bool,
+    #[serde(default)]
+    ignored: bool,
+    #[serde(default)]
+    excludePatterns: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct LsFileEntry {
+    path: String,
+    status: Option<char>,
+    sha: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitLsFilesOut {
+    files: Vec<LsFileEntry>,
+}
+
+fn git_ls_files(input: GitLsFilesIn) -> Result<GitLsFilesOut, KernelError> {
+    let dir = PathBuf::from(input.worktreePath.unwrap_or(input.repoRoot));
+
+    // `-t` prefixes each path with a status tag (H cached, R deleted, C modified, ? other, ...).
+    let mut args = git_args(&dir, &["ls-files", "-z", "-t"]);
+    for (on, flag) in [
+        (input.cached, "--cached"),
+        (input.deleted, "--deleted"),
+        (input.modified, "--modified"),
+        (input.others, "--others"),
+    ] {
+        if on {
+            args.push(flag.to_string());
+        }
+    }
+    if input.ignored {
+        if !input.others && !input.cached {
+            args.push("--others".to_string());
+        }
+        args.push("--ignored".to_string());
+    }
+    if input.others || input.ignored {
+        args.push("--exclude-standard".to_string());
+    }
+    for pattern in input.excludePatterns {
+        args.push(format!("--exclude={pattern}"));
+    }
+    let out = run_git_checked(&args, "git ls-files")?;
+
+    // Blob SHAs come from the index, so only tracked entries get one.
+    let staged = run_git_checked(
+        &git_args(&dir, &["ls-files", "-z", "--stage"]),
+        "git ls-files --stage",
+    )?;
+    let shas: BTreeMap<&str, &str> = staged
+        .stdout
+        .split('\0')
+        .filter_map(|rec| {
+            let (meta, path) = rec.split_once('\t')?;
+            Some((path, meta.split(' ').nth(1)?))
+        })
+        .collect();
+
+    let files = out
+        .stdout
+        .split('\0')
+        .filter(|rec| !rec.is_empty())
+        .map(|rec| {
+            let (status, path) = match rec.split_once(' ') {
+                Some((tag, path)) if tag.chars().count() == 1 => (tag.chars().next(), path),
+                _ => (None, rec),
+            };
+            LsFileEntry {
+                path: path.to_string(),
+                status,
+                sha: shas.get(path).map(|s| s.to_string()),
+            }
+        })
+        .collect();
+    Ok(GitLsFilesOut { files })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitCleanIn {
+    repoRoot: String,
+    #[serde(default)]
+    worktreePath: Option<String>,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    directories: bool,
+    #[serde(default)]
+    ignored: bool,
+    #[serde(default)]
+    dryRun: bool,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitCleanOut {
+    removedPaths: Vec<String>,
+}
+
+fn git_clean(input: GitCleanIn) -> Result<GitCleanOut, KernelError> {
+    let dir = PathBuf::from(input.worktreePath.unwrap_or(input.repoRoot));
+    if !input.force && !input.dryRun {
+        return Err(KernelError::InvalidInput(
+            "refusing to clean without force (or use dryRun)".to_string(),
+        ));
+    }
+
+    let mut args = git_args(&dir, &["clean"]);
+    args.push(if input.dryRun { "-n" } else { "-f" }.to_string());
+    if input.directories {
+        args.push("-d".to_string());
+    }
+    if input.ignored {
+        args.push("-x".to_string());
+    }
+    args.push("--".to_string());
+    args.extend(input.paths);
+    let out = run_git_checked(&args, "git clean")?;
+
+    Ok(GitCleanOut {
+        removedPaths: out
+            .stdout
+            .lines()
+            .filter_map(|l| {
+                l.strip_prefix("Would remove ")
+                    .or_else(|| l.strip_prefix("Removing "))
+            })
+            .map(|p| p.to_string())
+            .collect(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitLsRemoteIn {
+    repoRoot: String,
+    remote: String,
+    #[serde(default)]
+    refs: Vec<String>,
+    #[serde(default)]
+    tags: bool,
+    #[serde(default)]
+    heads: bool,
+    #[serde(default)]
+    exitCode: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct RemoteRef {
+    sha: String,
+    refName: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitLsRemoteOut {
+    refs: Vec<RemoteRef>,
+}
+
+fn git_ls_remote(input: GitLsRemoteIn) -> Result<GitLsRemoteOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.remote.trim().is_empty() {
+        return Err(KernelError::InvalidInput("remote is required".to_string()));
+    }
+    reject_option_like("remote", &input.remote)?;
+    for r in &input.refs {
+        reject_option_like("ref", r)?;
+    }
+    let mut args = git_args(&repo_root, &["ls-remote"]);
+    if input.tags {
+        args.push("--tags".to_string());
+    }
+    if input.heads {
+        args.push("--heads".to_string());
+    }
+    if input.exitCode {
+        args.push("--exit-code".to_string());
+    }
+    args.push(input.remote);
+    args.extend(input.refs);
+    let out = run_git(&args, None)?;
+    // With --exit-code, git exits 2 when no refs matched.
+    if out.status == 2 && out.stderr.is_empty() {
+        return Err(KernelError::NotFound(
+            "no matching refs found on remote".to_string(),
+        ));
+    }
+    if !out.ok {
+        return Err(git_failed("git ls-remote", &out));
+    }
+    Ok(GitLsRemoteOut {
+        refs: out
+            .stdout
+            .lines()
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(sha, name)| RemoteRef {
+                sha: sha.to_string(),
+                refName: name.to_string(),
+            })
+            .collect(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitDescribeIn {
+    repoRoot: String,
+    #[serde(default)]
+    commit: Option<String>,
+    #[serde(default)]
+    tags: bool,
+    #[serde(default)]
+    abbrev: Option<u32>,
+    #[serde(default)]
+    longFormat: bool,
+    #[serde(default)]
+    matchPattern: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitDescribeOut {
+    description: String,
+    tag: String,
+    distance: u32,
+    sha: String,
+}
+
+fn parse_describe(description: &str) -> (String, u32) {
+    // `<tag>-<n>-g<sha>`; tags may themselves contain dashes, so split from the right.
+    let mut it = description.rsplitn(3, '-');
+    if let (Some(g), Some(n), Some(tag)) = (it.next(), it.next(), it.next()) {
+        let is_sha = g
+            .strip_prefix('g')
+            .is_some_and(|h| !h.is_empty() && h.chars().all(|c| c.is_ascii_hexdigit()));
+        if let (true, Ok(distance)) = (is_sha, n.parse::<u32>()) {
+            return (tag.to_string(), distance);
+        }
+    }
+    (description.to_string(), 0)
+}
+
+fn git_describe(input: GitDescribeIn) -> Result<GitDescribeOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let commit = input.commit.unwrap_or_else(|| "HEAD".to_string());
+    reject_option_like("commit", &commit)?;
+    if let Some(pattern) = input.matchPattern.as_deref() {
+        reject_option_like("match pattern", pattern)?;
+    }
+
+    let mut args = git_args(&repo_root, &["describe"]);
+    if input.tags {
+        args.push("--tags".to_string());
+    }
+    if let Some(n) = input.abbrev {
+        args.push(format!("--abbrev={n}"));
+    }
+    if input.longFormat {
+        args.push("--long".to_string());
+    }
+    if let Some(pattern) = input.matchPattern {
+        args.push(format!("--match={pattern}"));
+    }
+    args.push(commit.clone());
+    let out = run_git(&args, None)?;
+    if !out.ok {
+        if out.stderr.contains("No names found") || out.stderr.contains("No tags can describe") {
+            return Err(KernelError::NotFound(format!(
+                "no tags found to describe {commit}"
+            )));
+        }
+        return Err(git_failed("git describe", &out));
+    }
+
+    let description = out.stdout.trim().to_string();
+    let (tag, distance) = parse_describe(&description);
+    let sha = run_git_checked(
+        &git_args(&repo_root, &["rev-parse", &format!("{commit}^{{commit}}")]),
+        "git rev-parse",
+    )?;
+    Ok(GitDescribeOut {
+        description,
+        tag,
+        distance,
+        sha: sha.stdout.trim().to_string(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitCheckIgnoreIn {
+    repoRoot: String,
+    paths: Vec<String>,
+    #[serde(default)]
+    verbose: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct IgnoredPath {
+    path: String,
+    pattern: Option<String>,
+    source: Option<String>,
+    lineNumber: Option<u32>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitCheckIgnoreOut {
+    ignoredPaths: Vec<IgnoredPath>,
+}
+
+fn git_check_ignore(input: GitCheckIgnoreIn) -> Result<GitCheckIgnoreOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.paths.is_empty() {
+        return Ok(GitCheckIgnoreOut {
+            ignoredPaths: Vec::new(),
+        });
+    }
+
+    let mut args = git_args(&repo_root, &["check-ignore", "-z", "--stdin"]);
+    if input.verbose {
+        args.push("--verbose".to_string());
+    }
+    let mut stdin = input.paths.join("\0");
+    stdin.push('\0');
+    let out = run_cmd_with_stdin(git_binary(), &args, None, Some(stdin.as_bytes()))?;
+    // Exit code 1 means none of the paths are ignored.
+    if out.status == 1 {
+        return Ok(GitCheckIgnoreOut {
+            ignoredPaths: Vec::new(),
+        });
+    }
+    if !out.ok {
+        return Err(git_failed("git check-ignore", &out));
+    }
+
+    let fields: Vec<&str> = out.stdout.split('\0').collect();
+    let ignored_paths = if input.verbose {
+        // `<source> NUL <linenum> NUL <pattern> NUL <pathname> NUL`
+        fields
+            .chunks_exact(4)
+            .map(|f| IgnoredPath {
+                path: f[3].to_string(),
+                pattern: Some(f[2].to_string()),
+                source: Some(f[0].to_string()),
+                lineNumber: f[1].parse().ok(),
+            })
+            .collect()
+    } else {
+        fields
+            .into_iter()
+            .filter(|p| !p.is_empty())
+            .map(|p| IgnoredPath {
+                path: p.to_string(),
+                pattern: None,
+                source: None,
+                lineNumber: None,
+            })
+            .collect()
+    };
+    Ok(GitCheckIgnoreOut {
+        ignoredPaths: ignored_paths,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitUpdateRefIn {
+    repoRoot: String,
+    #[serde(rename = "ref")]
+    ref_: String,
+    #[serde(default)]
+    newValue: String,
+    #[serde(default)]
+    oldValue: Option<String>,
+    #[serde(default = "default_true")]
+    deref: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitUpdateRefOut {
+    ok: bool,
+    deleted: bool,
+}
+
+const REF_CAS_FAILURE: &str = "REF_CAS_FAILURE";
+
+fn git_update_ref(input: GitUpdateRefIn) -> Result<GitUpdateRefOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.ref_.trim().is_empty() {
+        return Err(KernelError::InvalidInput("ref is empty".to_string()));
+    }
+    reject_option_like("ref", &input.ref_)?;
+    reject_option_like("newValue", &input.newValue)?;
+    if let Some(old) = input.oldValue.as_deref() {
+        reject_option_like("oldValue", old)?;
+    }
+    let delete = input.newValue.is_empty();
+
+    // git derefs symbolic refs by default; `deref: false` updates the symref itself.
+    let mut args = git_args(&repo_root, &["update-ref"]);
+    if !input.deref {
+        args.push("--no-deref".to_string());
+    }
+    if delete {
+        args.push("-d".to_string());
+        args.push(input.ref_.clone());
+    } else {
+        args.push(input.ref_.clone());
+        args.push(input.newValue);
+    }
+    let cas = input.oldValue.is_some();
+    if let Some(old) = input.oldValue {
+        args.push(old);
+    }
+    let out = run_git(&args, None)?;
+    if !out.ok {
+        if cas && out.stderr.contains("but expected") {
+            return Err(KernelError::RefCasFailed {
+                ref_name: input.ref_,
+                exit_code: out.status,
+                stderr: out.stderr,
+            });
+        }
+        return Err(git_failed("git update-ref", &out));
+    }
+    Ok(GitUpdateRefOut {
+        ok: true,
+        deleted: delete,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitMergeIn {
+    repoRoot: String,
+    #[serde(default)]
+    branch: String,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    ffOnly: bool,
+    #[serde(default)]
+    noFf: bool,
+    #[serde(default)]
+    squash: bool,
+    #[serde(default)]
+    operation: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitMergeOut {
+    newHead: Option<String>,
+    conflictFiles: Vec<String>,
+    fastForwarded: bool,
+}
+
+fn git_merge(input: GitMergeIn) -> Result<GitMergeOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.ffOnly && input.noFf {
+        return Err(KernelError::InvalidInput(
+            "ffOnly and noFf are mutually exclusive".to_string(),
+        ));
+    }
+
+    let mut args = git_args(&repo_root, &["-c", "core.editor=true", "merge"]);
+    match input.operation.as_deref() {
+        Some(op @ ("abort" | "continue")) => args.push(format!("--{op}")),
+        Some(op) => {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid merge operation: {op} (expected abort|continue)"
+            )))
+        }
+        None => {
+            if input.branch.trim().is_empty() {
+                return Err(KernelError::InvalidInput("branch is required".to_string()));
+            }
+            reject_option_like("branch", &input.branch)?;
+            if input.ffOnly {
+                args.push("--ff-only".to_string());
+            }
+            if input.noFf {
+                args.push("--no-ff".to_string());
+            }
+            if input.squash {
+                args.push("--squash".to_string());
+            }
+            match input.message {
+                Some(message) => {
+                    args.push("-m".to_string());
+                    args.push(message);
+                }
+                None => args.push("--no-edit".to_string()),
+            }
+            args.push(input.branch);
+        }
+    }
+    let out = run_git(&args, None)?;
+    if out.status == 1 && input.operation.is_none() {
+        let conflicts = conflict_files(&repo_root, &out.stdout);
+        if !conflicts.is_empty() {
+            return Ok(GitMergeOut {
+                newHead: None,
+                conflictFiles: conflicts,
+                fastForwarded: false,
+            });
+        }
+    }
+    if !out.ok {
+        return Err(git_failed("git merge", &out));
+    }
+
+    let head = run_git_checked(
+        &git_args(&repo_root, &["rev-parse", "HEAD"]),
+        "git rev-parse HEAD",
+    )?;
+    Ok(GitMergeOut {
+        newHead: Some(head.stdout.trim().to_string()),
+        conflictFiles: Vec::new(),
+        fastForwarded: out.stdout.lines().any(|l| l.trim() == "Fast-forward"),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitSubmoduleUpdateIn {
+    repoRoot: String,
+    #[serde(default)]
+    init: bool,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    remote: bool,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitSubmoduleUpdateOut {
+    /// Submodule paths git reported as checked out, merged or rebased.
+    updated: Vec<String>,
+}
+
+fn git_submodule_update(input: GitSubmoduleUpdateIn) -> Result<GitSubmoduleUpdateOut, KernelError> {
+    run_git_submodule_update(input, &[])
+}
+
+fn run_git_submodule_update(
+    input: GitSubmoduleUpdateIn,
+    env: &[(String, String)],
+) -> Result<GitSubmoduleUpdateOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut args = git_args(&repo_root, &["submodule", "update"]);
+    if input.init {
+        args.push("--init".to_string());
+    }
+    if input.recursive {
+        args.push("--recursive".to_string());
+    }
+    if input.remote {
+        args.push("--remote".to_string());
+    }
+    if !input.paths.is_empty() {
+        args.push("--".to_string());
+        args.extend(input.paths);
+    }
+    let out = run_git_with_env(&args, env)?;
+    if !out.ok {
+        return Err(git_failed("git submodule update", &out));
+    }
+
+    // `Submodule path 'libs/foo': checked out '<sha>'` (nested paths include the parent).
+    let mut updated: Vec<String> = Vec::new();
+    for line in out.stdout.lines().chain(out.stderr.lines()) {
+        let Some(rest) = line.trim().strip_prefix("Submodule path '") else {
+            continue;
+        };
+        if let Some((path, _)) = rest.split_once("':") {
+            if !updated.iter().any(|p| p == path) {
+                updated.push(path.to_string());
+            }
+        }
+    }
+    Ok(GitSubmoduleUpdateOut { updated })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitSubmoduleListIn {
+    repoRoot: String,
+}
+
+#[derive(Serialize, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+enum SubmoduleStatus {
+    Uninitialized,
+    Initialized,
+    /// Checked-out commit differs from the one recorded in the superproject.
+    Dirty,
+    Merge,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SubmoduleInfo {
+    name: String,
+    path: String,
+    url: String,
+    branch: Option<String>,
+    /// Checked-out commit; `None` until the submodule is initialized.
+    head: Option<String>,
+    status: SubmoduleStatus,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitSubmoduleListOut {
+    submodules: Vec<SubmoduleInfo>,
+}
+
+fn git_submodule_list(input: GitSubmoduleListIn) -> Result<GitSubmoduleListOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if !repo_root.join(".gitmodules").exists() {
+        return Ok(GitSubmoduleListOut {
+            submodules: Vec::new(),
+        });
+    }
+
+    // name -> (path, url, branch) from `.gitmodules`; names may themselves contain dots.
+    let config = run_git(
+        &git_args(
+            &repo_root,
+            &[
+                "config",
+                "-f",
+                ".gitmodules",
+                "--get-regexp",
+                r"^submodule\.",
+            ],
+        ),
+        None,
+    )?;
+    let mut modules: BTreeMap<String, (String, String, Option<String>)> = BTreeMap::new();
+    for line in config.stdout.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let Some((name, attr)) = key
+            .strip_prefix("submodule.")
+            .and_then(|k| k.rsplit_once('.'))
+        else {
+            continue;
+        };
+        let entry = modules.entry(name.to_string()).or_default();
+        match attr {
+            "path" => entry.0 = value.to_string(),
+            "url" => entry.1 = value.to_string(),
+            "branch" => entry.2 = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    // Untrimmed: the first line's status flag may be a space.
+    let status = run_cmd_untrimmed(
+        git_binary(),
+        &git_args(&repo_root, &["submodule", "status"]),
+        None,
+    )?;
+    if !status.ok {
+        return Err(git_failed("git submodule status", &status));
+    }
+    let mut submodules = Vec::new();
+    // `<flag><sha> <path>[ (<describe>)]`
+    for line in status.stdout.lines() {
+        let mut chars = line.chars();
+        let flag = chars.next().unwrap_or(' ');
+        let rest = chars.as_str();
+        let (sha, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        let path = rest.split(" (").next().unwrap_or(rest).to_string();
+        let status = match flag {
+            '-' => SubmoduleStatus::Uninitialized,
+            '+' => SubmoduleStatus::Dirty,
+            'U' => SubmoduleStatus::Merge,
+            _ => SubmoduleStatus::Initialized,
+        };
+        let (name, url, branch) = modules
+            .iter()
+            .find(|(_, (p, _, _))| *p == path)
+            .map(|(n, (_, u, b))| (n.clone(), u.clone(), b.clone()))
+            .unwrap_or_else(|| (path.clone(), String::new(), None));
+        submodules.push(SubmoduleInfo {
+            name,
+            head: (status != SubmoduleStatus::Uninitialized).then(|| sha.to_string()),
+            path,
+            url,
+            branch,
+            status,
+        });
+    }
+    Ok(GitSubmoduleListOut { submodules })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitFormatPatchIn {
+    repoRoot: String,
+    /// Revision range for `git format-patch`, e.g. `main..feature` or `HEAD~3`.
+    range: String,
+    /// Relative paths resolve against `repoRoot`.
+    outputDir: String,
+    #[serde(default)]
+    numberLines: bool,
+    #[serde(default)]
+    coverLetter: bool,
+    #[serde(default)]
+    thread: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitFormatPatchOut {
+    patchFiles: Vec<String>,
+}
+
+fn git_format_patch(input: GitFormatPatchIn) -> Result<GitFormatPatchOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.range.trim().is_empty() || input.range.starts_with('-') {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid range: {:?}",
+            input.range
+        )));
+    }
+    let output_dir = repo_root.join(&input.outputDir);
+    fs::create_dir_all(&output_dir)
+        .map_err(io_err(format!("failed to create {}", output_dir.display())))?;
+
+    let mut args = git_args(&repo_root, &["format-patch", "-o"]);
+    args.push(output_dir.display().to_string());
+    if input.numberLines {
+        args.push("--numbered".to_string());
+    }
+    if input.coverLetter {
+        args.push("--cover-letter".to_string());
+    }
+    if input.thread {
+        args.push("--thread".to_string());
+    }
+    args.push(input.range);
+    let out = run_git_checked(&args, "git format-patch")?;
+
+    Ok(GitFormatPatchOut {
+        patchFiles: out
+            .stdout
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitAmIn {
+    repoRoot: String,
+    #[serde(default)]
+    patchPaths: Vec<String>,
+    #[serde(default)]
+    threeWay: bool,
+    #[serde(default)]
+    signoff: bool,
+    #[serde(default)]
+    keepSubject: bool,
+    /// Abandon the in-progress session; every other field is ignored.
+    #[serde(default)]
+    abort: bool,
+    #[serde(default, rename = "continue")]
+    continue_: bool,
+    #[serde(default)]
+    skip: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitAmOut {
+    /// Commits created by this call, oldest first.
+    appliedCommits: Vec<String>,
+    inProgress: bool,
+    conflictFiles: Vec<String>,
+}
+
+fn git_am(input: GitAmIn) -> Result<GitAmOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let head = || -> Option<String> {
+        run_git(
+            &git_args(&repo_root, &["rev-parse", "--verify", "--quiet", "HEAD"]),
+            None,
+        )
+        .ok()
+        .filter(|out| out.ok)
+        .map(|out| out.stdout.trim().to_string())
+    };
+
+    let mut args = git_args(&repo_root, &["-c", "core.editor=true", "am"]);
+    if input.abort {
+        args.push("--abort".to_string());
+    } else {
+        if [input.continue_, input.skip, !input.patchPaths.is_empty()]
+            .iter()
+            .filter(|b| **b)
+            .count()
+            != 1
+        {
+            return Err(KernelError::InvalidInput(
+                "expected exactly one of patchPaths, continue or skip".to_string(),
+            ));
+        }
+        if input.threeWay {
+            args.push("--3way".to_string());
+        }
+        if input.signoff {
+            args.push("--signoff".to_string());
+        }
+        if input.keepSubject {
+            args.push("--keep".to_string());
+        }
+        if input.continue_ {
+            args.push("--continue".to_string());
+        } else if input.skip {
+            args.push("--skip".to_string());
+        } else {
+            args.push("--".to_string());
+            args.extend(input.patchPaths);
+        }
+    }
+
+    let before = head();
+    let out = run_git(&args, None)?;
+    let in_progress = git_path_exists(&repo_root, "rebase-apply");
+    if !out.ok && !in_progress {
+        return Err(git_failed("git am", &out));
+    }
+    let after = head();
+
+    let applied_commits = match (&before, &after) {
+        (_, None) => Vec::new(),
+        (before, Some(after)) if !input.abort && before.as_ref() != Some(after) => {
+            let range = match before {
+                Some(before) => format!("{before}..{after}"),
+                None => after.clone(),
+            };
+            run_git_checked(
+                &git_args(&repo_root, &["rev-list", "--reverse", &range]),
+                "git rev-list",
+            )?
+            .stdout
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    let mut conflict_files = if in_progress {
+        conflict_files(&repo_root, &out.stdout)
+    } else {
+        Vec::new()
+    };
+    if in_progress && conflict_files.is_empty() {
+        // Without --3way a failed hunk leaves no unmerged entries, only `error: patch failed: f:N`.
+        conflict_files = out
+            .stderr
+            .lines()
+            .filter_map(|l| l.strip_prefix("error: patch failed: "))
+            .filter_map(|l| l.rsplit_once(':').map(|(f, _)| f.to_string()))
+            .collect();
+    }
+    Ok(GitAmOut {
+        appliedCommits: applied_commits,
+        inProgress: in_progress,
+        conflictFiles: conflict_files,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitRemoteListIn {
+    repoRoot: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct RemoteInfo {
+    name: String,
+    fetchUrl: String,
+    pushUrl: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitRemoteListOut {
+    remotes: Vec<RemoteInfo>,
+}
+
+fn git_remote_list(input: GitRemoteListIn) -> Result<GitRemoteListOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let out = run_git_checked(&git_args(&repo_root, &["remote", "-v"]), "git remote -v")?;
+
+    // `<name>\t<url> (fetch)` and `<name>\t<url> (push)`, in remote order.
+    let mut remotes: Vec<RemoteInfo> = Vec::new();
+    for line in out.stdout.lines() {
+        let Some((name, rest)) = line.split_once('\t') else {
+            continue;
+        };
+        let Some((url, kind)) = rest.rsplit_once(' ') else {
+            continue;
+        };
+        let idx = match remotes.iter().position(|r| r.name == name) {
+            Some(i) => i,
+            None => {
+                remotes.push(RemoteInfo {
+                    name: name.to_string(),
+                    fetchUrl: String::new(),
+                    pushUrl: String::new(),
+                });
+                remotes.len() - 1
+            }
+        };
+        match kind {
+            "(fetch)" => remotes[idx].fetchUrl = url.to_string(),
+            "(push)" => remotes[idx].pushUrl = url.to_string(),
+            _ => {}
+        }
+    }
+    Ok(GitRemoteListOut { remotes })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitRemoteAddIn {
+    repoRoot: String,
+    name: String,
+    url: String,
+    /// Fetch the new remote right away (`--fetch`).
+    #[serde(default)]
+    fetch: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitRemoteAddOut {
+    ok: bool,
+}
+
+fn git_remote_add(input: GitRemoteAddIn) -> Result<GitRemoteAddOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if !is_valid_ref_name(&input.name) || input.name.contains('/') {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid remote name: {}",
+            input.name
+        )));
+    }
+    // git is never run through a shell, but the URL may be handed to ssh or a helper later.
+    if input.url.is_empty()
+        || input.url.starts_with('-')
+        || input.url.chars().any(|c| {
+            c.is_control()
+                || c.is_whitespace()
+                || matches!(
+                    c,
+                    ';' | '|' | '&' | '$' | '`' | '<' | '>' | '(' | ')' | '\'' | '"' | '\\'
+                )
+        })
+    {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid remote url: {}",
+            input.url
+        )));
+    }
+
+    let mut args = git_args(&repo_root, &["remote", "add"]);
+    if input.fetch {
+        args.push("--fetch".to_string());
+    }
+    args.push(input.name);
+    args.push(input.url);
+    run_git_checked(&args, "git remote add")?;
+    Ok(GitRemoteAddOut { ok: true })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitReflogIn {
+    repoRoot: String,
+    /// Defaults to `HEAD`.
+    #[serde(default, rename = "ref")]
+    ref_: Option<String>,
+    #[serde(default)]
+    maxCount: Option<u32>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ReflogEntry {
+    sha: String,
+    /// Value of the ref before this entry; empty for the oldest entry in the log.
+    previousSha: String,
+    author: String,
+    date: String,
+    subject: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitReflogOut {
+    entries: Vec<ReflogEntry>,
+}
+
+// Same NUL/record-separator layout as LOG_FORMAT. `%gd` renders as `<ref>@{<date>}` under `--date`.
+const REFLOG_FORMAT: &str = "format:%H%x00%gn%x00%gd%x00%gs%x1e";
+
+fn git_reflog(input: GitReflogIn) -> Result<GitReflogOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if input.maxCount == Some(0) {
+        return Err(KernelError::InvalidInput(
+            "maxCount must be greater than 0".to_string(),
+        ));
+    }
+    let ref_ = match input.ref_.as_deref().map(str::trim) {
+        None | Some("") => "HEAD".to_string(),
+        Some(r) if r.starts_with('-') => {
+            return Err(KernelError::InvalidInput(format!("invalid ref: {r}")));
+        }
+        Some(r) => r.to_string(),
+    };
+
+    let mut args = git_args(
+        &repo_root,
+        &[
+            "reflog",
+            "show",
+            "--date=iso-strict",
+            &format!("--format={REFLOG_FORMAT}"),
+        ],
+    );
+    // One extra entry so the last returned entry still knows its previous value.
+    if let Some(n) = input.maxCount {
+        args.push(format!("--max-count={}", n.saturating_add(1)));
+    }
+    args.push(ref_);
+    args.push("--".to_string());
+    let out = run_git_checked(&args, "git reflog show")?;
+
+    let records: Vec<Vec<&str>> = out
+        .stdout
+        .split('\x1e')
+        .map(|r| r.trim_start_matches('\n'))
+        .filter(|r| !r.is_empty())
+        .map(|r| r.split('\0').collect::<Vec<_>>())
+        .filter(|f| f.len() >= 4)
+        .collect();
+    let mut entries: Vec<ReflogEntry> = records
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let date = f[2]
+                .split_once("@{")
+                .map(|(_, d)| d.trim_end_matches('}'))
+                .unwrap_or(f[2]);
+            ReflogEntry {
+                sha: f[0].to_string(),
+                previousSha: records
+                    .get(i + 1)
+                    .map(|n| n[0].to_string())
+                    .unwrap_or_default(),
+                author: f[1].to_string(),
+                date: date.to_string(),
+                subject: f[3].to_string(),
+            }
+        })
+        .collect();
+    if let Some(n) = input.maxCount {
+        entries.truncate(n as usize);
+    }
+    Ok(GitReflogOut { entries })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitArchiveIn {
+    repoRoot: String,
+    treeish: String,
+    /// `tar`, `tar.gz` or `zip`.
+    format: String,
+    outputPath: String,
+    /// Top-level directory inside the archive (`--prefix=<prefix>/`).
+    #[serde(default)]
+    prefix: Option<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitArchiveOut {
+    outputPath: String,
+    size: u64,
+}
+
+fn git_archive(input: GitArchiveIn) -> Result<GitArchiveOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    if !matches!(input.format.as_str(), "tar" | "tar.gz" | "zip") {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid archive format: {} (expected tar|tar.gz|zip)",
+            input.format
+        )));
+    }
+    let treeish = input.treeish.trim();
+    if treeish.is_empty() || treeish.starts_with('-') {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid treeish: {}",
+            input.treeish
+        )));
+    }
+    let output_path = validate_fs_path(&input.outputPath)?;
+
+    let mut args = git_args(
+        &repo_root,
+        &["archive", &format!("--format={}", input.format)],
+    );
+    if let Some(prefix) = input.prefix.as_deref().map(|p| p.trim_matches('/')) {
+        if prefix.split('/').any(|c| c == "..") {
+            return Err(KernelError::PathTraversal(format!(
+                "invalid prefix: {prefix}"
+            )));
+        }
+        if !prefix.is_empty() {
+            args.push(format!("--prefix={prefix}/"));
+        }
+    }
+    args.push(treeish.to_string());
+    args.push("--".to_string());
+    args.extend(input.paths);
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(io_err(format!("failed to create {}", parent.display())))?;
+    }
+    let file = File::create(&output_path).map_err(io_err(format!(
+        "failed to create output file {}",
+        output_path.display()
+    )))?;
+
+    // The archive is streamed straight into the file; only stderr is captured.
+    let timeout = command_timeout();
+    let mut cmd = Command::new(git_binary());
+    cmd.args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(file))
+        .stderr(Stdio::piped());
+    if timeout.is_some() {
+        isolate_process_group(&mut cmd);
+    }
+    let mut child = cmd.spawn().map_err(io_err("git archive failed"))?;
+    let mut stderr_pipe = child.stderr.take();
+    let stderr = std::thread::spawn(move || {
+        let mut buf = String::new();
+        if let Some(p) = stderr_pipe.as_mut() {
+            let _ = p.read_to_string(&mut buf);
+        }
+        buf
+    });
+    let waited =
+        wait_with_timeout(&mut child, timeout, None).map_err(io_err("git archive failed"))?;
+    let stderr = stderr.join().unwrap_or_default();
+
+    let status = match waited {
+        Waited::Exited(status) => status,
+        _ => {
+            let _ = fs::remove_file(&output_path);
+            return Err(KernelError::Timeout {
+                command: "git archive".to_string(),
+                timeout_secs: timeout.map(|t| t.as_secs()).unwrap_or_default(),
+            });
+        }
+    };
+    if !status.success() {
+        let _ = fs::remove_file(&output_path);
+        return Err(git_failed(
+            "git archive",
+            &CmdOut {
+                ok: false,
+                status: status.code().unwrap_or(1),
+                stdout: String::new(),
+                stderr: stderr.trim_end().to_string(),
+            },
+        ));
+    }
+
+    let size = fs::metadata(&output_path)
+        .map_err(io_err(format!("failed to stat {}", output_path.display())))?
+        .len();
+    Ok(GitArchiveOut {
+        outputPath: output_path.display().to_string(),
+        size,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitCountObjectsIn {
+    repoRoot: String,
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Sizes are in KiB, as reported by `git count-objects`.
+#[derive(Serialize, Default, JsonSchema)]
+struct GitCountObjectsOut {
+    count: u64,
+    size: u64,
+    inPack: u64,
+    packs: u64,
+    sizePack: u64,
+    prunePackable: u64,
+    garbage: u64,
+    sizeGarbage: u64,
+}
+
+fn git_count_objects(input: GitCountObjectsIn) -> Result<GitCountObjectsOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut out = GitCountObjectsOut::default();
+    if !input.verbose {
+        // `<count> objects, <size> kilobytes`
+        let res = run_git_checked(
+            &git_args(&repo_root, &["count-objects"]),
+            "git count-objects",
+        )?;
+        let mut words = res.stdout.split_whitespace();
+        out.count = words
+            .next()
+            .and_then(|w| w.parse().ok())
+            .unwrap_or_default();
+        out.size = words
+            .nth(1)
+            .and_then(|w| w.parse().ok())
+            .unwrap_or_default();
+        return Ok(out);
+    }
+
+    let res = run_git_checked(
+        &git_args(&repo_root, &["count-objects", "-v"]),
+        "git count-objects -v",
+    )?;
+    for line in res.stdout.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().parse().unwrap_or_default();
+        match key.trim() {
+            "count" => out.count = value,
+            "size" => out.size = value,
+            "in-pack" => out.inPack = value,
+            "packs" => out.packs = value,
+            "size-pack" => out.sizePack = value,
+            "prune-packable" => out.prunePackable = value,
+            "garbage" => out.garbage = value,
+            "size-garbage" => out.sizeGarbage = value,
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitGcIn {
+    repoRoot: String,
+    #[serde(default)]
+    auto: bool,
+    #[serde(default)]
+    aggressive: bool,
+    /// Passed as `--prune=<date>`, e.g. `now` or `2.weeks.ago`.
+    #[serde(default)]
+    prune: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitGcOut {
+    ok: bool,
+    stdout: String,
+}
+
+/// `git gc` can run for minutes on large repos, so it gets its own timeout unless
+/// `--timeout-secs` sets one.
+const GIT_GC_DEFAULT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
+fn git_gc(input: GitGcIn) -> Result<GitGcOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let mut args = git_args(&repo_root, &["gc", "--quiet"]);
+    if input.auto {
+        args.push("--auto".to_string());
+    }
+    if input.aggressive {
+        args.push("--aggressive".to_string());
+    }
+    if let Some(prune) = input.prune.as_deref().map(str::trim) {
+        if prune.is_empty() || prune.starts_with('-') {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid prune date: {prune}"
+            )));
+        }
+        args.push(format!("--prune={prune}"));
+    }
+
+    let timeout = command_timeout().unwrap_or(GIT_GC_DEFAULT_TIMEOUT);
+    let out = run_cmd_with_timeout(git_binary(), &args, None, None, &[], Some(timeout))?;
+    if !out.ok {
+        return Err(git_failed("git gc", &out));
+    }
+    Ok(GitGcOut {
+        ok: true,
+        stdout: out.stdout.trim_end().to_string(),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitInitIn {
+    path: String,
+    #[serde(default)]
+    bare: bool,
+    #[serde(default)]
+    initialBranch: Option<String>,
+    /// Template directory (`--template=<path>`).
+    #[serde(default)]
+    template: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitInitOut {
+    path: String,
+    bare: bool,
+}
+
+fn git_init(input: GitInitIn) -> Result<GitInitOut, KernelError> {
+    let path = validate_fs_path(&input.path)?;
+    let mut args = git_args(&path, &["init", "--quiet"]);
+    if input.bare {
+        args.push("--bare".to_string());
+    }
+    if let Some(branch) = input.initialBranch.as_deref() {
+        if !is_valid_ref_name(branch) {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid branch name: {branch}"
+            )));
+        }
+        args.push(format!("--initial-branch={branch}"));
+    }
+    if let Some(template) = input.template.as_deref() {
+        args.push(format!(
+            "--template={}",
+            validate_fs_path(template)?.display()
+        ));
+    }
+
+    fs::create_dir_all(&path).map_err(io_err(format!("failed to create {}", path.display())))?;
+    run_git_checked(&args, "git init")?;
+    Ok(GitInitOut {
+        path: path.display().to_string(),
+        bare: input.bare,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitBisectIn {
+    repoRoot: String,
+    /// `start`, `good`, `bad`, `skip`, `reset` or `log`.
+    operation: String,
+    /// Revision for `start` (the bad commit), `good`, `bad`, `skip` or `reset`.
+    #[serde(default)]
+    commit: Option<String>,
+}
+
+#[derive(Serialize, Default, JsonSchema)]
+struct GitBisectOut {
+    /// Commit checked out for the next test.
+    currentCommit: Option<String>,
+    stepsRemaining: Option<u32>,
+    done: bool,
+    firstBadCommit: Option<String>,
+}
+
+/// Reads `Bisecting: ... (roughly N steps)` / `[<sha>] <subject>` and `<sha> is the first bad commit`.
+fn parse_bisect_output(text: &str) -> GitBisectOut {
+    let mut out = GitBisectOut::default();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(sha) = line.strip_suffix(" is the first bad commit") {
+            out.done = true;
+            out.firstBadCommit = Some(sha.to_string());
+        } else if let Some(sha) = line.strip_prefix("# first bad commit: [") {
+            // `git bisect log`
+            out.done = true;
+            out.firstBadCommit = sha.split(']').next().map(str::to_string);
+        } else if line.starts_with("Bisecting:") {
+            out.stepsRemaining = line
+                .split_once("(roughly ")
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .and_then(|n| n.parse().ok());
+        } else if out.stepsRemaining.is_some() && out.currentCommit.is_none() {
+            out.currentCommit = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.split(']').next())
+                .map(str::to_string);
+        }
+    }
+    out
+}
+
+fn git_bisect(input: GitBisectIn) -> Result<GitBisectOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let op = input.operation.as_str();
+    if !matches!(op, "start" | "good" | "bad" | "skip" | "reset" | "log") {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid bisect operation: {op} (expected start|good|bad|skip|reset|log)"
+        )));
+    }
+    let mut args = git_args(&repo_root, &["bisect", op]);
+    if let Some(commit) = input.commit.as_deref() {
+        if op == "log" || commit.trim().is_empty() || commit.starts_with('-') {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid bisect commit: {commit}"
+            )));
+        }
+        args.push(commit.to_string());
+    }
+    let out = run_git_checked(&args, &format!("git bisect {op}"))?;
+    Ok(parse_bisect_output(&out.stdout))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitSymbolicRefIn {
+    repoRoot: String,
+    /// Symbolic ref to read or update, e.g. `HEAD`.
+    name: String,
+    /// New target, e.g. `refs/heads/main`.
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    delete: bool,
+    /// Read the target without the `refs/heads/` prefix (`--short`).
+    #[serde(default)]
+    short: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitSymbolicRefOut {
+    /// Current (or newly set) target; `None` after a delete or when `name` is not symbolic.
+    value: Option<String>,
+}
+
+fn git_symbolic_ref(input: GitSymbolicRefIn) -> Result<GitSymbolicRefOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let name = input.name.as_str();
+    if name.starts_with('-') || !is_valid_ref_name(name) {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid ref name: {name}"
+        )));
+    }
+
+    if input.delete {
+        if input.value.is_some() {
+            return Err(KernelError::InvalidInput(
+                "delete and value are mutually exclusive".to_string(),
+            ));
+        }
+        run_git_checked(
+            &git_args(&repo_root, &["symbolic-ref", "--delete", name]),
+            "git symbolic-ref --delete",
+        )?;
+        return Ok(GitSymbolicRefOut { value: None });
+    }
+    if let Some(value) = input.value {
+        if !value.starts_with("refs/") || !is_valid_ref_name(&value) {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid symbolic ref target: {value} (expected refs/...)"
+            )));
+        }
+        run_git_checked(
+            &git_args(&repo_root, &["symbolic-ref", name, &value]),
+            "git symbolic-ref",
+        )?;
+        let value = if input.short {
+            value
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&value)
+                .to_string()
+        } else {
+            value
+        };
+        return Ok(GitSymbolicRefOut { value: Some(value) });
+    }
+
+    // `--quiet` makes a non-symbolic ref (e.g. a detached HEAD) exit 1 without a message.
+    let mut args = git_args(&repo_root, &["symbolic-ref", "--quiet"]);
+    if input.short {
+        args.push("--short".to_string());
+    }
+    args.push(name.to_string());
+    let out = run_git(&args, None)?;
+    if out.status == 1 && out.stderr.is_empty() {
+        return Ok(GitSymbolicRefOut { value: None });
+    }
+    if !out.ok {
+        return Err(git_failed("git symbolic-ref", &out));
+    }
+    Ok(GitSymbolicRefOut {
+        value: Some(out.stdout),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitNotesIn {
+    repoRoot: String,
+    /// `add`, `append`, `show`, `remove` or `list`.
+    operation: String,
+    /// Annotated object; defaults to `HEAD` (for `list`, all annotated objects).
+    #[serde(default)]
+    commit: Option<String>,
+    /// Note text for `add` and `append`.
+    #[serde(default)]
+    message: Option<String>,
+    /// Notes ref (`--ref=<ref>`), e.g. `ci` for `refs/notes/ci`.
+    #[serde(default, rename = "ref")]
+    ref_: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct NoteEntry {
+    notesSha: String,
+    objectSha: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitNotesOut {
+    /// Note text after `add`/`append`/`show`; `None` when the object has no note.
+    note: Option<String>,
+    entries: Vec<NoteEntry>,
+}
+
+fn git_notes(input: GitNotesIn) -> Result<GitNotesOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let op = input.operation.as_str();
+    if !matches!(op, "add" | "append" | "show" | "remove" | "list") {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid notes operation: {op} (expected add|append|show|remove|list)"
+        )));
+    }
+    let mut args = git_args(&repo_root, &["notes"]);
+    if let Some(notes_ref) = input.ref_.as_deref() {
+        if notes_ref.starts_with('-') || !is_valid_ref_name(notes_ref) {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid notes ref: {notes_ref}"
+            )));
+        }
+        args.push(format!("--ref={notes_ref}"));
+    }
+    if let Some(commit) = input.commit.as_deref() {
+        if commit.trim().is_empty() || commit.starts_with('-') {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid commit: {commit}"
+            )));
+        }
+    }
+    let commit = || input.commit.clone().unwrap_or_else(|| "HEAD".to_string());
+    let show = |args: &[String]| -> Result<Option<String>, KernelError> {
+        let mut show_args = args.to_vec();
+        show_args.push("show".to_string());
+        show_args.push(commit());
+        let out = run_git(&show_args, None)?;
+        if out.ok {
+            Ok(Some(out.stdout))
+        } else if out.stderr.contains("no note found") {
+            Ok(None)
+        } else {
+            Err(git_failed("git notes show", &out))
+        }
+    };
+
+    match op {
+        "add" | "append" => {
+            let Some(message) = input.message.as_deref() else {
+                return Err(KernelError::InvalidInput(format!(
+                    "message is required for {op}"
+                )));
+            };
+            let mut write_args = args.clone();
+            write_args.extend([
+                op.to_string(),
+                "-m".to_string(),
+                message.to_string(),
+                commit(),
+            ]);
+            run_git_checked(&write_args, &format!("git notes {op}"))?;
+            Ok(GitNotesOut {
+                note: show(&args)?,
+                entries: Vec::new(),
+            })
+        }
+        "show" => Ok(GitNotesOut {
+            note: show(&args)?,
+            entries: Vec::new(),
+        }),
+        "remove" => {
+            args.extend(["remove".to_string(), commit()]);
+            run_git_checked(&args, "git notes remove")?;
+            Ok(GitNotesOut {
+                note: None,
+                entries: Vec::new(),
+            })
+        }
+        _ => {
+            args.push("list".to_string());
+            args.extend(input.commit.clone());
+            let out = run_git(&args, None)?;
+            // Listing a single object without a note exits 1 with a message.
+            let no_note = input.commit.is_some() && out.stderr.contains("no note found");
+            if !out.ok && !no_note {
+                return Err(git_failed("git notes list", &out));
+            }
+            // `<notes blob sha> <annotated object sha>` per line; a single object lists just the blob.
+            let entries = out
+                .stdout
+                .lines()
+                .filter_map(|line| {
+                    let mut parts = line.split_whitespace();
+                    let notes_sha = parts.next()?.to_string();
+                    let object_sha = parts
+                        .next()
+                        .map(str::to_string)
+                        .or_else(|| input.commit.clone())?;
+                    Some(NoteEntry {
+                        notesSha: notes_sha,
+                        objectSha: object_sha,
+                    })
+                })
+                .collect();
+            Ok(GitNotesOut {
+                note: None,
+                entries,
+            })
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitBundleIn {
+    repoRoot: String,
+    /// `create`, `verify` or `list_heads`.
+    operation: String,
+    bundlePath: String,
+    /// Refs or ranges to include (`create` only).
+    #[serde(default)]
+    refs: Vec<String>,
+    /// Commits the receiver must already have; passed as `^<sha>` (`create` only).
+    #[serde(default)]
+    prerequisites: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct GitBundleOut {
+    /// False when `verify` finds the repository lacks a prerequisite.
+    ok: bool,
+    /// Ref names contained in the bundle.
+    heads: Vec<String>,
+    /// Prerequisite commits (for a failed `verify`, the missing ones).
+    prerequisites: Vec<String>,
+}
+
+/// Parses `git bundle verify` / `list-heads` output: `<sha> <ref>` head lines, prerequisite
+/// SHAs under `The bundle requires ...`, and `error: <sha>` lines listing missing prerequisites.
+fn parse_bundle_output(text: &str) -> (Vec<String>, Vec<String>) {
+    let (mut heads, mut prerequisites) = (Vec::new(), Vec::new());
+    let mut in_requires = false;
+    for line in text.lines() {
+        if line.starts_with("The bundle ") {
+            in_requires = line.starts_with("The bundle requires ");
+            continue;
+        }
+        if line.contains("lacks these prerequisite commits") {
+            in_requires = true;
+            continue;
+        }
+        let line = line.strip_prefix("error: ").unwrap_or(line).trim();
+        let mut parts = line.splitn(2, ' ');
+        let (Some(sha), name) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        if sha.len() < 40 || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        if in_requires {
+            prerequisites.push(sha.to_string());
+        } else if let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) {
+            heads.push(name.to_string());
+        }
+    }
+    (heads, prerequisites)
+}
+
+fn git_bundle(input: GitBundleIn) -> Result<GitBundleOut, KernelError> {
+    let repo_root = PathBuf::from(input.repoRoot);
+    let bundle_path = validate_fs_path(&input.bundlePath)?;
+    let bundle = bundle_path.display().to_string();
+    let list_heads = || -> Result<Vec<String>, KernelError> {
+        let out = run_git_checked(
+            &git_args(&repo_root, &["bundle", "list-heads", &bundle]),
+            "git bundle list-heads",
+        )?;
+        Ok(parse_bundle_output(&out.stdout).0)
+    };
+
+    match input.operation.as_str() {
+        "create" => {
+            if input.refs.is_empty() {
+                return Err(KernelError::InvalidInput(
+                    "refs is required for create".to_string(),
+                ));
+            }
+            if let Some(bad) = input
+                .refs
+                .iter()
+                .chain(&input.prerequisites)
+                .find(|r| r.trim().is_empty() || r.starts_with('-') || r.starts_with('^'))
+            {
+                return Err(KernelError::InvalidInput(format!(
+                    "invalid bundle ref: {bad}"
+                )));
+            }
+            let mut args = git_args(&repo_root, &["bundle", "create", "--quiet", &bundle]);
+            args.extend(input.refs);
+            args.extend(input.prerequisites.iter().map(|p| format!("^{p}")));
+            run_git_checked(&args, "git bundle create")?;
+            Ok(GitBundleOut {
+                ok: true,
+                heads: list_heads()?,
+                prerequisites: input.prerequisites,
+            })
+        }
+        "verify" | "list_heads" if !bundle_path.is_file() => Err(KernelError::NotFound(format!(
+            "bundle not found: {}",
+            bundle_path.display()
+        ))),
+        "verify" => {
+            let out = run_git(&git_args(&repo_root, &["bundle", "verify", &bundle]), None)?;
+            if !out.ok && !out.stderr.contains("lacks these prerequisite commits") {
+                return Err(git_failed("git bundle verify", &out));
+            }
+            let (heads, prerequisites) =
+                parse_bundle_output(&format!("{}\n{}", out.stdout, out.stderr));
+            Ok(GitBundleOut {
+                ok: out.ok,
+                heads: if out.ok { heads } else { list_heads()? },
+                prerequisites,
+            })
+        }
+        "list_heads" => Ok(GitBundleOut {
+            ok: true,
+            heads: list_heads()?,
+            prerequisites: Vec::new(),
+        }),
+        other => Err(KernelError::InvalidInput(format!(
+            "invalid bundle operation: {other} (expected create|verify|list_heads)"
+        ))),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GitCredentialIn {
+    /// Repository whose config selects the credential helper; the global config when absent.
+    #[serde(default)]
+    repoRoot: Option<String>,
+    /// `fill`, `approve` or `reject`.
+    operation: String,
+    protocol: String,
+    host: String,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+/// `password` only ever travels through the helper's stdin/stdout; it is never placed in
+/// arguments or error messages.
+#[derive(Serialize, JsonSchema)]
+struct GitCredentialOut {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+fn git_credential(input: GitCredentialIn) -> Result<GitCredentialOut, KernelError> {
+    run_git_credential(input, &[])
+}
+
+fn run_git_credential(
+    input: GitCredentialIn,
+    env: &[(String, String)],
+) -> Result<GitCredentialOut, KernelError> {
+    let op = input.operation.as_str();
+    if !matches!(op, "fill" | "approve" | "reject") {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid credential operation: {op} (expected fill|approve|reject)"
+        )));
+    }
+    if input.protocol.trim().is_empty() || input.host.trim().is_empty() {
+        return Err(KernelError::InvalidInput(
+            "protocol and host are required".to_string(),
+        ));
+    }
+    // A newline would let a value inject extra `key=value` attributes.
+    let fields = [
+        ("protocol", Some(&input.protocol)),
+        ("host", Some(&input.host)),
+        ("username", input.username.as_ref()),
+        ("password", input.password.as_ref()),
+    ];
+    let mut description = String::new();
+    for (key, value) in fields {
+        let Some(value) = value else {
+            continue;
+        };
+        if value.contains(['\n', '\0']) {
+            return Err(KernelError::InvalidInput(format!(
+                "{key} must not contain newlines"
+            )));
+        }
+        description.push_str(&format!("{key}={value}\n"));
+    }
+    description.push('\n');
+
+    let args = match input.repoRoot.as_deref() {
+        Some(repo_root) => git_args(Path::new(repo_root), &["credential", op]),
+        None => vec!["credential".to_string(), op.to_string()],
+    };
+    // `fill` must never fall back to an interactive terminal prompt.
+    let mut env = env.to_vec();
+    env.push(("GIT_TERMINAL_PROMPT".to_string(), "0".to_string()));
+    let out = run_cmd_with_timeout(
+        git_binary(),
+        &args,
+        None,
+        Some(description.as_bytes()),
+        &env,
+        command_timeout(),
+    )?;
+    if !out.ok {
+        return Err(git_failed(&format!("git credential {op}"), &out));
+    }
+
+    let (mut username, mut password) = (None, None);
+    for line in out.stdout.lines() {
+        match line.split_once('=') {
+            Some(("username", v)) => username = Some(v.to_string()),
+            Some(("password", v)) => password = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    Ok(GitCredentialOut { username, password })
+}
+
+/// Paths handed to `fs.*` commands must be absolute and free of `..` components.
+fn validate_fs_path(path: &str) -> Result<PathBuf, KernelError> {
+    let p = PathBuf::from(path);
+    if !p.is_absolute() {
+        return Err(KernelError::InvalidInput(format!(
+            "path must be absolute: {path}"
+        )));
+    }
+    if p.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(KernelError::PathTraversal(format!(
+            "path must not contain '..': {path}"
+        )));
+    }
+    Ok(p)
+}
+
+fn default_encoding() -> String {
+    "utf8".to_string()
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsReadIn {
+    path: String,
+    #[serde(default = "default_encoding")]
+    encoding: String,
+    #[serde(default)]
+    maxBytes: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsReadOut {
+    content: String,
+    size: u64,
+    truncated: bool,
+}
+
+fn fs_read(input: FsReadIn) -> Result<FsReadOut, KernelError> {
+    let path = validate_fs_path(&input.path)?;
+    if !matches!(input.encoding.as_str(), "utf8" | "base64") {
+        return Err(KernelError::InvalidInput(format!(
+            "invalid encoding: {} (expected utf8|base64)",
+            input.encoding
+        )));
+    }
+    let f = File::open(&path).map_err(io_err(format!("failed to open {}", path.display())))?;
+    let size = f
+        .metadata()
+        .map_err(io_err(format!("failed to stat {}", path.display())))?
+        .len();
+
+    let limit = input.maxBytes.unwrap_or(u64::MAX);
+    let mut buf = Vec::new();
+    f.take(limit)
+        .read_to_end(&mut buf)
+        .map_err(io_err(format!("failed to read {}", path.display())))?;
+    let truncated = size > buf.len() as u64;
+
+    let content = if input.encoding == "base64" {
+        base64::engine::general_purpose::STANDARD.encode(&buf)
+    } else {
+        match String::from_utf8(buf) {
+            Ok(s) => s,
+            // A truncated read may split a multi-byte character; drop the partial tail.
+            Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).unwrap_or_default()
+            }
+            Err(_) => {
+                return Err(KernelError::InvalidInput(format!(
+                    "file is not valid UTF-8 (use encoding \"base64\"): {}",
+                    path.display()
+                )))
+            }
+        }
+    };
+    Ok(FsReadOut {
+        content,
+        size,
+        truncated,
+    })
+}
+
+fn decode_content(content: &str, encoding: &str) -> Result<Vec<u8>, KernelError> {
+    match encoding {
+        "utf8" => Ok(content.as_bytes().to_vec()),
+        "base64" => base64::engine::general_purpose::STANDARD
+            .decode(content.trim())
+            .map_err(|e| KernelError::InvalidInput(format!("invalid base64 content: {e}"))),
+        other => Err(KernelError::InvalidInput(format!(
+            "invalid encoding: {other} (expected utf8|base64)"
+        ))),
+    }
+}
+
+/// Writes `content` to a temp file next to `path`, syncs it, then renames it into place,
+/// so readers only ever see the old file or the complete new one.
+fn fs_atomic_write(path: &Path, content: &[u8]) -> Result<(), KernelError> {
+    atomic_write_with(path, |f| f.write_all(content))
+}
+
+/// Distinguishes temp files of concurrent writes from the same process.
+static ATOMIC_WRITE_SEQ: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// `fs_atomic_write` with the body produced by `write`; on any error the temp file is
+/// removed and `path` is left untouched.
+fn atomic_write_with(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> Result<(), KernelError> {
+    let parent = path.parent().ok_or_else(|| {
+        KernelError::InvalidInput(format!("path has no parent directory: {}", path.display()))
+    })?;
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let seq = ATOMIC_WRITE_SEQ.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let tmp = parent.join(format!(".{file_name}.tmp.{}.{seq}", std::process::id()));
+    let res = File::create(&tmp)
+        .and_then(|mut f| write(&mut f).and_then(|_| f.sync_all()))
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(e) = res {
+        let _ = fs::remove_file(&tmp);
+        return Err(io_err(format!("failed to write {}", path.display()))(e));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsAtomicWriteIn {
+    path: String,
+    content: String,
+    #[serde(default = "default_encoding")]
+    encoding: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsAtomicWriteOut {
+    bytesWritten: u64,
+}
+
+fn fs_atomic_write_cmd(input: FsAtomicWriteIn) -> Result<FsAtomicWriteOut, KernelError> {
+    let path = validate_fs_path(&input.path)?;
+    let bytes = decode_content(&input.content, &input.encoding)?;
+    fs_atomic_write(&path, &bytes)?;
+    Ok(FsAtomicWriteOut {
+        bytesWritten: bytes.len() as u64,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsWriteIn {
+    path: String,
+    content: String,
+    #[serde(default = "default_encoding")]
+    encoding: String,
+    #[serde(default)]
+    createDirs: bool,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsWriteOut {
+    bytesWritten: u64,
+    created: bool,
+}
+
+fn fs_write(input: FsWriteIn) -> Result<FsWriteOut, KernelError> {
+    let path = validate_fs_path(&input.path)?;
+    let bytes = decode_content(&input.content, &input.encoding)?;
+    let existed = path.exists();
+    if existed && !input.overwrite {
+        return Err(KernelError::AlreadyExists(format!(
+            "refusing to overwrite existing file: {}",
+            path.display()
+        )));
+    }
+    let parent = path.parent().ok_or_else(|| {
+        KernelError::InvalidInput(format!("path has no parent directory: {}", path.display()))
+    })?;
+    if input.createDirs {
+        fs::create_dir_all(parent)
+            .map_err(io_err(format!("failed to create dir {}", parent.display())))?;
+    }
+
+    fs_atomic_write(&path, &bytes)?;
+
+    Ok(FsWriteOut {
+        bytesWritten: bytes.len() as u64,
+        created: !existed,
+    })
+}
+
+/// Minimal wildcard match: `*` matches any run of characters, `?` matches one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+fn format_mtime(meta: &fs::Metadata) -> String {
+    meta.modified()
+        .ok()
+        .and_then(|t| OffsetDateTime::from(t).format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsListIn {
+    path: String,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    maxDepth: Option<u32>,
+    #[serde(default)]
+    includeHidden: bool,
+    #[serde(default)]
+    glob: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsEntry {
+    path: String,
+    name: String,
+    isDir: bool,
+    isSymlink: bool,
+    size: u64,
+    mtime: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsListOut {
+    entries: Vec<FsEntry>,
+}
+
+fn fs_list(input: FsListIn) -> Result<FsListOut, KernelError> {
+    let root = validate_fs_path(&input.path)?;
+    let max_depth = if input.recursive {
+        input.maxDepth.unwrap_or(u32::MAX)
+    } else {
+        1
+    };
+
+    let mut entries = Vec::new();
+    let mut stack: Vec<(PathBuf, u32)> = vec![(root, 1)];
+    while let Some((dir, depth)) = stack.pop() {
+        let read =
+            fs::read_dir(&dir).map_err(io_err(format!("failed to list {}", dir.display())))?;
+        for entry in read {
+            let entry = entry.map_err(io_err(format!("failed to list {}", dir.display())))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') && !input.includeHidden {
+                continue;
+            }
+            // symlink_metadata: report links as links and never descend through them.
+            let meta = fs::symlink_metadata(entry.path())
+                .map_err(io_err(format!("failed to stat {}", entry.path().display())))?;
+            let is_dir = meta.is_dir();
+            if is_dir && depth < max_depth {
+                stack.push((entry.path(), depth + 1));
+            }
+            if input.glob.as_deref().is_some_and(|g| !glob_match(g, &name)) {
+                continue;
+            }
+            entries.push(FsEntry {
+                path: entry.path().display().to_string(),
+                name,
+                isDir: is_dir,
+                isSymlink: meta.file_type().is_symlink(),
+                size: meta.len(),
+                mtime: format_mtime(&meta),
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(FsListOut { entries })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsStatIn {
+    path: String,
+    #[serde(default)]
+    followSymlinks: bool,
+}
+
+#[derive(Serialize, Default, JsonSchema)]
+struct FsStatOut {
+    exists: bool,
+    isFile: bool,
+    isDir: bool,
+    isSymlink: bool,
+    size: u64,
+    mtime: String,
+    permissions: u32,
+}
+
+#[cfg(unix)]
+fn permission_bits(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permission_bits(_meta: &fs::Metadata) -> u32 {
+    0
+}
+
+fn fs_stat(input: FsStatIn) -> Result<FsStatOut, KernelError> {
+    let path = validate_fs_path(&input.path)?;
+    let link_meta = match fs::symlink_metadata(&path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FsStatOut::default()),
+        Err(e) => return Err(io_err(format!("failed to stat {}", path.display()))(e)),
+    };
+    let meta = if input.followSymlinks {
+        match fs::metadata(&path) {
+            Ok(m) => m,
+            // Dangling symlink: the target does not exist.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FsStatOut::default()),
+            Err(e) => return Err(io_err(format!("failed to stat {}", path.display()))(e)),
+        }
+    } else {
+        link_meta.clone()
+    };
+    Ok(FsStatOut {
+        exists: true,
+        isFile: meta.is_file(),
+        isDir: meta.is_dir(),
+        isSymlink: link_meta.file_type().is_symlink(),
+        size: meta.len(),
+        mtime: format_mtime(&meta),
+        permissions: permission_bits(&meta),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsDeleteIn {
+    path: String,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsDeleteOut {
+    ok: bool,
+}
+
+fn fs_delete(input: FsDeleteIn) -> Result<FsDeleteOut, KernelError> {
+    let path = validate_fs_path(&input.path)?;
+    if path.components().any(|c| c.as_os_str() == ".git") {
+        return Err(KernelError::InvalidInput(format!(
+            "refusing to delete inside a .git directory: {}",
+            path.display()
+        )));
+    }
+    let meta = match fs::symlink_metadata(&path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && input.force => {
+            return Ok(FsDeleteOut { ok: true });
+        }
+        Err(e) => return Err(io_err(format!("failed to stat {}", path.display()))(e)),
+    };
+
+    let res = if meta.is_dir() {
+        if !input.recursive {
+            return Err(KernelError::InvalidInput(format!(
+                "path is a directory (set recursive): {}",
+                path.display()
+            )));
+        }
+        fs::remove_dir_all(&path)
+    } else {
+        fs::remove_file(&path)
+    };
+    res.map_err(io_err(format!("failed to delete {}", path.display())))?;
+    Ok(FsDeleteOut { ok: true })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsCopyIn {
+    src: String,
+    dst: String,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    overwrite: bool,
+    #[serde(default)]
+    preserveMtime: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsCopyOut {
+    bytesCopied: u64,
+    filesCopied: u32,
+}
+
+fn collect_copy_plan(
+    src: &Path,
+    dst: &Path,
+    plan: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), KernelError> {
+    let read = fs::read_dir(src).map_err(io_err(format!("failed to list {}", src.display())))?;
+    for entry in read {
+        let entry = entry.map_err(io_err(format!("failed to list {}", src.display())))?;
+        let meta = fs::symlink_metadata(entry.path())
+            .map_err(io_err(format!("failed to stat {}", entry.path().display())))?;
+        let target = dst.join(entry.file_name());
+        if meta.is_dir() {
+            collect_copy_plan(&entry.path(), &target, plan)?;
+        } else {
+            plan.push((entry.path(), target));
+        }
+    }
+    Ok(())
+}
+
+fn fs_copy(input: FsCopyIn) -> Result<FsCopyOut, KernelError> {
+    let src = validate_fs_path(&input.src)?;
+    let dst = validate_fs_path(&input.dst)?;
+    let src_meta =
+        fs::metadata(&src).map_err(io_err(format!("failed to stat {}", src.display())))?;
+
+    let mut plan: Vec<(PathBuf, PathBuf)> = Vec::new();
+    if src_meta.is_dir() {
+        if !input.recursive {
+            return Err(KernelError::InvalidInput(format!(
+                "source is a directory (set recursive): {}",
+                src.display()
+            )));
+        }
+        if dst.starts_with(&src) {
+            return Err(KernelError::InvalidInput(format!(
+                "refusing to copy a directory into itself: src={} dst={}",
+                src.display(),
+                dst.display()
+            )));
+        }
+        collect_copy_plan(&src, &dst, &mut plan)?;
+    } else {
+        plan.push((src.clone(), dst.clone()));
+    }
+
+    // Check every destination up front so a refused copy leaves nothing half-done.
+    if !input.overwrite {
+        if let Some((_, existing)) = plan.iter().find(|(_, to)| to.exists()) {
+            return Err(KernelError::AlreadyExists(format!(
+                "refusing to overwrite existing file: {}",
+                existing.display()
+            )));
+        }
+    }
+
+    let mut bytes_copied = 0u64;
+    for (from, to) in plan.iter() {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)
+                .map_err(io_err(format!("failed to create dir {}", parent.display())))?;
+        }
+        bytes_copied += fs::copy(from, to).map_err(io_err(format!(
+            "failed to copy {} -> {}",
+            from.display(),
+            to.display()
+        )))?;
+        if input.preserveMtime {
+            let mtime = fs::metadata(from)
+                .and_then(|m| m.modified())
+                .map_err(io_err(format!(
+                    "failed to read mtime of {}",
+                    from.display()
+                )))?;
+            File::options()
+                .write(true)
+                .open(to)
+                .and_then(|f| f.set_modified(mtime))
+                .map_err(io_err(format!("failed to set mtime of {}", to.display())))?;
+        }
+    }
+    Ok(FsCopyOut {
+        bytesCopied: bytes_copied,
+        filesCopied: plan.len() as u32,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct FsHashIn {
+    path: String,
+    #[serde(default = "default_hash_algorithm")]
+    algorithm: String,
+}
+
+fn default_hash_algorithm() -> String {
+    "sha256".to_string()
+}
+
+#[derive(Serialize, JsonSchema)]
+struct FsHashOut {
+    hash: String,
+    algorithm: String,
+    size: u64,
+}
+
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+fn hex_lower(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Streams `reader` through `update` in fixed-size chunks; returns the byte count.
+fn for_each_chunk(
+    mut reader: impl Read,
+    mut update: impl FnMut(&[u8]) -> Result<(), KernelError>,
+) -> Result<u64, KernelError> {
+    let mut buf = vec![0u8; HASH_CHUNK_SIZE];
+    let mut total = 0u64;
+    loop {
+        let n = reader.read(&mut buf).map_err(io_err("failed to read"))?;
+        if n == 0 {
+            return Ok(total);
+        }
+        update(&buf[..n])?;
+        total += n as u64;
+    }
+}
+
+fn git_blob_sha1(file: File) -> Result<(String, u64), KernelError> {
+    // Use git itself so the result is exactly the blob SHA git would record.
+    let mut child = Command::new(git_binary())
+        .args(["hash-object", "--stdin", "--no-filters"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(io_err("git failed"))?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| io::Error::other("failed to open git stdin"))?;
+    let size = for_each_chunk(file, |chunk| {
+        stdin
+            .write_all(chunk)
+            .map_err(io_err("failed to stream to git hash-object"))
+    })?;
+    drop(stdin);
+    let out = child
+        .wait_with_output()
+        .map_err(io_err("git hash-object failed"))?;
+    if !out.status.success() {
+        return Err(KernelError::GitFailed {
+            command: "git hash-object".to_string(),
+            exit_code: out.status.code().unwrap_or(1),
+            stderr: String::from_utf8_lossy(&out.stderr).trim_end().to_string(),
+        });
+    }
+    Ok((
+        String::from_utf8_lossy(&out.stdout).trim().to_string(),
+        size,
+    ))
+}
+
+fn fs_hash(input: FsHashIn) -> Result<FsHashOut, KernelError> {
+    use sha2::Digest;
+    use std::hash::Hasher;
+
+    let path = validate_fs_path(&input.path)?;
+    let file = File::open(&path).map_err(io_err(format!("failed to open {}", path.display())))?;
+    let (hash, size) = match input.algorithm.as_str() {
+        "sha256" => {
+            let mut h = sha2::Sha256::new();
+            let size = for_each_chunk(file, |c| {
+                h.update(c);
+                Ok(())
+            })?;
+            (hex_lower(&h.finalize()), size)
+        }
+        "md5" => {
+            let mut h = md5::Md5::new();
+            let size = for_each_chunk(file, |c| {
+                h.update(c);
+                Ok(())
+            })?;
+            (hex_lower(&h.finalize()), size)
+        }
+        "xxhash64" => {
+            let mut h = twox_hash::XxHash64::with_seed(0);
+            let size = for_each_chunk(file, |c| {
+                h.write(c);
+                Ok(())
+            })?;
+            (format!("{:016x}", h.finish()), size)
+        }
+        "sha1" => git_blob_sha1(file)?,
+        other => {
+            return Err(KernelError::InvalidInput(format!(
+                "invalid hash algorithm: {other} (expected sha256|sha1|md5|xxhash64)"
+            )))
+        }
+    };
+    Ok(FsHashOut {
+        hash,
+        algorithm: input.algorithm,
+        size,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, JsonSchema)]
+struct JUnitSummary {
+    total: u32,
+    passed: u32,
+    failed: u32,
+    errored: u32,
+    skipped: u32,
+    /// `classname.name` of failed and errored test cases.
+    failedTests: Vec<String>,
+}
+
+/// Reads `name="value"` pairs from the inside of an XML start tag.
+fn xml_attrs(tag: &str) -> BTreeMap<String, String> {
+    let mut attrs = BTreeMap::new();
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq]
+            .split_whitespace()
+            .last()
+            .unwrap_or("")
+            .to_string();
+        let after = rest[eq + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(end) = after[1..].find(quote) else {
+            break;
+        };
+        let value = after[1..1 + end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&");
+        attrs.insert(key, value);
+        rest = &after[end + 2..];
+    }
+    attrs
+}
+
+/// Counts `<testcase>` outcomes in a JUnit XML report. This is a tag scanner, not a
+/// full XML parser: comments, CDATA and declarations are skipped, everything else
+/// besides `testcase`/`failure`/`error`/`skipped` is ignored.
+fn parse_junit(xml: &str) -> JUnitSummary {
+    #[derive(PartialEq)]
+    enum Outcome {
+        Passed,
+        Failed,
+        Errored,
+        Skipped,
+    }
+    let mut summary = JUnitSummary::default();
+    let mut finish = |name: String, outcome: Outcome| {
+        summary.total += 1;
+        match outcome {
+            Outcome::Passed => summary.passed += 1,
+            Outcome::Failed => summary.failed += 1,
+            Outcome::Errored => summary.errored += 1,
+            Outcome::Skipped => summary.skipped += 1,
+        }
+        if matches!(outcome, Outcome::Failed | Outcome::Errored) {
+            summary.failedTests.push(name);
+        }
+    };
+
+    let mut current: Option<(String, Outcome)> = None;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let skip_to = [
+            ("<!--", "-->"),
+            ("<![CDATA[", "]]>"),
+            ("<?", "?>"),
+            ("<!", ">"),
+        ]
+        .iter()
+        .find(|(open, _)| rest.starts_with(open))
+        .map(|(_, close)| *close);
+        if let Some(close) = skip_to {
+            match rest.find(close) {
+                Some(end) => rest = &rest[end + close.len()..],
+                None => break,
+            }
+            continue;
+        }
+
+        // Find the closing '>' outside quoted attribute values.
+        let mut quote = None;
+        let Some(end) = rest.char_indices().skip(1).find_map(|(i, ch)| {
+            match (quote, ch) {
+                (None, '"' | '\'') => quote = Some(ch),
+                (Some(q), _) if q == ch => quote = None,
+                (None, '>') => return Some(i),
+                _ => {}
+            }
+            None
+        }) else {
+            break;
+        };
+        let tag = rest[1..end].trim();
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let name = tag.split_whitespace().next().unwrap_or("");
+        match (name, closing) {
+            ("testcase", false) => {
+                if let Some((name, outcome)) = current.take() {
+                    finish(name, outcome);
+                }
+                let attrs = xml_attrs(tag);
+                let test_name = attrs.get("name").cloned().unwrap_or_default();
+                let full_name = match attrs.get("classname").filter(|c| !c.is_empty()) {
+                    Some(class) => format!("{class}.{test_name}"),
+                    None => test_name,
+                };
+                if self_closing {
+                    finish(full_name, Outcome::Passed);
+                } else {
+                    current = Some((full_name, Outcome::Passed));
+                }
+            }
+            ("testcase", true) => {
+                if let Some((name, outcome)) = current.take() {
+                    finish(name, outcome);
+                }
+            }
+            ("failure" | "error" | "skipped", false) => {
+                if let Some((_, outcome)) = current.as_mut().filter(|(_, o)| *o == Outcome::Passed)
+                {
+                    *outcome = match name {
+                        "failure" => Outcome::Failed,
+                        "error" => Outcome::Errored,
+                        _ => Outcome::Skipped,
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, outcome)) = current.take() {
+        finish(name, outcome);
+    }
+    summary
+}
+
+#[derive(Deserialize, JsonSchema, Default)]
+struct VerifyCmdIn {
+    name: String,
+    command: String,
+    /// Extra environment variables for this command only.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Overrides the run-level `timeoutSecs` for this command.
+    #[serde(default)]
+    timeoutSecs: Option<u64>,
+    /// Output beyond this many bytes (stdout and stderr combined) is dropped.
+    #[serde(default)]
+    maxOutputBytes: Option<u64>,
+    /// JUnit XML report written by the command (relative paths resolve against the worktree).
+    #[serde(default)]
+    junitOutputPath: Option<String>,
+    /// Extra attempts after a failure or timeout.
+    #[serde(default)]
+    retryCount: Option<u32>,
+    #[serde(default)]
+    retryDelayMs: Option<u64>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct VerifyRunIn {
+    worktreePath: String,
+    outDir: String,
+    commands: Vec<VerifyCmdIn>,
+    #[serde(default)]
+    timeoutSecs: Option<u64>,
+    /// When false, commands start from an empty environment (plus their own `env`).
+    #[serde(default = "default_true")]
+    inheritEnv: bool,
+    /// Run commands concurrently; results keep input order.
+    #[serde(default)]
+    parallel: bool,
+    /// Upper bound on concurrent commands when `parallel` (default: all at once).
+    #[serde(default)]
+    maxConcurrency: Option<u32>,
+    /// Stop after the first failing command: later commands are skipped and, when
+    /// `parallel`, commands still running are killed. Both report `exitCode: -2`.
+    #[serde(default)]
+    failFast: bool,
+    /// Extra summary files to write next to `summary.json`: `markdown`, `html`.
+    #[serde(default)]
+    summaryFormats: Vec<String>,
+}
+
+impl Default for VerifyRunIn {
+    fn default() -> Self {
+        VerifyRunIn {
+            worktreePath: String::new(),
+            outDir: String::new(),
+            commands: Vec::new(),
+            timeoutSecs: None,
+            inheritEnv: true,
+            parallel: false,
+            maxConcurrency: None,
+            failFast: false,
+            summaryFormats: Vec::new(),
+        }
+    }
+}
+
+// Deserialize is for `verify.compare`; defaults let it read summaries from older kernels.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct VerifyCmdOut {
+    name: String,
+    command: String,
+    ok: bool,
+    exitCode: i32,
+    outputPath: String,
+    #[serde(default)]
+    timedOut: bool,
+    #[serde(default)]
+    truncated: bool,
+    /// Parsed from `junitOutputPath` when the report exists; informational only.
+    #[serde(default)]
+    testResults: Option<JUnitSummary>,
+    /// Number of runs, including retries (`0` when skipped by `failFast`).
+    #[serde(default)]
+    attempts: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct VerifySummaryOut {
+    version: i32,
+    ranAt: String,
+    commands: Vec<VerifyCmdOut>,
+    ok: bool,
+    /// Format name (`json`, `markdown`, `html`) to the summary file written for it.
+    #[serde(default)]
+    summaryPaths: BTreeMap<String, String>,
+}
+
+/// File-name-safe form of a verify command name: lowercase ASCII alphanumerics, `.`, `_`
+/// and `-`, with other runs collapsed to one `-`. Empty results become `command`.
+pub fn safe_name(name: &str) -> String {
+    let mut out = String::new();
+    let mut last_was_dash = false;
+    for ch in name.trim().to_lowercase().chars() {
+        let allowed = ch.is_ascii_alphanumeric() || ch == '.' || ch == '_' || ch == '-';
+        if allowed {
+            out.push(ch);
+            last_was_dash = false;
+        } else if !last_was_dash {
+            out.push('-');
+            last_was_dash = true;
+        }
+    }
+    let out = out.trim_matches('-').to_string();
+    if out.is_empty() {
+        "command".to_string()
+    } else {
+        out
+    }
+}
+
+/// Where `verify.run` writes the output of the command called `name`.
+fn verify_output_path(out_dir: &Path, name: &str) -> PathBuf {
+    out_dir.join(format!("{}.txt", safe_name(name)))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct VerifySafeNameIn {
+    name: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct VerifySafeNameOut {
+    safeName: String,
+}
+
+fn verify_safe_name(input: VerifySafeNameIn) -> Result<VerifySafeNameOut, KernelError> {
+    Ok(VerifySafeNameOut {
+        safeName: safe_name(&input.name),
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct VerifyOutputPathIn {
+    outDir: String,
+    name: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct VerifyOutputPathOut {
+    outputPath: String,
+}
+
+fn verify_output_path_cmd(input: VerifyOutputPathIn) -> Result<VerifyOutputPathOut, KernelError> {
+    Ok(VerifyOutputPathOut {
+        outputPath: verify_output_path(Path::new(&input.outDir), &input.name)
+            .display()
+            .to_string(),
+    })
+}
+
+/// Caps how many bytes reach the output file; writes past the cap are dropped (`Ok(0)`).
+struct CappedWriter {
+    file: File,
+    written: u64,
+    cap: u64,
+    truncated: bool,
+}
+
+impl Write for CappedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = self.cap.saturating_sub(self.written).min(buf.len() as u64) as usize;
+        self.truncated |= room < buf.len();
+        if room == 0 {
+            return Ok(0);
+        }
+        let n = self.file.write(&buf[..room])?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+struct ShellRunOut {
+    waited: Waited,
+    truncated: bool,
+}
+
+fn run_shell_command_to_file(
+    c: &VerifyCmdIn,
+    cwd: &Path,
+    output_path: &Path,
+    inherit_env: bool,
+    timeout: Option<std::time::Duration>,
+    cancel: Option<&std::sync::atomic::AtomicBool>,
+) -> Result<ShellRunOut, KernelError> {
+    let file = File::create(output_path).map_err(io_err(format!(
+        "failed to create output file {}",
+        output_path.display()
+    )))?;
+
+    let mut cmd;
+    if cfg!(windows) {
+        cmd = Command::new("cmd");
+        cmd.arg("/C").arg(&c.command);
+    } else {
+        cmd = Command::new("sh");
+        cmd.arg("-lc").arg(&c.command);
+    }
+    if !inherit_env {
+        cmd.env_clear();
+    }
+    cmd.envs(&c.env).current_dir(cwd).stdin(Stdio::null());
+    // Without a cap the child writes straight to the file; with one, both pipes are
+    // drained through a shared `CappedWriter`.
+    let capped = match c.maxOutputBytes {
+        None => {
+            let file_err = file
+                .try_clone()
+                .map_err(io_err("failed to clone output file handle"))?;
+            cmd.stdout(Stdio::from(file)).stderr(Stdio::from(file_err));
+            None
+        }
+        Some(cap) => {
+            cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+            Some(std::sync::Arc::new(std::sync::Mutex::new(CappedWriter {
+                file,
+                written: 0,
+                cap,
+                truncated: false,
+            })))
+        }
+    };
+    if timeout.is_some() || cancel.is_some() {
+        isolate_process_group(&mut cmd);
+    }
+    let mut child = cmd.spawn().map_err(io_err("failed to run command"))?;
+
+    let mut readers = Vec::new();
+    if let Some(writer) = &capped {
+        let pipes: [Option<Box<dyn Read + Send>>; 2] = [
+            child
+                .stdout
+                .take()
+                .map(|p| Box::new(p) as Box<dyn Read + Send>),
+            child
+                .stderr
+                .take()
+                .map(|p| Box::new(p) as Box<dyn Read + Send>),
+        ];
+        for mut pipe in pipes.into_iter().flatten() {
+            let writer = writer.clone();
+            // Keep draining after the cap so the child never blocks on a full pipe.
+            readers.push(std::thread::spawn(move || {
+                let mut buf = [0u8; 8192];
+                while let Ok(n) = pipe.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    let _ = writer
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .write(&buf[..n]);
+                }
+            }));
+        }
+    }
+    let waited =
+        wait_with_timeout(&mut child, timeout, cancel).map_err(io_err("failed to run command"))?;
+    for reader in readers {
+        let _ = reader.join();
+    }
+
+    let truncated = capped
+        .as_ref()
+        .is_some_and(|w| w.lock().unwrap_or_else(|e| e.into_inner()).truncated);
+    let mut footer = String::new();
+    if truncated {
+        footer.push_str(&format!(
+            "\n[OUTPUT TRUNCATED at {} bytes]\n",
+            c.maxOutputBytes.unwrap_or_default()
+        ));
+    }
+    if let Waited::TimedOut = waited {
+        footer.push_str(&format!(
+            "\n[TIMEOUT after {}s]\n",
+            timeout.map(|t| t.as_secs()).unwrap_or_default()
+        ));
+    }
+    if !footer.is_empty() {
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(output_path)
+            .map_err(io_err(format!(
+                "failed to reopen output file {}",
+                output_path.display()
+            )))?;
+        file.write_all(footer.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(io_err(format!(
+                "failed to write output file {}",
+                output_path.display()
+            )))?;
+    }
+
+    Ok(ShellRunOut { waited, truncated })
+}
+
+fn verify_run(mut input: VerifyRunIn) -> Result<VerifySummaryOut, KernelError> {
+    for c in &mut input.commands {
+        c.maxOutputBytes = c.maxOutputBytes.or(kernel_config().maxOutputBytes);
+    }
+    let worktree = PathBuf::from(input.worktreePath);
+    let out_dir = PathBuf::from(input.outDir);
+    fs::create_dir_all(&out_dir).map_err(io_err(format!(
+        "failed to create verify outDir {}",
+        out_dir.display()
+    )))?;
+
+    let timeout = input
+        .timeoutSecs
+        .map(std::time::Duration::from_secs)
+        .or_else(command_timeout);
+
+    // summary.json is always written.
+    let mut formats = vec![("json", "json")];
+    for format in &input.summaryFormats {
+        let ext = match format.as_str() {
+            "json" => continue,
+            "markdown" => "md",
+            "html" => "html",
+            other => {
+                return Err(KernelError::InvalidInput(format!(
+                    "unknown summary format: {other} (expected json, markdown or html)"
+                )))
+            }
+        };
+        if !formats.iter().any(|(f, _)| f == format) {
+            formats.push((format.as_str(), ext));
+        }
+    }
+
+    // Set once a command fails under `failFast`; stops scheduling and kills running commands.
+    let failed = std::sync::atomic::AtomicBool::new(false);
+    let cancel = input.failFast.then_some(&failed);
+
+    let run_one = |c: &VerifyCmdIn| -> Result<VerifyCmdOut, KernelError> {
+        let output_path = verify_output_path(&out_dir, &c.name);
+
+        // Each attempt recreates the output file, so only the last run's output is kept.
+        let mut attempts = 0;
+        let (run, exit_code) = loop {
+            attempts += 1;
+            let run = run_shell_command_to_file(
+                c,
+                &worktree,
+                &output_path,
+                input.inheritEnv,
+                c.timeoutSecs
+                    .map(std::time::Duration::from_secs)
+                    .or(timeout),
+                cancel,
+            )?;
+            let exit_code = match run.waited {
+                Waited::Exited(status) => status.code().unwrap_or(1),
+                Waited::TimedOut => -1,
+                Waited::Cancelled => -2,
+            };
+            if exit_code == 0 || exit_code == -2 || attempts > c.retryCount.unwrap_or(0) {
+                break (run, exit_code);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(
+                c.retryDelayMs.unwrap_or(0),
+            ));
+        };
+        let test_results = c
+            .junitOutputPath
+            .as_ref()
+            .and_then(|p| fs::read_to_string(worktree.join(p)).ok())
+            .map(|xml| parse_junit(&xml));
+
+        Ok(VerifyCmdOut {
+            name: c.name.clone(),
+            command: c.command.clone(),
+            ok: exit_code == 0,
+            exitCode: exit_code,
+            outputPath: output_path.display().to_string(),
+            timedOut: matches!(run.waited, Waited::TimedOut),
+            truncated: run.truncated,
+            testResults: test_results,
+            attempts,
+        })
+    };
+
+    let ran_at = now_iso();
+    // Sequential runs are a single worker.
+    let workers = if input.parallel {
+        input
+            .maxConcurrency
+            .map(|n| n.max(1) as usize)
+            .unwrap_or(usize::MAX)
+    } else {
+        1
+    }
+    .min(input.commands.len());
+    // Workers pull the next command index from a shared counter; results keep input order.
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let slots: Vec<std::sync::Mutex<Option<Result<VerifyCmdOut, KernelError>>>> = input
+        .commands
+        .iter()
+        .map(|_| std::sync::Mutex::new(None))
+        .collect();
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                if failed.load(std::sync::atomic::Ordering::SeqCst) {
+                    break;
+                }
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let Some(c) = input.commands.get(i) else {
+                    break;
+                };
+                let res = run_one(c);
+                if input.failFast && !res.as_ref().is_ok_and(|r| r.ok || r.exitCode == -2) {
+                    failed.store(true, std::sync::atomic::Ordering::SeqCst);
+                }
+                *slots[i].lock().unwrap_or_else(|e| e.into_inner()) = Some(res);
+            });
+        }
+    });
+    let results: Vec<VerifyCmdOut> = slots
+        .into_iter()
+        .zip(&input.commands)
+        .map(|(slot, c)| {
+            slot.into_inner()
+                .unwrap_or_else(|e| e.into_inner())
+                .unwrap_or_else(|| {
+                    Ok(VerifyCmdOut {
+                        name: c.name.clone(),
+                        command: c.command.clone(),
+                        ok: false,
+                        exitCode: -2,
+                        outputPath: String::new(),
+                        timedOut: false,
+                        truncated: false,
+                        testResults: None,
+                        attempts: 0,
+                    })
+                })
+        })
+        .collect::<Result<_, _>>()?;
+    let all_ok = results.iter().all(|r| r.ok);
+
+    let summary_paths: BTreeMap<String, String> = formats
+        .iter()
+        .map(|(format, ext)| {
+            let path = out_dir.join(format!("summary.{ext}"));
+            (format.to_string(), path.display().to_string())
+        })
+        .collect();
+    let summary = VerifySummaryOut {
+        version: 1,
+        ranAt: ran_at,
+        commands: results,
+        ok: all_ok,
+        summaryPaths: summary_paths,
+    };
+
+    // Write evidence files.
+    for (format, path) in &summary.summaryPaths {
+        let body = match format.as_str() {
+            "markdown" => verify_summary_markdown(&summary),
+            "html" => verify_summary_html(&summary),
+            _ => {
+                let mut json = serde_json::to_string_pretty(&summary)
+                    .map_err(|e| io_err("failed to serialize verify summary")(e.into()))?;
+                json.push('\n');
+                json
+            }
+        };
+        fs_atomic_write(Path::new(path), body.as_bytes())?;
+    }
+
+    Ok(summary)
+}
+
+fn verify_summary_markdown(summary: &VerifySummaryOut) -> String {
+    let mut md = format!(
+        "## Verify summary: {}\n\nRan at {}\n\n| Name | Status | Exit Code |\n| --- | --- | --- |\n",
+        if summary.ok { "✅ passed" } else { "❌ failed" },
+        summary.ranAt
+    );
+    for c in &summary.commands {
+        let status = if c.ok { "✅ pass" } else { "**❌ fail**" };
+        md.push_str(&format!(
+            "| {} | {status} | {} |\n",
+            c.name.replace('|', "\\|"),
+            c.exitCode
+        ));
+    }
+    md
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn verify_summary_html(summary: &VerifySummaryOut) -> String {
+    let rows: String = summary
+        .commands
+        .iter()
+        .map(|c| {
+            let (class, status) = if c.ok {
+                ("pass", "✅ pass")
+            } else {
+                ("fail", "❌ fail")
+            };
+            format!(
+                "<tr class=\"{class}\"><td>{}</td><td>{status}</td><td>{}</td></tr>\n",
+                html_escape(&c.name),
+                c.exitCode
+            )
+        })
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Verify summary</title>\n\
+         <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:4px 8px}}.pass{{color:#1a7f37}}\
+         .fail{{color:#cf222e;font-weight:bold}}</style></head>\n\
+         <body><h1>Verify summary: {}</h1><p>Ran at {}</p>\n\
+         <table><tr><th>Name</th><th>Status</th><th>Exit Code</th></tr>\n{rows}</table></body></html>\n",
+        if summary.ok { "passed" } else { "failed" },
+        html_escape(&summary.ranAt)
+    )
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct VerifyCleanIn {
+    /// Absolute path with at least one `*`/`?` wildcard component below a non-root directory.
+    outDirPattern: String,
+    /// Only runs older than this are removed (default: one day).
+    #[serde(default)]
+    olderThanSecs: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct VerifyCleanOut {
+    removedDirs: Vec<String>,
+    bytesFreed: u64,
+    /// Matched directories left alone because they contain `.git` or do not look like
+    /// verify output.
+    skippedDirs: Vec<String>,
+}
+
+const VERIFY_CLEAN_DEFAULT_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Expands wildcard components of an absolute path against the filesystem (directories only).
+fn expand_dir_glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut found = vec![PathBuf::new()];
+    for comp in pattern.components() {
+        let part = comp.as_os_str().to_string_lossy();
+        if !part.contains(['*', '?']) {
+            found.iter_mut().for_each(|p| p.push(comp));
+            continue;
+        }
+        found = found
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .flatten()
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+            .filter(|e| glob_match(&part, &e.file_name().to_string_lossy()))
+            .map(|e| e.path())
+            .collect();
+    }
+    found.retain(|p| fs::symlink_metadata(p).is_ok_and(|m| m.is_dir()));
+    found.sort();
+    found
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(read) = fs::read_dir(path) else {
+        return 0;
+    };
+    read.flatten()
+        .map(|e| match fs::symlink_metadata(e.path()) {
+            Ok(m) if m.is_dir() => dir_size(&e.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn contains_git_dir(path: &Path) -> bool {
+    let Ok(read) = fs::read_dir(path) else {
+        return false;
+    };
+    read.flatten().any(|e| {
+        e.file_name() == ".git"
+            || fs::symlink_metadata(e.path()).is_ok_and(|m| m.is_dir())
+                && contains_git_dir(&e.path())
+    })
+}
+
+/// A `summary.json`, or (for a partial run) nothing but `*.txt`/`*.xml` evidence files.
+fn looks_like_verify_output(dir: &Path) -> bool {
+    if fs::symlink_metadata(dir.join("summary.json")).is_ok_and(|m| m.is_file()) {
+        return true;
+    }
+    let Ok(read) = fs::read_dir(dir) else {
+        return false;
+    };
+    read.flatten().all(|e| {
+        let path = e.path();
+        fs::symlink_metadata(&path).is_ok_and(|m| m.is_file())
+            && path
+                .extension()
+                .is_some_and(|ext| ext == "txt" || ext == "xml")
+    })
+}
+
+fn verify_clean(input: VerifyCleanIn) -> Result<VerifyCleanOut, KernelError> {
+    let pattern = validate_fs_path(&input.outDirPattern)?;
+    // Components are `/`, then at least one literal directory before the first wildcard.
+    let first_wildcard = pattern
+        .components()
+        .position(|c| c.as_os_str().to_string_lossy().contains(['*', '?']));
+    if first_wildcard.is_none_or(|i| i < 2) {
+        return Err(KernelError::InvalidInput(format!(
+            "outDirPattern needs a wildcard component below a non-root directory: {}",
+            pattern.display()
+        )));
+    }
+    if pattern.components().any(|c| c.as_os_str() == ".git") {
+        return Err(KernelError::InvalidInput(format!(
+            "refusing to clean inside a .git directory: {}",
+            pattern.display()
+        )));
+    }
+    let age = input.olderThanSecs.unwrap_or(VERIFY_CLEAN_DEFAULT_AGE_SECS);
+    let cutoff = OffsetDateTime::now_utc() - time::Duration::seconds(age as i64);
+
+    let mut removed_dirs = Vec::new();
+    let mut skipped_dirs = Vec::new();
+    let mut bytes_freed = 0;
+    for dir in expand_dir_glob(&pattern) {
+        if contains_git_dir(&dir) || !looks_like_verify_output(&dir) {
+            skipped_dirs.push(dir.display().to_string());
+            continue;
+        }
+        // Without a readable summary (a partial run) the directory's mtime stands in for `ranAt`.
+        let ran_at = fs::read_to_string(dir.join("summary.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+            .and_then(|v| v.get("ranAt")?.as_str().map(str::to_string))
+            .and_then(|s| OffsetDateTime::parse(&s, &Rfc3339).ok())
+            .or_else(|| {
+                let modified = fs::metadata(&dir).and_then(|m| m.modified()).ok()?;
+                Some(OffsetDateTime::from(modified))
+            });
+        if ran_at.is_some_and(|t| t > cutoff) {
+            continue;
+        }
+        let size = dir_size(&dir);
+        fs::remove_dir_all(&dir).map_err(io_err(format!("failed to remove {}", dir.display())))?;
+        bytes_freed += size;
+        removed_dirs.push(dir.display().to_string());
+    }
+
+    Ok(VerifyCleanOut {
+        removedDirs: removed_dirs,
+        bytesFreed: bytes_freed,
+        skippedDirs: skipped_dirs,
+    })
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct VerifyCompareIn {
+    baselineSummaryPath: String,
+    currentSummaryPath: String,
+}
+
+#[derive(Serialize, Debug, PartialEq, JsonSchema)]
+struct ExitCodeChange {
+    name: String,
+    baseline: i32,
+    current: i32,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct VerifyCompareOut {
+    newFailures: Vec<String>,
+    newPasses: Vec<String>,
+    unchanged: Vec<String>,
+    exitCodeChanges: Vec<ExitCodeChange>,
+}
+
+fn read_verify_summary(path: &str) -> Result<VerifySummaryOut, KernelError> {
+    let text = fs::read_to_string(path).map_err(io_err(format!("failed to read {path}")))?;
+    serde_json::from_str(&text)
+        .map_err(|e| KernelError::InvalidInput(format!("invalid verify summary {path}: {e}")))
+}
+
+/// Joins two summaries by command name. A command only in the current run counts by its
+/// own status; one only in the baseline is a new failure if it used to pass.
+fn verify_compare(input: VerifyCompareIn) -> Result<VerifyCompareOut, KernelError> {
+    let baseline = read_verify_summary(&input.baselineSummaryPath)?;
+    let current = read_verify_summary(&input.currentSummaryPath)?;
+    let by_name = |s: &VerifySummaryOut| -> BTreeMap<String, (bool, i32)> {
+        s.commands
+            .iter()
+            .map(|c| (c.name.clone(), (c.ok, c.exitCode)))
+            .collect()
+    };
+    let baseline = by_name(&baseline);
+    let current = by_name(&current);
+
+    let mut out = VerifyCompareOut {
+        newFailures: Vec::new(),
+        newPasses: Vec::new(),
+        unchanged: Vec::new(),
+        exitCodeChanges: Vec::new(),
+    };
+    let names: BTreeSet<&String> = baseline.keys().chain(current.keys()).collect();
+    for name in names {
+        let list = match (baseline.get(name), current.get(name)) {
+            (Some((was_ok, was_code)), Some((ok, code))) => {
+                if was_code != code {
+                    out.exitCodeChanges.push(ExitCodeChange {
+                        name: name.clone(),
+                        baseline: *was_code,
+                        current: *code,
+                    });
+                }
+                match (was_ok, ok) {
+                    (true, false) => &mut out.newFailures,
+                    (false, true) => &mut out.newPasses,
+                    _ => &mut out.unchanged,
+                }
+            }
+            (None, Some((true, _))) => &mut out.newPasses,
+            (None, Some((false, _))) | (Some((true, _)), None) => &mut out.newFailures,
+            _ => &mut out.unchanged,
+        };
+        list.push(name.clone());
+    }
+    Ok(out)
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct BatchOp {
+    command: String,
+    #[serde(default)]
+    input: serde_json::Value,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct BatchIn {
+    operations: Vec<BatchOp>,
+    #[serde(default = "default_true")]
+    stopOnFirstError: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct BatchResult {
+    command: String,
+    ok: bool,
+    output: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct BatchOut {
+    results: Vec<BatchResult>,
+}
+
+fn batch(input: BatchIn) -> Result<BatchOut, KernelError> {
+    let mut results: Vec<BatchResult> = Vec::new();
+    let mut failed = false;
+    for op in input.operations {
+        if failed && input.stopOnFirstError {
+            results.push(BatchResult {
+                command: op.command,
+                ok: false,
+                output: None,
+                error: Some("skipped: an earlier operation failed".to_string()),
+            });
+            continue;
+        }
+        let res = if op.command == "batch" {
+            Err(KernelError::InvalidInput(
+                "batch cannot be nested".to_string(),
+            ))
+        } else {
+            dispatch(&op.command, op.input)
+        };
+        match res {
+            Ok(output) => results.push(BatchResult {
+                command: op.command,
+                ok: true,
+                output: Some(output),
+                error: None,
+            }),
+            Err(e) => {
+                failed = true;
+                results.push(BatchResult {
+                    command: op.command,
+                    ok: false,
+                    output: None,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+    Ok(BatchOut { results })
+}
+
+/// Decodes `input` for `f`, runs it, and re-encodes the output.
+fn run_json<I, O>(
+    input: serde_json::Value,
+    f: fn(I) -> Result<O, KernelError>,
+) -> Result<serde_json::Value, KernelError>
+where
+    I: for<'de> Deserialize<'de>,
+    O: Serialize,
+{
+    let input: I = serde_json::from_value(input)
+        .map_err(|e| KernelError::InvalidInput(format!("invalid JSON input: {e}")))?;
+    let out = f(input)?;
+    serde_json::to_value(out).map_err(|e| io_err("failed to serialize JSON")(e.into()))
+}
+
+/// Commands that never write to the filesystem or a repository; they still run under `--dry-run`.
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "protocol.version",
+    "repo.info",
+    "worktree.list",
+    "worktree.inspect",
+    "patch.validate",
+    "git.status",
+    "git.log",
+    "git.diff",
+    "git.diff.cached",
+    "git.show",
+    "git.branch.list",
+    "git.merge_base",
+    "git.config.get",
+    "git.rev_parse",
+    "git.ls_files",
+    "git.ls_remote",
+    "git.describe",
+    "git.check_ignore",
+    "git.submodule.list",
+    "git.remote.list",
+    "git.reflog",
+    "git.count_objects",
+    "git.worktree.head",
+    "fs.read",
+    "fs.list",
+    "fs.stat",
+    "fs.hash",
+    "verify.compare",
+    "verify.safe_name",
+    "verify.output_path",
+];
+
+/// Stand-in for the SHAs a skipped command would have produced.
+const DRY_RUN_PLACEHOLDER_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// Builds a value matching `schema` for a command skipped by `--dry-run`: optional fields
+/// are null, SHAs are zeros, and strings named like an input field echo that input.
+fn dry_run_placeholder(
+    schema: &serde_json::Value,
+    root: &serde_json::Value,
+    name: Option<&str>,
+    input: &serde_json::Value,
+) -> serde_json::Value {
+    use serde_json::Value;
+    if let Some(def) = schema["$ref"]
+        .as_str()
+        .and_then(|r| r.strip_prefix("#/$defs/"))
+    {
+        return dry_run_placeholder(&root["$defs"][def], root, name, input);
+    }
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(first) = schema["enum"].as_array().and_then(|v| v.first()) {
+        return first.clone();
+    }
+    if let Some(variants) = schema["anyOf"].as_array().or(schema["oneOf"].as_array()) {
+        if variants.iter().any(|v| v["type"] == "null") {
+            return Value::Null;
+        }
+        return variants
+            .first()
+            .map_or(Value::Null, |v| dry_run_placeholder(v, root, name, input));
+    }
+    let ty = match &schema["type"] {
+        Value::Array(types) if types.iter().any(|t| t == "null") => return Value::Null,
+        Value::Array(types) => types.first().and_then(Value::as_str).unwrap_or(""),
+        other => other.as_str().unwrap_or(""),
+    };
+    match ty {
+        "object" => match schema["properties"].as_object() {
+            Some(props) => props
+                .iter()
+                .map(|(k, v)| (k.clone(), dry_run_placeholder(v, root, Some(k), input)))
+                .collect(),
+            None => Value::Object(Default::default()),
+        },
+        "string" => {
+            let name = name.unwrap_or("");
+            match input.get(name).filter(|v| v.is_string()) {
+                Some(echo) => echo.clone(),
+                None if name.to_ascii_lowercase().ends_with("sha") => {
+                    Value::String(DRY_RUN_PLACEHOLDER_SHA.to_string())
+                }
+                None => Value::String(String::new()),
+            }
+        }
+        "integer" | "number" => Value::from(0),
+        "boolean" => Value::Bool(name == Some("ok")),
+        "array" => Value::Array(Vec::new()),
+        _ => Value::Null,
+    }
+}
+
+/// Routes a command name to its handler; shared by the CLI entry point and `batch`.
+fn dispatch(cmd: &str, input: serde_json::Value) -> Result<serde_json::Value, KernelError> {
+    // `batch` itself runs so that each of its operations is checked individually.
+    if cmd != "batch" && DRY_RUN.with(|d| d.get()) && !READ_ONLY_COMMANDS.contains(&cmd) {
+        let schema = command_schema(cmd)
+            .ok_or_else(|| KernelError::InvalidInput(format!("unknown command: {cmd}")))?;
+        // Echo from the redacted summary: the raw input may hold passwords or file contents.
+        let output = &schema["output"];
+        let mut out = dry_run_placeholder(output, output, None, &audit_input_summary(&input));
+        if let Some(map) = out.as_object_mut() {
+            map.insert("dryRun".to_string(), serde_json::Value::Bool(true));
+        }
+        return Ok(out);
+    }
+    match cmd {
+        "worktree.ensure" => run_json(input, worktree_ensure),
+        "worktree.remove" => run_json(input, worktree_remove),
+        "worktree.list" => run_json(input, worktree_list),
+        "worktree.prune" => run_json(input, worktree_prune),
+        "worktree.move" => run_json(input, worktree_move),
+        "worktree.lock" => run_json(input, worktree_lock),
+        "worktree.unlock" => run_json(input, worktree_unlock),
+        "worktree.repair" => run_json(input, worktree_repair),
+        "worktree.inspect" => run_json(input, worktree_inspect),
+        "patch.apply" => run_json(input, patch_apply),
+        "patch.generate" => run_json(input, patch_generate),
+        "patch.validate" => run_json(input, patch_validate),
+        "git.commit_all" => run_json(input, commit_all),
+        "git.status" => run_json(input, git_status),
+        "git.log" => run_json(input, git_log),
+        "git.diff" => run_json(input, git_diff),
+        "git.show" => run_json(input, git_show),
+        "git.branch.list" => run_json(input, git_branch_list),
+        "git.branch.delete" => run_json(input, git_branch_delete),
+        "git.branch.rename" => run_json(input, git_branch_rename),
+        "git.fetch" => run_json(input, git_fetch),
+        "git.push" => run_json(input, git_push),
+        "git.merge_base" => run_json(input, git_merge_base),
+        "git.cherry_pick" => run_json(input, git_cherry_pick),
+        "git.reset" => run_json(input, git_reset),
+        "git.stash" => run_json(input, git_stash),
+        "git.stash_pop" => run_json(input, git_stash_pop),
+        "git.rebase" => run_json(input, git_rebase),
+        "git.tag" => run_json(input, git_tag),
+        "git.config.get" => run_json(input, git_config_get),
+        "git.config.set" => run_json(input, git_config_set),
+        "git.rev_parse" => run_json(input, git_rev_parse),
+        "git.ls_files" => run_json(input, git_ls_files),
+        "git.clean" => run_json(input, git_clean),
+        "git.ls_remote" => run_json(input, git_ls_remote),
+        "git.describe" => run_json(input, git_describe),
+        "git.check_ignore" => run_json(input, git_check_ignore),
+        "git.update_ref" => run_json(input, git_update_ref),
+        "git.merge" => run_json(input, git_merge),
+        "git.submodule.update" => run_json(input, git_submodule_update),
+        "git.submodule.list" => run_json(input, git_submodule_list),
+        "git.format_patch" => run_json(input, git_format_patch),
+        "git.am" => run_json(input, git_am),
+        "git.remote.list" => run_json(input, git_remote_list),
+        "git.remote.add" => run_json(input, git_remote_add),
+        "git.reflog" => run_json(input, git_reflog),
+        "git.archive" => run_json(input, git_archive),
+        "git.count_objects" => run_json(input, git_count_objects),
+        "git.gc" => run_json(input, git_gc),
+        "git.init" => run_json(input, git_init),
+        "git.bisect" => run_json(input, git_bisect),
+        "git.worktree.head" => run_json(input, git_worktree_head),
+        "git.diff.cached" => run_json(input, git_diff_cached),
+        "git.symbolic_ref" => run_json(input, git_symbolic_ref),
+        "git.notes" => run_json(input, git_notes),
+        "git.bundle" => run_json(input, git_bundle),
+        "git.credential" => run_json(input, git_credential),
+        "fs.read" => run_json(input, fs_read),
+        "fs.write" => run_json(input, fs_write),
+        "fs.atomic_write" => run_json(input, fs_atomic_write_cmd),
+        "fs.list" => run_json(input, fs_list),
+        "fs.stat" => run_json(input, fs_stat),
+        "fs.delete" => run_json(input, fs_delete),
+        "fs.copy" => run_json(input, fs_copy),
+        "fs.hash" => run_json(input, fs_hash),
+        "protocol.version" => run_json(input, protocol_version),
+        "repo.info" => run_json(input, repo_info),
+        "verify.run" => run_json(input, verify_run),
+        "verify.clean" => run_json(input, verify_clean),
+        "verify.compare" => run_json(input, verify_compare),
+        "verify.safe_name" => run_json(input, verify_safe_name),
+        "verify.output_path" => run_json(input, verify_output_path_cmd),
+        "batch" => run_json(input, batch),
+        _ => Err(KernelError::InvalidInput(format!("unknown command: {cmd}"))),
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+struct VersionOut {
+    kernelVersion: String,
+    gitVersion: Option<String>,
+    gitPath: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// `ECC_GIT_PATH` (or the config file's `gitBinary`) if set, else the first `git` executable
+/// on `PATH`.
+fn find_git_path() -> Option<PathBuf> {
+    if let Some(p) = env_or_config(std::env::var_os("ECC_GIT_PATH"), &kernel_config().gitBinary) {
+        return Some(PathBuf::from(p));
+    }
+    let exe = if cfg!(windows) { "git.exe" } else { "git" };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(exe))
+        .find(|p| p.is_file())
+}
+
+fn version_info(
+    git_path: Option<PathBuf>,
+    run: impl Fn(&str, &[String]) -> Result<CmdOut, KernelError>,
+) -> VersionOut {
+    let mut out = VersionOut {
+        kernelVersion: env!("CARGO_PKG_VERSION").to_string(),
+        gitVersion: None,
+        gitPath: git_path.as_ref().map(|p| p.display().to_string()),
+        error: None,
+    };
+    let Some(git_path) = git_path else {
+        out.error = Some("git not found on PATH (set ECC_GIT_PATH)".to_string());
+        return out;
+    };
+    match run(&git_path.display().to_string(), &["--version".to_string()]) {
+        Ok(res) if res.ok => {
+            let v = res.stdout.trim();
+            out.gitVersion = Some(v.strip_prefix("git version ").unwrap_or(v).to_string());
+        }
+        Ok(res) => out.error = Some(git_failed("git --version", &res).to_string()),
+        Err(e) => out.error = Some(e.to_string()),
+    }
+    out
+}
+
+/// One line of the `ECC_AUDIT_LOG` file.
+#[derive(Serialize, Deserialize)]
+struct AuditEntry {
+    timestamp: String,
+    command: String,
+    /// The command input with sensitive values redacted.
+    inputSummary: serde_json::Value,
+    ok: bool,
+    /// `KernelError::kind` of a failed command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    errorKind: Option<String>,
+    durationMs: u64,
+    pid: u32,
+}
+
+/// Input keys whose values never reach the audit log (matched case-insensitively as substrings).
+const AUDIT_REDACTED_KEYS: &[&str] = &["password", "token", "secret", "content", "env"];
+
+fn audit_input_summary(input: &serde_json::Value) -> serde_json::Value {
+    match input {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| {
+                let key = k.to_ascii_lowercase();
+                let value = if AUDIT_REDACTED_KEYS.iter().any(|s| key.contains(s)) {
+                    serde_json::Value::String("[redacted]".to_string())
+                } else {
+                    audit_input_summary(v)
+                };
+                (k.clone(), value)
+            })
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(audit_input_summary).collect(),
+        other => other.clone(),
+    }
+}
+
+/// Appends `entry` as one JSON line. The file is locked for the write so concurrent
+/// kernel processes never interleave lines.
+fn write_audit_entry(path: &Path, entry: &AuditEntry) -> Result<(), String> {
+    let mut line =
+        serde_json::to_vec(entry).map_err(|e| format!("failed to serialize audit entry: {e}"))?;
+    line.push(b'\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed to open audit log {}: {e}", path.display()))?;
+    file.lock()
+        .map_err(|e| format!("failed to lock audit log {}: {e}", path.display()))?;
+    let written = file
+        .write_all(&line)
+        .and_then(|_| file.flush())
+        .map_err(|e| format!("failed to write audit log {}: {e}", path.display()));
+    let _ = file.unlock();
+    written
+}
+
+/// Input keys holding filesystem paths, checked against `allowedRoots` at any nesting depth.
+const PATH_KEYS: &[&str] = &[
+    "repoRoot",
+    "worktreePath",
+    "worktreePaths",
+    "newPath",
+    "cwd",
+    "path",
+    "src",
+    "dst",
+    "patchPath",
+    "patchPaths",
+    "outputPath",
+    "outDir",
+    "outputDir",
+    "outDirPattern",
+    "bundlePath",
+    "template",
+    "currentSummaryPath",
+    "baselineSummaryPath",
+    "junitOutputPath",
+    "paths",
+    "url",
+    "remote",
+];
+
+/// The local path named by a `url`/`remote` value, or `None` for a network URL or remote name.
+fn local_remote_path<'a>(key: &str, raw: &'a str) -> Option<&'a str> {
+    if !matches!(key, "url" | "remote") {
+        return Some(raw);
+    }
+    if let Some(path) = raw.strip_prefix("file://") {
+        return Some(path);
+    }
+    // `scheme://host/...` and scp-style `host:path` (a colon before any slash) are remote.
+    let scp_like = raw.find(':').is_some_and(|c| !raw[..c].contains('/'));
+    if raw.contains("://") || scp_like || (key == "remote" && !raw.contains('/')) {
+        return None;
+    }
+    Some(raw)
+}
+
+/// `ECC_ALLOWED_ROOTS` (a `PATH`-style list) when set, else the config file's `allowedRoots`.
+fn allowed_roots_from(env: Option<std::ffi::OsString>, config: &[String]) -> Vec<String> {
+    match env.filter(|v| !v.is_empty()) {
+        Some(list) => std::env::split_paths(&list)
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| p.display().to_string())
+            .collect(),
+        None => config.to_vec(),
+    }
+}
+
+/// Rejects any path-valued input field outside `allowed_roots` (no-op when empty). Relative
+/// values resolve against the enclosing `worktreePath`/`repoRoot`, like the commands do.
+fn check_allowed_roots(
+    input: &serde_json::Value,
+    allowed_roots: &[String],
+) -> Result<(), KernelError> {
+    if allowed_roots.is_empty() {
+        return Ok(());
+    }
+    let roots = allowed_roots
+        .iter()
+        .map(|r| abs_path(Path::new(r)))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_paths_under(input, &roots, None)
+}
+
+fn check_paths_under(
+    value: &serde_json::Value,
+    roots: &[PathBuf],
+    base: Option<&Path>,
+) -> Result<(), KernelError> {
+    match value {
+        serde_json::Value::Object(map) => {
+            let own_base = ["worktreePath", "repoRoot"]
+                .iter()
+                .find_map(|k| map.get(*k).and_then(|v| v.as_str()))
+                .map(|b| abs_path(Path::new(b)))
+                .transpose()?;
+            let base = own_base.as_deref().or(base);
+            for (key, v) in map {
+                if PATH_KEYS.contains(&key.as_str()) {
+                    let values: Vec<&str> = match v {
+                        serde_json::Value::String(s) => vec![s.as_str()],
+                        serde_json::Value::Array(items) => {
+                            items.iter().filter_map(|i| i.as_str()).collect()
+                        }
+                        _ => Vec::new(),
+                    };
+                    for raw in values.into_iter().filter_map(|r| local_remote_path(key, r)) {
+                        let p = Path::new(raw);
+                        let resolved = match base {
+                            Some(b)
+                                if p.is_relative()
+                                    && !matches!(key.as_str(), "worktreePath" | "repoRoot") =>
+                            {
+                                normalize_worktree_path(&b.join(p))
+                            }
+                            _ => abs_path(p)?,
+                        };
+                        if !roots.iter().any(|r| resolved.starts_with(r)) {
+                            return Err(KernelError::PathTraversal(format!(
+                                "{key} is outside allowedRoots: {raw}"
+                            )));
+                        }
+                    }
+                } else {
+                    check_paths_under(v, roots, base)?;
+                }
+            }
+            Ok(())
+        }
+        serde_json::Value::Array(items) => items
+            .iter()
+            .try_for_each(|i| check_paths_under(i, roots, base)),
+        _ => Ok(()),
+    }
+}
+
+/// Global flags offered by `completion`: `(flag, value, description)` where `value` is
+/// `Some("file")` for a path argument or `Some(<hint>)` for another value.
+const GLOBAL_FLAGS: &[(&str, Option<&str>, &str)] = &[
+    ("--help", None, "show usage"),
+    ("--version", None, "show kernel and git versions"),
+    ("--pretty", None, "indent the JSON response"),
+    (
+        "--dry-run",
+        None,
+        "report mutating commands instead of running them",
+    ),
+    (
+        "--config-file",
+        Some("file"),
+        "JSON file with installation defaults",
+    ),
+    ("--input-file", Some("file"), "read JSON input from a file"),
+    (
+        "--output-file",
+        Some("file"),
+        "write the JSON response to a file",
+    ),
+    (
+        "--timeout-secs",
+        Some("seconds"),
+        "kill spawned commands after n seconds",
+    ),
+    (
+        "--json-schema",
+        Some("command"),
+        "print JSON Schemas for a command",
+    ),
+];
+
+const COMPLETION_SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// Completion script for `shell`, generated from `COMMANDS` and `GLOBAL_FLAGS`.
+fn completion_script(shell: &str) -> Option<String> {
+    let words: Vec<&str> = GLOBAL_FLAGS
+        .iter()
+        .map(|(flag, _, _)| *flag)
+        .chain(COMMANDS.iter().copied())
+        .chain(["completion"])
+        .collect();
+    let file_flags: Vec<&str> = GLOBAL_FLAGS
+        .iter()
+        .filter(|(_, value, _)| *value == Some("file"))
+        .map(|(flag, _, _)| *flag)
+        .collect();
+    let shells = COMPLETION_SHELLS.join(" ");
+    let script = match shell {
+        "bash" => format!(
+            r#"_ecc_kernel() {{
+  local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+  case "$prev" in
+    {file_flags}) COMPREPLY=( $(compgen -f -- "$cur") ); return ;;
+    --shell) COMPREPLY=( $(compgen -W "{shells}" -- "$cur") ); return ;;
+  esac
+  COMPREPLY=( $(compgen -W "{words}" -- "$cur") )
+}}
+complete -F _ecc_kernel ecc-kernel
+"#,
+            file_flags = file_flags.join("|"),
+            words = words.join(" "),
+        ),
+        "zsh" => format!(
+            r#"#compdef ecc-kernel
+_ecc_kernel() {{
+  case "${{words[CURRENT-1]}}" in
+    {file_flags}) _files; return ;;
+    --shell) compadd {shells}; return ;;
+  esac
+  compadd -- {words}
+}}
+compdef _ecc_kernel ecc-kernel
+"#,
+            file_flags = file_flags.join("|"),
+            words = words.join(" "),
+        ),
+        "fish" => {
+            let mut out = String::from("complete -c ecc-kernel -f\n");
+            for (flag, value, description) in GLOBAL_FLAGS {
+                let long = flag.trim_start_matches("--");
+                let arg = match value {
+                    Some("file") => " -r -F",
+                    Some(_) => " -x",
+                    None => "",
+                };
+                out.push_str(&format!(
+                    "complete -c ecc-kernel -l {long}{arg} -d '{description}'\n"
+                ));
+            }
+            out.push_str(&format!(
+                "complete -c ecc-kernel -n '__fish_seen_subcommand_from completion' -l shell -x -a '{shells}'\n"
+            ));
+            let commands: Vec<&str> = COMMANDS.iter().copied().chain(["completion"]).collect();
+            out.push_str(&format!(
+                "complete -c ecc-kernel -a '{}'\n",
+                commands.join(" ")
+            ));
+            out
+        }
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// `{ "input", "output" }` JSON Schemas for a command's handler.
+fn schema_of<I: JsonSchema, O: JsonSchema>(
+    _handler: fn(I) -> Result<O, KernelError>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "input": schemars::schema_for!(I),
+        "output": schemars::schema_for!(O),
+    })
+}
+
+/// Schemas for `--json-schema`; mirrors the handlers in `dispatch`.
+fn command_schema(cmd: &str) -> Option<serde_json::Value> {
+    let schema = match cmd {
+        "worktree.ensure" => schema_of(worktree_ensure),
+        "worktree.remove" => schema_of(worktree_remove),
+        "worktree.list" => schema_of(worktree_list),
+        "worktree.prune" => schema_of(worktree_prune),
+        "worktree.move" => schema_of(worktree_move),
+        "worktree.lock" => schema_of(worktree_lock),
+        "worktree.unlock" => schema_of(worktree_unlock),
+        "worktree.repair" => schema_of(worktree_repair),
+        "worktree.inspect" => schema_of(worktree_inspect),
+        "patch.apply" => schema_of(patch_apply),
+        "patch.generate" => schema_of(patch_generate),
+        "patch.validate" => schema_of(patch_validate),
+        "git.commit_all" => schema_of(commit_all),
+        "git.status" => schema_of(git_status),
+        "git.log" => schema_of(git_log),
+        "git.diff" => schema_of(git_diff),
+        "git.show" => schema_of(git_show),
+        "git.branch.list" => schema_of(git_branch_list),
+        "git.branch.delete" => schema_of(git_branch_delete),
+        "git.branch.rename" => schema_of(git_branch_rename),
+        "git.fetch" => schema_of(git_fetch),
+        "git.push" => schema_of(git_push),
+        "git.merge_base" => schema_of(git_merge_base),
+        "git.cherry_pick" => schema_of(git_cherry_pick),
+        "git.reset" => schema_of(git_reset),
+        "git.stash" => schema_of(git_stash),
+        "git.stash_pop" => schema_of(git_stash_pop),
+        "git.rebase" => schema_of(git_rebase),
+        "git.tag" => schema_of(git_tag),
+        "git.config.get" => schema_of(git_config_get),
+        "git.config.set" => schema_of(git_config_set),
+        "git.rev_parse" => schema_of(git_rev_parse),
+        "git.ls_files" => schema_of(git_ls_files),
+        "git.clean" => schema_of(git_clean),
+        "git.ls_remote" => schema_of(git_ls_remote),
+        "git.describe" => schema_of(git_describe),
+        "git.check_ignore" => schema_of(git_check_ignore),
+        "git.update_ref" => schema_of(git_update_ref),
+        "git.merge" => schema_of(git_merge),
+        "git.submodule.update" => schema_of(git_submodule_update),
+        "git.submodule.list" => schema_of(git_submodule_list),
+        "git.format_patch" => schema_of(git_format_patch),
+        "git.am" => schema_of(git_am),
+        "git.remote.list" => schema_of(git_remote_list),
+        "git.remote.add" => schema_of(git_remote_add),
+        "git.reflog" => schema_of(git_reflog),
+        "git.archive" => schema_of(git_archive),
+        "git.count_objects" => schema_of(git_count_objects),
+        "git.gc" => schema_of(git_gc),
+        "git.init" => schema_of(git_init),
+        "git.bisect" => schema_of(git_bisect),
+        "git.worktree.head" => schema_of(git_worktree_head),
+        "git.diff.cached" => schema_of(git_diff_cached),
+        "git.symbolic_ref" => schema_of(git_symbolic_ref),
+        "git.notes" => schema_of(git_notes),
+        "git.bundle" => schema_of(git_bundle),
+        "git.credential" => schema_of(git_credential),
+        "fs.read" => schema_of(fs_read),
+        "fs.write" => schema_of(fs_write),
+        "fs.atomic_write" => schema_of(fs_atomic_write_cmd),
+        "fs.list" => schema_of(fs_list),
+        "fs.stat" => schema_of(fs_stat),
+        "fs.delete" => schema_of(fs_delete),
+        "fs.copy" => schema_of(fs_copy),
+        "fs.hash" => schema_of(fs_hash),
+        "protocol.version" => schema_of(protocol_version),
+        "repo.info" => schema_of(repo_info),
+        "verify.run" => schema_of(verify_run),
+        "verify.clean" => schema_of(verify_clean),
+        "verify.compare" => schema_of(verify_compare),
+        "verify.safe_name" => schema_of(verify_safe_name),
+        "verify.output_path" => schema_of(verify_output_path_cmd),
+        "batch" => schema_of(batch),
+        _ => return None,
+    };
+    Some(schema)
+}
+
+fn usage() {
+    let cmds = COMMANDS.join("\n  ");
+    eprintln!(
+        r#"ecc-kernel
+
+Usage:
+  ecc-kernel [flags] <command>   (JSON input on stdin; JSON output on stdout)
+  ecc-kernel completion --shell <bash|zsh|fish>
+
+Flags:
+  --input-file <path>    read JSON input from a file instead of stdin
+  --output-file <path>   write the JSON response to a file instead of stdout
+  --pretty               indent the JSON response
+  --timeout-secs <n>     kill spawned commands (and their children) after n seconds
+  --dry-run              report mutating commands instead of running them
+  --json-schema <cmd>    print JSON Schemas for a command's input and output
+  --config-file <path>   JSON defaults: gitBinary, auditLogPath, defaultTimeoutSecs,
+                         maxOutputBytes, allowedRoots (env vars and flags win)
+
+Environment:
+  ECC_GIT_PATH           git executable to use (default: `git` on PATH)
+  ECC_AUDIT_LOG          append a JSON line per command (redacted input, ok, duration) to this file
+  ECC_ALLOWED_ROOTS      `:`-separated roots that every path in a command input must lie under
+
+Commands:
+  {cmds}
+"#
+        ,
+        cmds = cmds
+    );
+}
+
+/// Global flags plus the command name, in any order on the command line.
+#[derive(Debug)]
+struct CliArgs {
+    command: Option<String>,
+    input: IoMode,
+    output: IoMode,
+    pretty: bool,
+    timeoutSecs: Option<u64>,
+    dryRun: bool,
+    configFile: Option<PathBuf>,
+    /// `--shell` for the `completion` sub-command.
+    shell: Option<String>,
+    /// `--json-schema <command>`: print the command's schemas instead of running it.
+    jsonSchema: Option<String>,
+}
+
+fn parse_cli_args(args: &[String]) -> Result<CliArgs, KernelError> {
+    let mut cli = CliArgs {
+        command: None,
+        input: IoMode::Stdio,
+        output: IoMode::Stdio,
+        pretty: false,
+        timeoutSecs: None,
+        dryRun: false,
+        configFile: None,
+        shell: None,
+        jsonSchema: None,
+    };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if f.starts_with("--") => (f, Some(v.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "--pretty" => cli.pretty = true,
+            "--dry-run" => cli.dryRun = true,
+            "--timeout-secs" => {
+                let value = match inline {
+                    Some(v) => v,
+                    None => it.next().cloned().unwrap_or_default(),
+                };
+                let secs = value
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| {
+                        KernelError::InvalidInput(format!(
+                            "--timeout-secs requires a positive integer, got {value:?}"
+                        ))
+                    })?;
+                cli.timeoutSecs = Some(secs);
+            }
+            "--shell" => {
+                let value = match inline {
+                    Some(v) => v,
+                    None => it.next().cloned().unwrap_or_default(),
+                };
+                if !COMPLETION_SHELLS.contains(&value.as_str()) {
+                    return Err(KernelError::InvalidInput(format!(
+                        "--shell must be one of {}, got {value:?}",
+                        COMPLETION_SHELLS.join("|")
+                    )));
+                }
+                cli.shell = Some(value);
+            }
+            "--json-schema" => {
+                let value = match inline {
+                    Some(v) => v,
+                    None => it.next().cloned().unwrap_or_default(),
+                };
+                if value.is_empty() {
+                    return Err(KernelError::InvalidInput(
+                        "--json-schema requires a command".to_string(),
+                    ));
+                }
+                cli.jsonSchema = Some(value);
+            }
+            "--input-file" | "--output-file" | "--config-file" => {
+                let value = match inline {
+                    Some(v) => v,
+                    None => it.next().cloned().ok_or_else(|| {
+                        KernelError::InvalidInput(format!("{flag} requires a path"))
+                    })?,
+                };
+                if value.is_empty() {
+                    return Err(KernelError::InvalidInput(format!("{flag} requires a path")));
+                }
+                if flag == "--config-file" {
+                    cli.configFile = Some(PathBuf::from(value));
+                    continue;
+                }
+                let mode = IoMode::File(PathBuf::from(value));
+                if flag == "--input-file" {
+                    cli.input = mode;
+                } else {
+                    cli.output = mode;
+                }
+            }
+            _ if cli.command.is_none() => cli.command = Some(arg.clone()),
+            _ => {
+                return Err(KernelError::InvalidInput(format!(
+                    "unexpected argument: {arg}"
+                )))
+            }
+        }
+    }
+    Ok(cli)
+}
+
+/// Loads `--config-file` (if any) and applies the global defaults; must run before any git call.
+fn apply_kernel_config(cli: &CliArgs) -> Result<(), KernelError> {
+    if let Some(path) = &cli.configFile {
+        let _ = KERNEL_CONFIG.set(load_kernel_config(path)?);
+    }
+    if let Some(secs) = cli.timeoutSecs.or(kernel_config().defaultTimeoutSecs) {
+        let _ = COMMAND_TIMEOUT.set(std::time::Duration::from_secs(secs));
+    }
+    Ok(())
+}
+
+fn real_main(cli: &CliArgs) -> Result<(), KernelError> {
+    if let Some(name) = &cli.jsonSchema {
+        let schema = command_schema(name)
+            .ok_or_else(|| KernelError::InvalidInput(format!("unknown command: {name}")))?;
+        return write_output_json(&cli.output, &schema);
+    }
+
+    let cmd = match &cli.command {
+        Some(c) => c.clone(),
+        None => {
+            usage();
+            return Err(KernelError::InvalidInput("missing command".to_string()));
+        }
+    };
+
+    if cmd == "--help" || cmd == "-h" {
+        usage();
+        return Ok(());
+    }
+
+    if cmd == "--version" || cmd == "-V" {
+        let out = version_info(find_git_path(), |program, args| {
+            run_cmd(program, args, None)
+        });
+        return write_output_json(&cli.output, &out);
+    }
+
+    if cmd == "completion" {
+        let shell = cli.shell.as_deref().ok_or_else(|| {
+            KernelError::InvalidInput(format!(
+                "completion requires --shell <{}>",
+                COMPLETION_SHELLS.join("|")
+            ))
+        })?;
+        print!("{}", completion_script(shell).unwrap_or_default());
+        return Ok(());
+    }
+
+    if !COMMANDS.contains(&cmd.as_str()) {
+        return Err(KernelError::InvalidInput(format!("unknown command: {cmd}")));
+    }
+    let input: serde_json::Value = read_input_json(&cli.input)?;
+    let allowed_roots = allowed_roots_from(
+        std::env::var_os("ECC_ALLOWED_ROOTS"),
+        &kernel_config().allowedRoots,
+    );
+    let audit_log = env_or_config(
+        std::env::var_os("ECC_AUDIT_LOG"),
+        &kernel_config().auditLogPath,
+    );
+    let result = run_audited(
+        &cmd,
+        input,
+        &allowed_roots,
+        audit_log.as_deref().map(Path::new),
+    );
+    write_output_json(&cli.output, &result?)
+}
+
+/// Runs `cmd` after the allowedRoots check and, with an audit log, records the outcome of
+/// both: a command rejected with `pathTraversal` is audited like any other failure.
+fn run_audited(
+    cmd: &str,
+    input: serde_json::Value,
+    allowed_roots: &[String],
+    audit_log: Option<&Path>,
+) -> Result<serde_json::Value, KernelError> {
+    let started = std::time::Instant::now();
+    let summary = audit_log.map(|_| audit_input_summary(&input));
+    let result = check_allowed_roots(&input, allowed_roots).and_then(|()| dispatch(cmd, input));
+    if let (Some(path), Some(summary)) = (audit_log, summary) {
+        let entry = AuditEntry {
+            timestamp: now_iso(),
+            command: cmd.to_string(),
+            inputSummary: summary,
+            ok: result.is_ok(),
+            errorKind: result.as_ref().err().map(|e| e.kind().to_string()),
+            durationMs: started.elapsed().as_millis() as u64,
+            pid: std::process::id(),
+        };
+        if let Err(e) = write_audit_entry(path, &entry) {
+            eprintln!("ecc-kernel: {e}");
+        }
+    }
+    result
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (output, result) = match parse_cli_args(&args) {
+        Ok(cli) => {
+            OUTPUT_CONFIG.with(|c| c.set(OutputConfig { pretty: cli.pretty }));
+            DRY_RUN.with(|d| d.set(cli.dryRun));
+            (
+                cli.output.clone(),
+                apply_kernel_config(&cli).and_then(|()| real_main(&cli)),
+            )
+        }
+        Err(err) => (IoMode::Stdio, Err(err)),
+    };
+    match result {
+        Ok(()) => ExitCode::from(0),
+        Err(err) => {
+            let missing_command =
+                matches!(&err, KernelError::InvalidInput(m) if m == "missing command");
+            if !missing_command && write_response(&output, &err.to_response()).is_err() {
+                eprintln!("{err}");
+            }
+            ExitCode::from(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmp_dir(prefix: &str) -> PathBuf {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!(
+            "{}-{}-{}",
+            prefix,
+            std::process::id(),
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        dir
+    }
+
+    fn git(repo: &Path, args: &[&str]) -> Result<CmdOut, String> {
+        let mut v: Vec<String> = Vec::new();
+        v.push("-C".to_string());
+        v.push(repo.display().to_string());
+        for a in args {
             v.push(a.to_string());
         }
-        run_git(&v, None)
+        run_git(&v, None).map_err(|e| e.to_string())
+    }
+
+    fn init_git_repo(repo: &Path) -> Result<(), String> {
+        fs::create_dir_all(repo).map_err(|e| format!("mkdir failed: {e}"))?;
+        let out = git(repo, &["init"])?;
+        if !out.ok {
+            return Err(out.stderr);
+        }
+
+        // Ensure commits work in CI.
+        let _ = git(repo, &["config", "user.email", "ecc@example.com"])?;
+        let _ = git(repo, &["config", "user.name", "ECC"])?;
+
+        fs::write(repo.join("base.txt"), "base\n").map_err(|e| format!("write failed: {e}"))?;
+        let out_add = git(repo, &["add", "-A"])?;
+        if !out_add.ok {
+            return Err(out_add.stderr);
+        }
+        let out_commit = git(repo, &["commit", "-m", "init"])?;
+        if !out_commit.ok {
+            return Err(out_commit.stderr);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn protocol_version_has_required_fields() {
+        let out = protocol_version(ProtocolVersionIn {}).unwrap();
+        assert_eq!(out.version, 1);
+        assert_eq!(out.protocol, PROTOCOL_VERSION);
+        assert!(!out.kernelVersion.trim().is_empty());
+
+        let cmds: std::collections::BTreeSet<String> = out.commands.into_iter().collect();
+        for c in COMMANDS.iter() {
+            assert!(cmds.contains(*c), "missing command: {c}");
+        }
+    }
+
+    #[test]
+    fn repo_info_returns_repo_details_and_cleanliness() {
+        let dir = tmp_dir("ecc-kernel-test-repo");
+        let res = init_git_repo(&dir);
+        assert!(res.is_ok(), "init_git_repo failed: {:?}", res.err());
+
+        let info = repo_info(RepoInfoIn {
+            cwd: dir.display().to_string(),
+        })
+        .unwrap();
+
+        assert_eq!(info.version, 1);
+        let got_root = info.repoRoot.clone().unwrap();
+        let expected_abs = fs::canonicalize(&dir).unwrap();
+        let got_abs = fs::canonicalize(std::path::Path::new(&got_root)).unwrap();
+        assert_eq!(got_abs, expected_abs);
+        assert!(!info.branch.trim().is_empty());
+        assert_eq!(info.sha.len(), 40);
+        assert!(info.clean);
+
+        // Modifying a tracked file should mark repo as dirty.
+        fs::write(dir.join("base.txt"), "changed\n").unwrap();
+        let info2 = repo_info(RepoInfoIn {
+            cwd: dir.display().to_string(),
+        })
+        .unwrap();
+        assert!(!info2.clean);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repo_info_outside_repo_is_null() {
+        let dir = tmp_dir("ecc-kernel-test-norepo");
+        fs::create_dir_all(&dir).unwrap();
+
+        let info = repo_info(RepoInfoIn {
+            cwd: dir.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(info.version, 1);
+        assert!(info.repoRoot.is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn canon(p: &str) -> PathBuf {
+        fs::canonicalize(p).unwrap()
+    }
+
+    #[test]
+    fn worktree_list_reports_bare_repo() {
+        let dir = tmp_dir("ecc-kernel-test-bare");
+        fs::create_dir_all(&dir).unwrap();
+        assert!(git(&dir, &["init", "--bare"]).unwrap().ok);
+
+        let out = worktree_list(WorktreeListIn {
+            repoRoot: dir.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(out.worktrees.len(), 1);
+        assert!(out.worktrees[0].isMain);
+        assert!(out.worktrees[0].isBare);
+        assert!(out.worktrees[0].branch.is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_list_reports_linked_and_locked_worktrees() {
+        let dir = tmp_dir("ecc-kernel-test-wtlist");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt1 = tmp_dir("ecc-kernel-test-wtlist-a");
+        let wt2 = tmp_dir("ecc-kernel-test-wtlist-b");
+        for (wt, branch) in [(&wt1, "feature-a"), (&wt2, "feature-b")] {
+            worktree_ensure(WorktreeEnsureIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: wt.display().to_string(),
+                branch: branch.to_string(),
+                baseSha: head.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        assert!(
+            git(&dir, &["worktree", "lock", &wt2.display().to_string()])
+                .unwrap()
+                .ok
+        );
+
+        let out = worktree_list(WorktreeListIn {
+            repoRoot: dir.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(out.worktrees.len(), 3);
+        assert!(out.worktrees[0].isMain);
+        assert!(!out.worktrees[0].isBare);
+
+        let a = &out.worktrees[1];
+        assert_eq!(canon(&a.path), canon(&wt1.display().to_string()));
+        assert_eq!(a.branch.as_deref(), Some("feature-a"));
+        assert_eq!(a.head, head);
+        assert!(!a.isMain);
+        assert!(!a.isLocked);
+
+        let b = &out.worktrees[2];
+        assert_eq!(b.branch.as_deref(), Some("feature-b"));
+        assert!(b.isLocked);
+
+        let _ = git(&dir, &["worktree", "unlock", &wt2.display().to_string()]);
+        let _ = fs::remove_dir_all(&wt1);
+        let _ = fs::remove_dir_all(&wt2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_prune_dry_run_reports_without_mutating() {
+        let dir = tmp_dir("ecc-kernel-test-prune");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-prune-wt");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "stale".to_string(),
+            baseSha: head,
+            ..Default::default()
+        })
+        .unwrap();
+        fs::remove_dir_all(&wt).unwrap();
+
+        let dry = worktree_prune(WorktreePruneIn {
+            repoRoot: dir.display().to_string(),
+            dryRun: true,
+            expire: None,
+        })
+        .unwrap();
+        assert!(dry.ok);
+        assert_eq!(dry.prunedPaths.len(), 1);
+        assert!(dry.prunedPaths[0].starts_with("worktrees/"));
+        let listed = worktree_list(WorktreeListIn {
+            repoRoot: dir.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(listed.worktrees.len(), 2);
+
+        let real = worktree_prune(WorktreePruneIn {
+            repoRoot: dir.display().to_string(),
+            dryRun: false,
+            expire: None,
+        })
+        .unwrap();
+        assert_eq!(real.prunedPaths, dry.prunedPaths);
+        let listed = worktree_list(WorktreeListIn {
+            repoRoot: dir.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(listed.worktrees.len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_move_keeps_branch_and_rejects_existing_destination() {
+        let dir = tmp_dir("ecc-kernel-test-move");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-move-src");
+        let dst = tmp_dir("ecc-kernel-test-move-dst");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "movable".to_string(),
+            baseSha: head.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let out = worktree_move(WorktreeMoveIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            newPath: dst.display().to_string(),
+        })
+        .unwrap();
+        assert_eq!(out.newPath, dst.display().to_string());
+        assert!(!wt.exists());
+        assert_eq!(
+            git(&dst, &["branch", "--show-current"]).unwrap().stdout,
+            "movable"
+        );
+        assert_eq!(git(&dst, &["rev-parse", "HEAD"]).unwrap().stdout, head);
+
+        // Moving back onto an existing directory is refused before git runs.
+        fs::create_dir_all(&wt).unwrap();
+        let err = worktree_move(WorktreeMoveIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: dst.display().to_string(),
+            newPath: wt.display().to_string(),
+        })
+        .err()
+        .unwrap();
+        assert!(
+            err.to_string().contains("destination already exists"),
+            "{err}"
+        );
+
+        let _ = fs::remove_dir_all(&wt);
+        let _ = fs::remove_dir_all(&dst);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_lock_and_unlock_are_idempotent() {
+        let dir = tmp_dir("ecc-kernel-test-lock");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-lock-wt");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "lockable".to_string(),
+            baseSha: head,
+            ..Default::default()
+        })
+        .unwrap();
+        let is_locked = || {
+            worktree_list(WorktreeListIn {
+                repoRoot: dir.display().to_string(),
+            })
+            .unwrap()
+            .worktrees[1]
+                .isLocked
+        };
+
+        let lock = || {
+            worktree_lock(WorktreeLockIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: wt.display().to_string(),
+                reason: Some("agent busy".to_string()),
+            })
+            .unwrap()
+        };
+        assert!(!lock().alreadyLocked);
+        assert!(is_locked());
+        assert!(lock().alreadyLocked);
+
+        let unlock = || {
+            worktree_unlock(WorktreeUnlockIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: wt.display().to_string(),
+            })
+            .unwrap()
+        };
+        assert!(unlock().wasLocked);
+        assert!(!is_locked());
+        assert!(!unlock().wasLocked);
+
+        let _ = fs::remove_dir_all(&wt);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_status_reports_untracked_renames_and_divergence() {
+        let dir = tmp_dir("ecc-kernel-test-status");
+        init_git_repo(&dir).unwrap();
+        let base = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+
+        // Upstream gets one commit main does not have, and vice versa.
+        let tree = git(&dir, &["rev-parse", "HEAD^{tree}"]).unwrap().stdout;
+        let other = git(&dir, &["commit-tree", &tree, "-p", &base, "-m", "other"])
+            .unwrap()
+            .stdout;
+        assert!(
+            git(&dir, &["update-ref", "refs/heads/upstream", &other])
+                .unwrap()
+                .ok
+        );
+        assert!(
+            git(&dir, &["commit", "--allow-empty", "-m", "ahead"])
+                .unwrap()
+                .ok
+        );
+        assert!(
+            git(&dir, &["branch", "--set-upstream-to=upstream"])
+                .unwrap()
+                .ok
+        );
+
+        assert!(
+            git(&dir, &["mv", "base.txt", "renamed file.txt"])
+                .unwrap()
+                .ok
+        );
+        fs::write(dir.join("new.txt"), "new\n").unwrap();
+
+        let out = git_status(GitStatusIn {
+            repoRoot: dir.display().to_string(),
+            short: false,
+            branch: true,
+            untrackedFiles: None,
+        })
+        .unwrap();
+        assert!(out.branch.is_some());
+        assert_eq!(out.ahead, Some(1));
+        assert_eq!(out.behind, Some(1));
+
+        let renamed = out.entries.iter().find(|e| e.indexStatus == 'R').unwrap();
+        assert_eq!(renamed.path, "renamed file.txt");
+        assert_eq!(renamed.origPath.as_deref(), Some("base.txt"));
+        let untracked = out.entries.iter().find(|e| e.indexStatus == '?').unwrap();
+        assert_eq!(untracked.path, "new.txt");
+
+        let short = git_status(GitStatusIn {
+            repoRoot: dir.display().to_string(),
+            short: true,
+            branch: true,
+            untrackedFiles: None,
+        });
+        assert_eq!(short.err().unwrap().kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_log_parses_multiline_bodies_and_validates_input() {
+        let dir = tmp_dir("ecc-kernel-test-log");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        assert!(git(&dir, &["add", "a.txt"]).unwrap().ok);
+        assert!(
+            git(&dir, &["commit", "-m", "add a\n\nfirst line\nsecond line"])
+                .unwrap()
+                .ok
+        );
+
+        let out = git_log(GitLogIn {
+            repoRoot: dir.display().to_string(),
+            ref_: String::new(),
+            maxCount: None,
+            paths: Vec::new(),
+            follow: false,
+        })
+        .unwrap();
+        assert_eq!(out.commits.len(), 2);
+        assert_eq!(out.commits[0].subject, "add a");
+        assert_eq!(out.commits[0].body, "first line\nsecond line");
+        assert_eq!(out.commits[0].parentShas, vec![out.commits[1].sha.clone()]);
+        assert_eq!(out.commits[0].authorEmail, "ecc@example.com");
+        assert!(out.commits[1].parentShas.is_empty());
+
+        let followed = git_log(GitLogIn {
+            repoRoot: dir.display().to_string(),
+            ref_: "HEAD".to_string(),
+            maxCount: Some(5),
+            paths: vec!["base.txt".to_string()],
+            follow: true,
+        })
+        .unwrap();
+        assert_eq!(followed.commits.len(), 1);
+        assert_eq!(followed.commits[0].subject, "init");
+
+        let zero = git_log(GitLogIn {
+            repoRoot: dir.display().to_string(),
+            ref_: String::new(),
+            maxCount: Some(0),
+            paths: Vec::new(),
+            follow: false,
+        });
+        assert!(zero.is_err());
+        let follow_many = git_log(GitLogIn {
+            repoRoot: dir.display().to_string(),
+            ref_: String::new(),
+            maxCount: None,
+            paths: Vec::new(),
+            follow: true,
+        });
+        assert!(follow_many.is_err());
+        let out_file = dir.join("injected.txt");
+        let injected = git_log(GitLogIn {
+            repoRoot: dir.display().to_string(),
+            ref_: format!("--output={}", out_file.display()),
+            maxCount: None,
+            paths: Vec::new(),
+            follow: false,
+        });
+        assert!(matches!(injected, Err(KernelError::InvalidInput(_))));
+        assert!(!out_file.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_diff_returns_patch_stats_and_names() {
+        let dir = tmp_dir("ecc-kernel-test-diff");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("base.txt"), "base\nmore\n").unwrap();
+        let input = |stat: bool, name_only: bool| GitDiffIn {
+            repoRoot: dir.display().to_string(),
+            base: "HEAD".to_string(),
+            head: None,
+            paths: Vec::new(),
+            stat,
+            nameOnly: name_only,
+            unified: None,
+        };
+
+        let patch = git_diff(input(false, false)).unwrap().patch.unwrap();
+        assert!(patch.starts_with("diff --git a/base.txt b/base.txt"));
+        assert!(patch.ends_with("+more\n"));
+
+        let stats = git_diff(input(true, false)).unwrap();
+        assert!(stats.patch.is_none());
+        let stats = stats.stats.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].path, "base.txt");
+        assert_eq!(stats[0].insertions, 1);
+        assert_eq!(stats[0].deletions, 0);
+
+        let names = git_diff(input(false, true)).unwrap().files.unwrap();
+        assert_eq!(names, vec!["base.txt".to_string()]);
+
+        assert!(git_diff(input(true, true)).is_err());
+
+        let out_file = dir.join("injected.txt");
+        let injected = format!("--output={}", out_file.display());
+        let bad_base = git_diff(GitDiffIn {
+            base: injected.clone(),
+            ..input(false, false)
+        });
+        assert!(matches!(bad_base, Err(KernelError::InvalidInput(_))));
+        let bad_head = git_diff(GitDiffIn {
+            head: Some("--ext-diff".to_string()),
+            ..input(false, false)
+        });
+        assert!(matches!(bad_head, Err(KernelError::InvalidInput(_))));
+        assert!(!out_file.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_diff_patch_keeps_trailing_blank_context_lines() {
+        let dir = tmp_dir("ecc-kernel-test-diff-blank-context");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("blank.txt"), "a\nb\nc\n\n\n").unwrap();
+        assert!(git(&dir, &["add", "blank.txt"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-m", "blank"]).unwrap().ok);
+        fs::write(dir.join("blank.txt"), "A\nb\nc\n\n\n").unwrap();
+
+        let patch = git_diff(GitDiffIn {
+            repoRoot: dir.display().to_string(),
+            base: "HEAD".to_string(),
+            head: None,
+            paths: Vec::new(),
+            stat: false,
+            nameOnly: false,
+            unified: None,
+        })
+        .unwrap()
+        .patch
+        .unwrap();
+        assert!(patch.ends_with(" c\n \n"), "{patch:?}");
+
+        let patch_path = dir.join("blank.diff");
+        fs::write(&patch_path, &patch).unwrap();
+        assert!(git(&dir, &["checkout", "--", "blank.txt"]).unwrap().ok);
+        let check = git(&dir, &["apply", "--check", patch_path.to_str().unwrap()]).unwrap();
+        assert!(check.ok, "{}", check.stderr);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_show_reads_historical_blob_and_rejects_bad_names() {
+        let dir = tmp_dir("ecc-kernel-test-show");
+        init_git_repo(&dir).unwrap();
+        let sha = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        fs::write(dir.join("base.txt"), "changed\n").unwrap();
+
+        let blob = git_show(GitShowIn {
+            repoRoot: dir.display().to_string(),
+            object: format!("{}:base.txt", &sha[..8]),
+            format: String::new(),
+        })
+        .unwrap();
+        assert_eq!(blob.objectType, "blob");
+        assert_eq!(blob.content, "base\n");
+
+        let commit = git_show(GitShowIn {
+            repoRoot: dir.display().to_string(),
+            object: "HEAD~0".to_string(),
+            format: "oneline".to_string(),
+        })
+        .unwrap();
+        assert_eq!(commit.objectType, "commit");
+        assert!(commit.content.starts_with(&sha));
+
+        for bad in ["", "--output=/tmp/x", "HEAD..main", "a b", "HEAD:"] {
+            assert!(!is_valid_object_name(bad), "{bad}");
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_branch_list_reports_upstreams_and_remote_branches() {
+        let empty = tmp_dir("ecc-kernel-test-branches-empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(git(&empty, &["init"]).unwrap().ok);
+        let out = git_branch_list(GitBranchListIn {
+            repoRoot: empty.display().to_string(),
+            remote: false,
+            all: false,
+            contains: None,
+        })
+        .unwrap();
+        assert!(out.branches.is_empty());
+
+        let origin = tmp_dir("ecc-kernel-test-branches-origin");
+        init_git_repo(&origin).unwrap();
+        let current = git(&origin, &["branch", "--show-current"]).unwrap().stdout;
+        let clone = tmp_dir("ecc-kernel-test-branches-clone");
+        let cloned = run_git(
+            &[
+                "clone".to_string(),
+                origin.display().to_string(),
+                clone.display().to_string(),
+            ],
+            None,
+        )
+        .unwrap();
+        assert!(cloned.ok, "{}", cloned.stderr);
+        assert!(git(&clone, &["branch", "topic"]).unwrap().ok);
+
+        let local = git_branch_list(GitBranchListIn {
+            repoRoot: clone.display().to_string(),
+            remote: false,
+            all: false,
+            contains: None,
+        })
+        .unwrap();
+        assert_eq!(local.branches.len(), 2);
+        let main = local.branches.iter().find(|b| b.name == current).unwrap();
+        assert!(main.isCurrent);
+        assert!(!main.isRemote);
+        assert_eq!(main.upstream, Some(format!("origin/{current}")));
+        assert_eq!(main.head.len(), 40);
+        let topic = local.branches.iter().find(|b| b.name == "topic").unwrap();
+        assert!(!topic.isCurrent);
+        assert!(topic.upstream.is_none());
+
+        let remote = git_branch_list(GitBranchListIn {
+            repoRoot: clone.display().to_string(),
+            remote: true,
+            all: false,
+            contains: Some(main.head.clone()),
+        })
+        .unwrap();
+        assert_eq!(remote.branches.len(), 1);
+        assert_eq!(remote.branches[0].name, format!("origin/{current}"));
+        assert!(remote.branches[0].isRemote);
+        let option = git_branch_list(GitBranchListIn {
+            repoRoot: clone.display().to_string(),
+            remote: false,
+            all: false,
+            contains: Some("--delete".to_string()),
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&empty);
+        let _ = fs::remove_dir_all(&origin);
+        let _ = fs::remove_dir_all(&clone);
+    }
+
+    #[test]
+    fn git_branch_delete_requires_force_for_unmerged_branches() {
+        let dir = tmp_dir("ecc-kernel-test-branch-delete");
+        init_git_repo(&dir).unwrap();
+        let base = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let tree = git(&dir, &["rev-parse", "HEAD^{tree}"]).unwrap().stdout;
+        let unmerged = git(&dir, &["commit-tree", &tree, "-p", &base, "-m", "wip"])
+            .unwrap()
+            .stdout;
+        assert!(git(&dir, &["branch", "wip", &unmerged]).unwrap().ok);
+        let input = |force: bool| GitBranchDeleteIn {
+            repoRoot: dir.display().to_string(),
+            branch: "wip".to_string(),
+            force,
+            deleteRemoteTracking: false,
+        };
+
+        assert!(git_branch_delete(input(false)).is_err());
+        assert!(branch_exists(&dir, "wip"));
+        let out = git_branch_delete(input(true)).unwrap();
+        assert_eq!(out.deletedRef, "refs/heads/wip");
+        assert!(!branch_exists(&dir, "wip"));
+
+        let err = git_branch_delete(input(true)).err().unwrap();
+        assert!(matches!(err, KernelError::NotFound(_)), "{err}");
+        assert_eq!(err.to_string(), "branch not found: wip");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_branch_rename_refuses_or_forces_over_existing_branch() {
+        let dir = tmp_dir("ecc-kernel-test-branch-rename");
+        init_git_repo(&dir).unwrap();
+        let base = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let tree = git(&dir, &["rev-parse", "HEAD^{tree}"]).unwrap().stdout;
+        let other = git(&dir, &["commit-tree", &tree, "-p", &base, "-m", "other"])
+            .unwrap()
+            .stdout;
+        assert!(git(&dir, &["branch", "old", &other]).unwrap().ok);
+        assert!(git(&dir, &["branch", "taken"]).unwrap().ok);
+        let input = |force: bool| GitBranchRenameIn {
+            repoRoot: dir.display().to_string(),
+            oldName: "old".to_string(),
+            newName: "taken".to_string(),
+            force,
+        };
+
+        let err = git_branch_rename(input(false)).err().unwrap();
+        assert!(err.to_string().contains("already exists"), "{err}");
+
+        let out = git_branch_rename(input(true)).unwrap();
+        assert_eq!(out.newName, "taken");
+        assert!(out.activeWorktrees.is_empty());
+        assert!(!branch_exists(&dir, "old"));
+        assert_eq!(git(&dir, &["rev-parse", "taken"]).unwrap().stdout, other);
+
+        // `git branch` parses options anywhere, so `-D` as the new name would delete `taken`.
+        let option = git_branch_rename(GitBranchRenameIn {
+            oldName: "taken".to_string(),
+            newName: "-D".to_string(),
+            ..input(false)
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+        assert!(branch_exists(&dir, "taken"));
+
+        // Renaming the checked-out branch reports the worktree using it.
+        let current = git(&dir, &["branch", "--show-current"]).unwrap().stdout;
+        let out = git_branch_rename(GitBranchRenameIn {
+            repoRoot: dir.display().to_string(),
+            oldName: current,
+            newName: "renamed-main".to_string(),
+            force: false,
+        })
+        .unwrap();
+        assert_eq!(out.activeWorktrees.len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn clone_repo(origin: &Path, prefix: &str) -> PathBuf {
+        let clone = tmp_dir(prefix);
+        let out = run_git(
+            &[
+                "clone".to_string(),
+                origin.display().to_string(),
+                clone.display().to_string(),
+            ],
+            None,
+        )
+        .unwrap();
+        assert!(out.ok, "{}", out.stderr);
+        let _ = git(&clone, &["config", "user.email", "ecc@example.com"]);
+        let _ = git(&clone, &["config", "user.name", "ECC"]);
+        clone
+    }
+
+    #[test]
+    fn git_fetch_reports_updated_and_new_refs() {
+        let origin = tmp_dir("ecc-kernel-test-fetch-origin");
+        init_git_repo(&origin).unwrap();
+        let clone = clone_repo(&origin, "ecc-kernel-test-fetch-clone");
+        let branch = git(&origin, &["branch", "--show-current"]).unwrap().stdout;
+        let old = git(&origin, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert!(
+            git(&origin, &["commit", "--allow-empty", "-m", "next"])
+                .unwrap()
+                .ok
+        );
+        assert!(git(&origin, &["branch", "feature"]).unwrap().ok);
+        let new = git(&origin, &["rev-parse", "HEAD"]).unwrap().stdout;
+
+        let out = git_fetch(GitFetchIn {
+            repoRoot: clone.display().to_string(),
+            remote: None,
+            refspecs: Vec::new(),
+            prune: true,
+            depth: None,
+            tags: false,
+        })
+        .unwrap();
+        let updated = out
+            .updatedRefs
+            .iter()
+            .find(|r| r.refName == format!("origin/{branch}"))
+            .unwrap();
+        assert_eq!(updated.oldSha, old);
+        assert_eq!(updated.newSha, new);
+        let created = out
+            .updatedRefs
+            .iter()
+            .find(|r| r.refName == "origin/feature")
+            .unwrap();
+        assert!(created.oldSha.is_empty());
+        assert_eq!(created.newSha, new);
+
+        let bad_depth = git_fetch(GitFetchIn {
+            repoRoot: clone.display().to_string(),
+            remote: None,
+            refspecs: Vec::new(),
+            prune: false,
+            depth: Some(0),
+            tags: false,
+        });
+        assert!(bad_depth.is_err());
+
+        // Option-like remotes and refspecs would let the input pick the upload-pack command.
+        let marker = clone.join("pwned");
+        let upload_pack = format!("--upload-pack=touch {}; git-upload-pack", marker.display());
+        for (remote, refspec) in [
+            (upload_pack.clone(), "HEAD".to_string()),
+            (origin.display().to_string(), upload_pack.clone()),
+        ] {
+            let err = git_fetch(GitFetchIn {
+                repoRoot: clone.display().to_string(),
+                remote: Some(remote),
+                refspecs: vec![refspec],
+                prune: false,
+                depth: None,
+                tags: false,
+            })
+            .err()
+            .unwrap();
+            assert_eq!(err.kind(), "invalidInput");
+        }
+        assert!(!marker.exists());
+
+        let _ = fs::remove_dir_all(&origin);
+        let _ = fs::remove_dir_all(&clone);
+    }
+
+    #[test]
+    fn git_push_sets_upstream_and_honours_dry_run() {
+        let seed = tmp_dir("ecc-kernel-test-push-seed");
+        init_git_repo(&seed).unwrap();
+        let bare = tmp_dir("ecc-kernel-test-push-bare");
+        let out = run_git(
+            &[
+                "clone".to_string(),
+                "--bare".to_string(),
+                seed.display().to_string(),
+                bare.display().to_string(),
+            ],
+            None,
+        )
+        .unwrap();
+        assert!(out.ok, "{}", out.stderr);
+        let clone = clone_repo(&bare, "ecc-kernel-test-push-clone");
+        assert!(git(&clone, &["branch", "feature"]).unwrap().ok);
+        let input = |dry_run: bool| GitPushIn {
+            repoRoot: clone.display().to_string(),
+            remote: None,
+            branch: "feature".to_string(),
+            setUpstream: true,
+            forceWithLease: true,
+            forceWithLeaseExpect: None,
+            dryRun: dry_run,
+        };
+
+        let dry = git_push(input(true)).unwrap();
+        assert!(dry.ok);
+        assert_eq!(dry.remoteRef, "refs/heads/feature");
+        assert!(!ref_exists(&bare, "refs/heads/feature"));
+
+        let real = git_push(input(false)).unwrap();
+        assert!(real.ok);
+        assert_eq!(real.remote, "origin");
+        assert_eq!(real.localRef, "refs/heads/feature");
+        assert!(ref_exists(&bare, "refs/heads/feature"));
+        let upstream = git(&clone, &["config", "branch.feature.remote"])
+            .unwrap()
+            .stdout;
+        assert_eq!(upstream, "origin");
+
+        let marker = clone.join("pwned");
+        let receive_pack = format!(
+            "--receive-pack=touch {}; git-receive-pack",
+            marker.display()
+        );
+        let bad_remote = git_push(GitPushIn {
+            remote: Some(receive_pack.clone()),
+            branch: bare.display().to_string(),
+            ..input(false)
+        });
+        assert_eq!(bad_remote.err().unwrap().kind(), "invalidInput");
+        let bad_branch = git_push(GitPushIn {
+            branch: receive_pack,
+            ..input(false)
+        });
+        assert_eq!(bad_branch.err().unwrap().kind(), "invalidInput");
+        assert!(!marker.exists());
+
+        let _ = fs::remove_dir_all(&seed);
+        let _ = fs::remove_dir_all(&bare);
+        let _ = fs::remove_dir_all(&clone);
+    }
+
+    #[test]
+    fn git_merge_base_handles_diverged_criss_cross_and_unrelated_histories() {
+        let dir = tmp_dir("ecc-kernel-test-merge-base");
+        init_git_repo(&dir).unwrap();
+        let base = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let tree = git(&dir, &["rev-parse", "HEAD^{tree}"]).unwrap().stdout;
+        let commit = |parents: &[&str], msg: &str| {
+            let mut args = vec!["commit-tree", tree.as_str()];
+            for p in parents {
+                args.push("-p");
+                args.push(p);
+            }
+            args.push("-m");
+            args.push(msg);
+            git(&dir, &args).unwrap().stdout
+        };
+        let x1 = commit(&[&base], "x1");
+        let y1 = commit(&[&base], "y1");
+        let x2 = commit(&[&x1, &y1], "x2");
+        let y2 = commit(&[&y1, &x1], "y2");
+        let orphan = commit(&[], "orphan");
+        let merge_base = |c1: &str, c2: &str, all: bool| {
+            git_merge_base(GitMergeBaseIn {
+                repoRoot: dir.display().to_string(),
+                commit1: c1.to_string(),
+                commit2: c2.to_string(),
+                all,
+            })
+            .unwrap()
+            .bases
+        };
+
+        assert_eq!(merge_base(&x1, &y1, false), vec![base.clone()]);
+        assert_eq!(merge_base(&x1, &x1, false), vec![x1.clone()]);
+
+        let mut crossed = merge_base(&x2, &y2, true);
+        crossed.sort();
+        let mut expected = vec![x1.clone(), y1.clone()];
+        expected.sort();
+        assert_eq!(crossed, expected);
+        assert_eq!(merge_base(&x2, &y2, false).len(), 1);
+
+        // An octopus merge of a, b and c shares both a and b with a merge of a and b.
+        let a = commit(&[&base], "a");
+        let b = commit(&[&base], "b");
+        let c = commit(&[&base], "c");
+        let octopus = commit(&[&a, &b, &c], "octopus");
+        let ab = commit(&[&a, &b], "ab");
+        let mut octo_bases = merge_base(&octopus, &ab, true);
+        octo_bases.sort();
+        let mut expected = vec![a.clone(), b.clone()];
+        expected.sort();
+        assert_eq!(octo_bases, expected);
+        assert_eq!(merge_base(&octopus, &c, true), vec![c.clone()]);
+
+        assert!(merge_base(&base, &orphan, false).is_empty());
+        assert!(git_merge_base(GitMergeBaseIn {
+            repoRoot: dir.display().to_string(),
+            commit1: "does-not-exist".to_string(),
+            commit2: base.clone(),
+            all: false,
+        })
+        .is_err());
+        let option = git_merge_base(GitMergeBaseIn {
+            repoRoot: dir.display().to_string(),
+            commit1: "--is-ancestor".to_string(),
+            commit2: base,
+            all: false,
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_cherry_pick_grafts_commits_and_reports_conflicts() {
+        let dir = tmp_dir("ecc-kernel-test-cherry-pick");
+        init_git_repo(&dir).unwrap();
+        let main = git(&dir, &["branch", "--show-current"]).unwrap().stdout;
+        assert!(git(&dir, &["checkout", "-q", "-b", "topic"]).unwrap().ok);
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        assert!(git(&dir, &["add", "-A"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "add a"]).unwrap().ok);
+        let pick_a = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        fs::write(dir.join("base.txt"), "topic\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "edit base"]).unwrap().ok);
+        let pick_base = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert!(git(&dir, &["checkout", "-q", &main]).unwrap().ok);
+
+        let out = git_cherry_pick(GitCherryPickIn {
+            repoRoot: dir.display().to_string(),
+            commits: vec![pick_a],
+            noCommit: false,
+            mainline: None,
+            allowEmpty: false,
+        })
+        .unwrap();
+        assert_eq!(out.newCommits.len(), 1);
+        assert_eq!(
+            out.newCommits[0],
+            git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout
+        );
+        assert!(dir.join("a.txt").exists());
+
+        let option = git_cherry_pick(GitCherryPickIn {
+            repoRoot: dir.display().to_string(),
+            commits: vec!["--quit".to_string()],
+            noCommit: false,
+            mainline: None,
+            allowEmpty: false,
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+
+        fs::write(dir.join("base.txt"), "main\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "diverge"]).unwrap().ok);
+        let err = git_cherry_pick(GitCherryPickIn {
+            repoRoot: dir.display().to_string(),
+            commits: vec![pick_base],
+            noCommit: false,
+            mainline: None,
+            allowEmpty: false,
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("conflicts"), "{err}");
+        assert!(err.to_string().contains("- base.txt"), "{err}");
+
+        let _ = git(&dir, &["cherry-pick", "--abort"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_reset_moves_head_and_validates_mode() {
+        let dir = tmp_dir("ecc-kernel-test-reset");
+        init_git_repo(&dir).unwrap();
+        let first = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        fs::write(dir.join("base.txt"), "second\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "second"]).unwrap().ok);
+        let second = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let reset = |mode: Option<&str>, paths: Vec<String>| {
+            git_reset(GitResetIn {
+                repoRoot: dir.display().to_string(),
+                target: first.clone(),
+                mode: mode.map(|m| m.to_string()),
+                paths,
+            })
+        };
+
+        assert!(reset(Some("bogus"), Vec::new()).is_err());
+        assert!(reset(Some("hard"), vec!["base.txt".to_string()]).is_err());
+        let option = git_reset(GitResetIn {
+            repoRoot: dir.display().to_string(),
+            target: "--hard".to_string(),
+            mode: None,
+            paths: Vec::new(),
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+
+        let out = reset(Some("hard"), Vec::new()).unwrap();
+        assert_eq!(out.previousHead, second);
+        assert_eq!(out.newHead, first);
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "base\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_stash_round_trips_and_reports_pop_conflicts() {
+        let dir = tmp_dir("ecc-kernel-test-stash");
+        init_git_repo(&dir).unwrap();
+        let stash = |untracked: bool| {
+            git_stash(GitStashIn {
+                repoRoot: dir.display().to_string(),
+                message: Some("wip".to_string()),
+                includeUntracked: untracked,
+                keepIndex: false,
+            })
+        };
+        let pop = || {
+            git_stash_pop(GitStashPopIn {
+                repoRoot: dir.display().to_string(),
+                index: None,
+                reinstateIndex: false,
+            })
+            .unwrap()
+        };
+
+        assert!(stash(false).is_err());
+
+        fs::write(dir.join("base.txt"), "stashed\n").unwrap();
+        fs::write(dir.join("new.txt"), "untracked\n").unwrap();
+        assert_eq!(stash(true).unwrap().stashRef, "stash@{0}");
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "base\n");
+        assert!(!dir.join("new.txt").exists());
+
+        let out = pop();
+        assert!(out.ok);
+        assert!(out.conflicts.is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.join("base.txt")).unwrap(),
+            "stashed\n"
+        );
+
+        assert!(stash(false).is_ok());
+        fs::write(dir.join("base.txt"), "committed\n").unwrap();
+        assert!(
+            git(&dir, &["commit", "-q", "-am", "conflicting"])
+                .unwrap()
+                .ok
+        );
+        let out = pop();
+        assert!(!out.ok);
+        assert_eq!(out.conflicts, vec!["base.txt".to_string()]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_rebase_replays_commits_and_exposes_conflicts() {
+        let dir = tmp_dir("ecc-kernel-test-rebase");
+        init_git_repo(&dir).unwrap();
+        let main = git(&dir, &["branch", "--show-current"]).unwrap().stdout;
+        assert!(git(&dir, &["checkout", "-q", "-b", "topic"]).unwrap().ok);
+        fs::write(dir.join("topic.txt"), "topic\n").unwrap();
+        assert!(git(&dir, &["add", "-A"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "topic"]).unwrap().ok);
+        assert!(git(&dir, &["checkout", "-q", &main]).unwrap().ok);
+        fs::write(dir.join("main.txt"), "main\n").unwrap();
+        assert!(git(&dir, &["add", "-A"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "main"]).unwrap().ok);
+        let main_head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let rebase = |upstream: &str, branch: Option<&str>, operation: Option<&str>| {
+            git_rebase(GitRebaseIn {
+                repoRoot: dir.display().to_string(),
+                upstream: upstream.to_string(),
+                branch: branch.map(|b| b.to_string()),
+                onto: None,
+                interactive: false,
+                autosquash: false,
+                operation: operation.map(|o| o.to_string()),
+            })
+        };
+
+        let out = rebase(&main, Some("topic"), None).unwrap();
+        assert!(!out.inProgress);
+        assert_eq!(
+            git(&dir, &["rev-parse", "HEAD~1"]).unwrap().stdout,
+            main_head
+        );
+        assert_eq!(
+            out.newHead,
+            git(&dir, &["rev-parse", "topic"]).unwrap().stdout
+        );
+
+        fs::write(dir.join("main.txt"), "topic side\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "conflict"]).unwrap().ok);
+        assert!(git(&dir, &["checkout", "-q", &main]).unwrap().ok);
+        fs::write(dir.join("main.txt"), "main side\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "conflict"]).unwrap().ok);
+        let out = rebase(&main, Some("topic"), None).unwrap();
+        assert!(out.inProgress);
+        assert_eq!(out.conflictFiles, vec!["main.txt".to_string()]);
+
+        let out = rebase("", None, Some("abort")).unwrap();
+        assert!(!out.inProgress);
+        assert!(rebase(&main, None, Some("bogus")).is_err());
+
+        let marker = dir.join("pwned");
+        let exec = format!("--exec=touch {}", marker.display());
+        for (upstream, branch) in [
+            (exec.as_str(), Some("HEAD~1")),
+            (main.as_str(), Some(exec.as_str())),
+        ] {
+            let err = rebase(upstream, branch, None).err().unwrap();
+            assert_eq!(err.kind(), "invalidInput");
+        }
+        let onto = git_rebase(GitRebaseIn {
+            repoRoot: dir.display().to_string(),
+            upstream: main.clone(),
+            branch: None,
+            onto: Some(exec.clone()),
+            interactive: false,
+            autosquash: false,
+            operation: None,
+        });
+        assert_eq!(onto.err().unwrap().kind(), "invalidInput");
+        assert!(!marker.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_tag_creates_lists_and_protects_release_tags() {
+        let dir = tmp_dir("ecc-kernel-test-tag");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let tag = |operation: &str, name: Option<&str>, message: Option<&str>, force: bool| {
+            git_tag(GitTagIn {
+                repoRoot: dir.display().to_string(),
+                operation: operation.to_string(),
+                name: name.map(|n| n.to_string()),
+                target: None,
+                message: message.map(|m| m.to_string()),
+                force,
+                delete: false,
+            })
+        };
+
+        let light = tag("create", Some("v1.2.0"), None, false).unwrap();
+        assert_eq!(light.sha.as_deref(), Some(head.as_str()));
+        let annotated = tag("create", Some("v1.10.0"), Some("release"), false).unwrap();
+        assert_ne!(annotated.sha.as_deref(), Some(head.as_str()));
+        assert_eq!(
+            git(&dir, &["cat-file", "-t", annotated.sha.as_deref().unwrap()])
+                .unwrap()
+                .stdout,
+            "tag"
+        );
+        tag("create", Some("scratch"), None, false).unwrap();
+
+        let listed = tag("list", None, None, false).unwrap().tags;
+        assert_eq!(&listed[..2], &["v1.10.0".to_string(), "v1.2.0".to_string()]);
+
+        assert!(tag("delete", Some("v1.2.0"), None, false).is_err());
+        assert!(tag("delete", Some("v1.2.0"), None, true).is_ok());
+        assert!(tag("delete", Some("scratch"), None, false).is_ok());
+        assert_eq!(
+            tag("list", None, None, false).unwrap().tags,
+            vec!["v1.10.0".to_string()]
+        );
+        assert!(tag("bogus", None, None, false).is_err());
+        let option = tag("create", Some("--list"), None, false);
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+        let option_target = git_tag(GitTagIn {
+            repoRoot: dir.display().to_string(),
+            operation: "create".to_string(),
+            name: Some("scratch".to_string()),
+            target: Some("--points-at=HEAD".to_string()),
+            message: None,
+            force: false,
+            delete: false,
+        });
+        assert_eq!(option_target.err().unwrap().kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_config_get_and_set_respect_scope() {
+        let dir = tmp_dir("ecc-kernel-test-config");
+        init_git_repo(&dir).unwrap();
+        // Point the global scope at a scratch file so the test never touches ~/.gitconfig.
+        let global = tmp_dir("ecc-kernel-test-config-global");
+        let env = [(
+            "GIT_CONFIG_GLOBAL".to_string(),
+            global.display().to_string(),
+        )];
+        let get = |key: &str, global: bool| {
+            run_git_config_get(
+                GitConfigGetIn {
+                    repoRoot: dir.display().to_string(),
+                    key: key.to_string(),
+                    global,
+                },
+                &env,
+            )
+        };
+        let set = |key: &str, value: &str, global: bool| {
+            run_git_config_set(
+                GitConfigSetIn {
+                    repoRoot: dir.display().to_string(),
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    global,
+                    add: false,
+                },
+                &env,
+            )
+        };
+
+        assert_eq!(get("ecc.missing", false).unwrap().value, None);
+        assert!(set("ecc.scope", "local", false).unwrap().ok);
+        assert!(set("ecc.scope", "global", true).unwrap().ok);
+        assert_eq!(
+            get("ecc.scope", false).unwrap().value.as_deref(),
+            Some("local")
+        );
+        assert_eq!(
+            get("ecc.scope", true).unwrap().value.as_deref(),
+            Some("global")
+        );
+        assert!(fs::read_to_string(&global)
+            .unwrap()
+            .contains("scope = global"));
+
+        assert!(set("ecc.bad\nkey", "x", false).is_err());
+        assert!(get("ecc.bad\nkey", false).is_err());
+
+        let _ = fs::remove_file(&global);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_rev_parse_resolves_refs_and_rejects_missing_ones() {
+        let dir = tmp_dir("ecc-kernel-test-rev-parse");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let rev_parse = |r: &str, verify: bool, abbreviate: Option<u32>| {
+            git_rev_parse(GitRevParseIn {
+                repoRoot: dir.display().to_string(),
+                ref_: r.to_string(),
+                verify,
+                abbreviate,
+            })
+        };
+
+        let out = rev_parse("HEAD", true, None).unwrap();
+        assert_eq!(out.sha, head);
+        assert_eq!(out.objectType, "commit");
+        assert_eq!(
+            rev_parse("HEAD^{tree}", true, None).unwrap().objectType,
+            "tree"
+        );
+        assert_eq!(rev_parse("HEAD", false, Some(7)).unwrap().sha, head[..7]);
+
+        assert!(rev_parse("no-such-branch", true, None).is_err());
+        assert!(rev_parse("  ", true, None).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_ls_files_reports_ignored_and_deleted_files() {
+        let dir = tmp_dir("ecc-kernel-test-ls-files");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join("tracked file.txt"), "t\n").unwrap();
+        assert!(git(&dir, &["add", "-A"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "more"]).unwrap().ok);
+        fs::write(dir.join("debug.log"), "noise\n").unwrap();
+        fs::write(dir.join("scratch.tmp"), "noise\n").unwrap();
+        fs::remove_file(dir.join("base.txt")).unwrap();
+        let input = || GitLsFilesIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: None,
+            cached: false,
+            deleted: false,
+            modified: false,
+            others: false,
+            ignored: false,
+            excludePatterns: Vec::new(),
+        };
+
+        let cached = git_ls_files(GitLsFilesIn {
+            cached: true,
+            ..input()
+        })
+        .unwrap();
+        let spaced = cached
+            .files
+            .iter()
+            .find(|f| f.path == "tracked file.txt")
+            .unwrap();
+        assert_eq!(spaced.status, Some('H'));
+        assert_eq!(spaced.sha.as_ref().map(|s| s.len()), Some(40));
+
+        let deleted = git_ls_files(GitLsFilesIn {
+            deleted: true,
+            ..input()
+        })
+        .unwrap();
+        assert_eq!(deleted.files.len(), 1);
+        assert_eq!(deleted.files[0].path, "base.txt");
+        assert_eq!(deleted.files[0].status, Some('R'));
+
+        let ignored = git_ls_files(GitLsFilesIn {
+            ignored: true,
+            excludePatterns: vec!["*.tmp".to_string()],
+            ..input()
+        })
+        .unwrap();
+        let mut paths: Vec<&str> = ignored.files.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["debug.log", "scratch.tmp"]);
+        assert!(ignored.files.iter().all(|f| f.sha.is_none()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_clean_requires_force_and_reports_removed_paths() {
+        let dir = tmp_dir("ecc-kernel-test-clean");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("artifact.txt"), "x\n").unwrap();
+        fs::create_dir_all(dir.join("build")).unwrap();
+        fs::write(dir.join("build").join("out.o"), "x\n").unwrap();
+        let clean = |force: bool, dry_run: bool| {
+            git_clean(GitCleanIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: None,
+                force,
+                directories: true,
+                ignored: false,
+                dryRun: dry_run,
+                paths: Vec::new(),
+            })
+        };
+
+        assert!(clean(false, false).is_err());
+
+        let dry = clean(false, true).unwrap();
+        assert_eq!(
+            dry.removedPaths,
+            vec!["artifact.txt".to_string(), "build/".to_string()]
+        );
+        assert!(dir.join("artifact.txt").exists());
+
+        let real = clean(true, false).unwrap();
+        assert_eq!(real.removedPaths, dry.removedPaths);
+        assert!(!dir.join("artifact.txt").exists());
+        assert!(!dir.join("build").exists());
+        assert!(dir.join("base.txt").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_ls_remote_lists_refs_of_a_path_remote() {
+        let origin = tmp_dir("ecc-kernel-test-ls-remote-origin");
+        init_git_repo(&origin).unwrap();
+        let head = git(&origin, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert!(git(&origin, &["branch", "feature"]).unwrap().ok);
+        let clone = clone_repo(&origin, "ecc-kernel-test-ls-remote-clone");
+        let ls_remote = |refs: Vec<String>, exit_code: bool| {
+            git_ls_remote(GitLsRemoteIn {
+                repoRoot: clone.display().to_string(),
+                remote: "origin".to_string(),
+                refs,
+                tags: false,
+                heads: true,
+                exitCode: exit_code,
+            })
+        };
+
+        let out = ls_remote(Vec::new(), false).unwrap();
+        assert_eq!(out.refs.len(), 2);
+        let feature = out
+            .refs
+            .iter()
+            .find(|r| r.refName == "refs/heads/feature")
+            .unwrap();
+        assert_eq!(feature.sha, head);
+
+        assert!(ls_remote(vec!["missing".to_string()], false)
+            .unwrap()
+            .refs
+            .is_empty());
+        assert!(ls_remote(vec!["missing".to_string()], true).is_err());
+
+        // ls_remote is read-only, so it also runs under --dry-run.
+        let marker = clone.join("pwned");
+        let upload_pack = format!("--upload-pack=touch {}; git-upload-pack", marker.display());
+        DRY_RUN.with(|d| d.set(true));
+        let results: Vec<_> = [
+            (upload_pack.clone(), origin.display().to_string()),
+            ("origin".to_string(), upload_pack),
+        ]
+        .into_iter()
+        .map(|(remote, r)| {
+            dispatch(
+                "git.ls_remote",
+                serde_json::json!({
+                    "repoRoot": clone.display().to_string(),
+                    "remote": remote,
+                    "refs": [r],
+                }),
+            )
+        })
+        .collect();
+        DRY_RUN.with(|d| d.set(false));
+        for result in results {
+            assert_eq!(result.err().unwrap().kind(), "invalidInput");
+        }
+        assert!(!marker.exists());
+
+        let _ = fs::remove_dir_all(&origin);
+        let _ = fs::remove_dir_all(&clone);
+    }
+
+    #[test]
+    fn git_describe_parses_distance_and_exact_tags() {
+        let dir = tmp_dir("ecc-kernel-test-describe");
+        init_git_repo(&dir).unwrap();
+        let describe = |long_format: bool| {
+            git_describe(GitDescribeIn {
+                repoRoot: dir.display().to_string(),
+                commit: None,
+                tags: true,
+                abbrev: Some(12),
+                longFormat: long_format,
+                matchPattern: None,
+            })
+        };
+
+        let err = describe(false).err().unwrap();
+        assert!(matches!(err, KernelError::NotFound(_)), "{err}");
+        assert_eq!(err.to_string(), "no tags found to describe HEAD");
+
+        assert!(git(&dir, &["tag", "release-1.0"]).unwrap().ok);
+        let exact = describe(false).unwrap();
+        assert_eq!(exact.description, "release-1.0");
+        assert_eq!(exact.tag, "release-1.0");
+        assert_eq!(exact.distance, 0);
+        let exact_long = describe(true).unwrap();
+        assert_eq!(exact_long.tag, "release-1.0");
+        assert_eq!(exact_long.distance, 0);
+        assert!(exact_long.description.starts_with("release-1.0-0-g"));
+
+        assert!(
+            git(&dir, &["commit", "-q", "--allow-empty", "-m", "one"])
+                .unwrap()
+                .ok
+        );
+        assert!(
+            git(&dir, &["commit", "-q", "--allow-empty", "-m", "two"])
+                .unwrap()
+                .ok
+        );
+        let ahead = describe(false).unwrap();
+        assert_eq!(ahead.tag, "release-1.0");
+        assert_eq!(ahead.distance, 2);
+        assert_eq!(ahead.sha, git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout);
+
+        for (commit, pattern) in [(Some("--all"), None), (None, Some("--dirty"))] {
+            let option = git_describe(GitDescribeIn {
+                repoRoot: dir.display().to_string(),
+                commit: commit.map(|c| c.to_string()),
+                tags: true,
+                abbrev: None,
+                longFormat: false,
+                matchPattern: pattern.map(|p| p.to_string()),
+            });
+            assert_eq!(option.err().unwrap().kind(), "invalidInput");
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_check_ignore_reports_gitignore_and_info_exclude_matches() {
+        let dir = tmp_dir("ecc-kernel-test-check-ignore");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join(".gitignore"), "# comment\n*.log\n").unwrap();
+        fs::write(dir.join(".git").join("info").join("exclude"), "secret/\n").unwrap();
+        let check = |paths: &[&str], verbose: bool| {
+            git_check_ignore(GitCheckIgnoreIn {
+                repoRoot: dir.display().to_string(),
+                paths: paths.iter().map(|p| p.to_string()).collect(),
+                verbose,
+            })
+            .unwrap()
+            .ignoredPaths
+        };
+
+        assert!(check(&[], true).is_empty());
+        assert!(check(&["src/main.rs"], true).is_empty());
+
+        let out = check(&["src/main.rs", "debug log.log", "secret/key.pem"], true);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].path, "debug log.log");
+        assert_eq!(out[0].pattern.as_deref(), Some("*.log"));
+        assert_eq!(out[0].source.as_deref(), Some(".gitignore"));
+        assert_eq!(out[0].lineNumber, Some(2));
+        assert_eq!(out[1].path, "secret/key.pem");
+        assert_eq!(out[1].pattern.as_deref(), Some("secret/"));
+        assert!(out[1].source.as_deref().unwrap().ends_with("info/exclude"));
+
+        let terse = check(&["a.log"], false);
+        assert_eq!(terse.len(), 1);
+        assert!(terse[0].pattern.is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_update_ref_supports_compare_and_swap_and_delete() {
+        let dir = tmp_dir("ecc-kernel-test-update-ref");
+        init_git_repo(&dir).unwrap();
+        let first = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert!(
+            git(&dir, &["commit", "-q", "--allow-empty", "-m", "second"])
+                .unwrap()
+                .ok
+        );
+        let second = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert!(git(&dir, &["branch", "replay", &first]).unwrap().ok);
+        let update = |new: &str, old: Option<&str>| {
+            git_update_ref(GitUpdateRefIn {
+                repoRoot: dir.display().to_string(),
+                ref_: "refs/heads/replay".to_string(),
+                newValue: new.to_string(),
+                oldValue: old.map(|o| o.to_string()),
+                deref: true,
+            })
+        };
+
+        let err = update(&second, Some(&second)).err().unwrap();
+        assert!(err.to_string().starts_with(REF_CAS_FAILURE), "{err}");
+        assert_eq!(err.kind(), "gitFailed");
+        assert_eq!(err.details()["casFailure"], true);
+        assert_eq!(err.details()["ref"], "refs/heads/replay");
+        assert_eq!(git(&dir, &["rev-parse", "replay"]).unwrap().stdout, first);
+
+        assert!(update(&second, Some(&first)).unwrap().ok);
+        assert_eq!(git(&dir, &["rev-parse", "replay"]).unwrap().stdout, second);
+
+        // `git update-ref` parses options anywhere: `-d` as the ref would delete `replay`.
+        let option = git_update_ref(GitUpdateRefIn {
+            repoRoot: dir.display().to_string(),
+            ref_: "-d".to_string(),
+            newValue: "refs/heads/replay".to_string(),
+            oldValue: None,
+            deref: true,
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+        assert_eq!(
+            update("--stdin", None).err().unwrap().kind(),
+            "invalidInput"
+        );
+        assert!(branch_exists(&dir, "replay"));
+
+        let out = update("", None).unwrap();
+        assert!(out.deleted);
+        assert!(!branch_exists(&dir, "replay"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_merge_fast_forwards_detects_conflicts_and_aborts() {
+        let dir = tmp_dir("ecc-kernel-test-merge");
+        init_git_repo(&dir).unwrap();
+        let main = git(&dir, &["branch", "--show-current"]).unwrap().stdout;
+        assert!(git(&dir, &["checkout", "-q", "-b", "topic"]).unwrap().ok);
+        fs::write(dir.join("base.txt"), "topic\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "topic"]).unwrap().ok);
+        let topic = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert!(git(&dir, &["checkout", "-q", &main]).unwrap().ok);
+        let merge = |branch: &str, ff_only: bool, no_ff: bool, operation: Option<&str>| {
+            git_merge(GitMergeIn {
+                repoRoot: dir.display().to_string(),
+                branch: branch.to_string(),
+                message: None,
+                ffOnly: ff_only,
+                noFf: no_ff,
+                squash: false,
+                operation: operation.map(|o| o.to_string()),
+            })
+        };
+
+        assert!(merge("topic", true, true, None).is_err());
+        let option = merge("--strategy=ours", false, false, None);
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+
+        let out = merge("topic", true, false, None).unwrap();
+        assert!(out.fastForwarded);
+        assert_eq!(out.newHead.as_deref(), Some(topic.as_str()));
+
+        assert!(
+            git(&dir, &["checkout", "-q", "-b", "other", "HEAD~1"])
+                .unwrap()
+                .ok
+        );
+        fs::write(dir.join("base.txt"), "other\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "other"]).unwrap().ok);
+        let out = merge("topic", false, false, None).unwrap();
+        assert!(out.newHead.is_none());
+        assert_eq!(out.conflictFiles, vec!["base.txt".to_string()]);
+
+        let out = merge("", false, false, Some("abort")).unwrap();
+        assert!(out.conflictFiles.is_empty());
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "other\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_read_handles_utf8_binary_and_truncation() {
+        let dir = tmp_dir("ecc-kernel-test-fs-read");
+        fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("text.txt");
+        let bin = dir.join("blob.bin");
+        fs::write(&text, "héllo\n").unwrap();
+        fs::write(&bin, [0u8, 159, 146, 150, 255]).unwrap();
+        let read = |p: &Path, encoding: &str, max: Option<u64>| {
+            fs_read(FsReadIn {
+                path: p.display().to_string(),
+                encoding: encoding.to_string(),
+                maxBytes: max,
+            })
+        };
+
+        let out = read(&text, "utf8", None).unwrap();
+        assert_eq!(out.content, "héllo\n");
+        assert_eq!(out.size, 7);
+        assert!(!out.truncated);
+
+        assert!(read(&bin, "utf8", None).is_err());
+        let out = read(&bin, "base64", None).unwrap();
+        assert_eq!(out.content, "AJ+Slv8=");
+
+        // Cutting inside `é` drops the partial character.
+        let out = read(&text, "utf8", Some(2)).unwrap();
+        assert_eq!(out.content, "h");
+        assert!(out.truncated);
+        assert_eq!(out.size, 7);
+
+        assert!(read(Path::new("relative.txt"), "utf8", None).is_err());
+        assert!(read(&dir.join("..").join("x"), "utf8", None).is_err());
+        assert!(read(&text, "latin1", None).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_write_is_atomic_and_guards_existing_files() {
+        let dir = tmp_dir("ecc-kernel-test-fs-write");
+        let target = dir.join("nested").join("out.bin");
+        let write = |content: &str, encoding: &str, create_dirs: bool, overwrite: bool| {
+            fs_write(FsWriteIn {
+                path: target.display().to_string(),
+                content: content.to_string(),
+                encoding: encoding.to_string(),
+                createDirs: create_dirs,
+                overwrite,
+            })
+        };
+
+        assert!(write("x", "utf8", false, false).is_err());
+        let out = write("AJ+Slv8=", "base64", true, false).unwrap();
+        assert!(out.created);
+        assert_eq!(out.bytesWritten, 5);
+        assert_eq!(fs::read(&target).unwrap(), vec![0u8, 159, 146, 150, 255]);
+
+        assert!(write("again", "utf8", false, false).is_err());
+        let out = write("again", "utf8", false, true).unwrap();
+        assert!(!out.created);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "again");
+
+        // No temp files are left behind next to the target.
+        let leftovers = fs::read_dir(dir.join("nested")).unwrap().count();
+        assert_eq!(leftovers, 1);
+        assert!(write("x", "hex", false, true).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn glob_match_supports_star_and_question_mark() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(glob_match("ma?n.*", "main.rs"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "a-b-b-c"));
+        assert!(!glob_match("*.rs", "main.rs.bak"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn fs_list_respects_depth_hidden_and_glob() {
+        let dir = tmp_dir("ecc-kernel-test-fs-list");
+        fs::create_dir_all(dir.join("src").join("deep")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        fs::write(dir.join("README.md"), "r").unwrap();
+        fs::write(dir.join("src").join("lib.rs"), "l").unwrap();
+        fs::write(dir.join("src").join("deep").join("mod.rs"), "m").unwrap();
+        fs::write(dir.join(".hidden").join("x.rs"), "x").unwrap();
+        let list = |recursive: bool, max_depth: Option<u32>, hidden: bool, glob: Option<&str>| {
+            let out = fs_list(FsListIn {
+                path: dir.display().to_string(),
+                recursive,
+                maxDepth: max_depth,
+                includeHidden: hidden,
+                glob: glob.map(|g| g.to_string()),
+            })
+            .unwrap();
+            out.entries
+                .into_iter()
+                .map(|e| {
+                    e.path
+                        .trim_start_matches(&dir.display().to_string())
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(list(false, None, false, None), vec!["/README.md", "/src"]);
+        assert_eq!(
+            list(true, Some(2), false, Some("*.rs")),
+            vec!["/src/lib.rs"]
+        );
+        assert_eq!(
+            list(true, None, true, Some("*.rs")),
+            vec!["/.hidden/x.rs", "/src/deep/mod.rs", "/src/lib.rs"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_stat_reports_files_missing_paths_and_symlinks() {
+        let dir = tmp_dir("ecc-kernel-test-fs-stat");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file.txt");
+        fs::write(&file, "12345").unwrap();
+        let stat = |p: &Path, follow: bool| {
+            fs_stat(FsStatIn {
+                path: p.display().to_string(),
+                followSymlinks: follow,
+            })
+            .unwrap()
+        };
+
+        let out = stat(&file, false);
+        assert!(out.exists && out.isFile && !out.isDir && !out.isSymlink);
+        assert_eq!(out.size, 5);
+        assert!(!out.mtime.is_empty());
+
+        let missing = stat(&dir.join("missing"), false);
+        assert!(
+            !missing.exists && !missing.isFile && missing.size == 0 && missing.permissions == 0
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+            assert_eq!(stat(&file, false).permissions, 0o640);
+
+            let link = dir.join("link");
+            std::os::unix::fs::symlink(&file, &link).unwrap();
+            let no_follow = stat(&link, false);
+            assert!(no_follow.isSymlink && !no_follow.isFile);
+            let follow = stat(&link, true);
+            assert!(follow.isSymlink && follow.isFile);
+            assert_eq!(follow.size, 5);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_delete_guards_git_dirs_and_directories() {
+        let dir = tmp_dir("ecc-kernel-test-fs-delete");
+        init_git_repo(&dir).unwrap();
+        fs::create_dir_all(dir.join("build").join("obj")).unwrap();
+        fs::write(dir.join("build").join("obj").join("a.o"), "x").unwrap();
+        let delete = |p: PathBuf, recursive: bool, force: bool| {
+            fs_delete(FsDeleteIn {
+                path: p.display().to_string(),
+                recursive,
+                force,
+            })
+        };
+
+        let err = delete(dir.join(".git").join("objects"), true, false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains(".git"), "{err}");
+        assert!(delete(dir.join(".git"), true, true).is_err());
+        assert!(dir.join(".git").join("objects").exists());
+
+        assert!(delete(dir.join("build"), false, false).is_err());
+        assert!(delete(dir.join("build"), true, false).unwrap().ok);
+        assert!(!dir.join("build").exists());
+
+        assert!(delete(dir.join("base.txt"), false, false).unwrap().ok);
+        assert!(delete(dir.join("base.txt"), false, false).is_err());
+        assert!(delete(dir.join("base.txt"), false, true).unwrap().ok);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_copy_copies_trees_and_guards_overwrites() {
+        let dir = tmp_dir("ecc-kernel-test-fs-copy");
+        let src = dir.join("template");
+        fs::create_dir_all(src.join("a").join("b").join("c")).unwrap();
+        fs::write(src.join("top.txt"), "top").unwrap();
+        fs::write(src.join("a").join("b").join("c").join("deep.txt"), "deep!").unwrap();
+        let dst = dir.join("seeded");
+        let copy = |dst: &Path, overwrite: bool| {
+            fs_copy(FsCopyIn {
+                src: src.display().to_string(),
+                dst: dst.display().to_string(),
+                recursive: true,
+                overwrite,
+                preserveMtime: true,
+            })
+        };
+
+        let out = copy(&dst, false).unwrap();
+        assert_eq!(out.filesCopied, 2);
+        assert_eq!(out.bytesCopied, 8);
+        let deep = dst.join("a").join("b").join("c").join("deep.txt");
+        assert_eq!(fs::read_to_string(&deep).unwrap(), "deep!");
+        assert_eq!(
+            fs::metadata(&deep).unwrap().modified().unwrap(),
+            fs::metadata(src.join("a").join("b").join("c").join("deep.txt"))
+                .unwrap()
+                .modified()
+                .unwrap()
+        );
+
+        fs::write(dst.join("top.txt"), "local edit").unwrap();
+        assert!(copy(&dst, false).is_err());
+        assert_eq!(
+            fs::read_to_string(dst.join("top.txt")).unwrap(),
+            "local edit"
+        );
+        assert!(copy(&dst, true).is_ok());
+        assert_eq!(fs::read_to_string(dst.join("top.txt")).unwrap(), "top");
+
+        assert!(copy(&src.join("nested"), false).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_hash_matches_known_digests_and_git_blob_shas() {
+        let dir = tmp_dir("ecc-kernel-test-fs-hash");
+        fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty");
+        let hello = dir.join("hello.txt");
+        fs::write(&empty, "").unwrap();
+        fs::write(&hello, "hello\n").unwrap();
+        let hash = |p: &Path, algorithm: &str| {
+            fs_hash(FsHashIn {
+                path: p.display().to_string(),
+                algorithm: algorithm.to_string(),
+            })
+        };
+
+        let out = hash(&empty, "sha256").unwrap();
+        assert_eq!(
+            out.hash,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(out.size, 0);
+        assert_eq!(
+            hash(&empty, "md5").unwrap().hash,
+            "d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert_eq!(hash(&empty, "xxhash64").unwrap().hash, "ef46db3751d8e999");
+        assert_eq!(
+            hash(&empty, "sha1").unwrap().hash,
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+
+        // `git hash-object` of "hello\n".
+        let out = hash(&hello, "sha1").unwrap();
+        assert_eq!(out.hash, "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert_eq!(out.size, 6);
+
+        assert!(hash(&hello, "crc32").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fs_atomic_write_never_exposes_a_partial_file() {
+        let dir = tmp_dir("ecc-kernel-test-fs-atomic-write");
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("summary.json");
+        let old = vec![b'a'; 4 * 1024 * 1024];
+        let new = vec![b'b'; 4 * 1024 * 1024];
+        fs_atomic_write(&target, &old).unwrap();
+
+        // A write that fails halfway leaves the target untouched and no temp file behind.
+        let err = atomic_write_with(&target, |f| {
+            f.write_all(&new[..1024])?;
+            Err(io::Error::other("injected write failure"))
+        });
+        assert!(matches!(err, Err(KernelError::Io(_))));
+        assert_eq!(fs::read(&target).unwrap(), old);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Concurrent writers in one process use distinct temp files.
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let target = target.clone();
+                let old = old.clone();
+                std::thread::spawn(move || fs_atomic_write(&target, &old))
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap().unwrap();
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Concurrent readers see either the old or the new content, never a mix.
+        let reader_target = target.clone();
+        let reader = std::thread::spawn(move || {
+            for _ in 0..50 {
+                let data = fs::read(&reader_target).unwrap();
+                assert_eq!(data.len(), 4 * 1024 * 1024);
+                assert!(data.iter().all(|b| *b == data[0]));
+            }
+        });
+        for i in 0..10 {
+            fs_atomic_write(&target, if i % 2 == 0 { &new } else { &old }).unwrap();
+        }
+        reader.join().unwrap();
+
+        let out = fs_atomic_write_cmd(FsAtomicWriteIn {
+            path: target.display().to_string(),
+            content: "done\n".to_string(),
+            encoding: "utf8".to_string(),
+        })
+        .unwrap();
+        assert_eq!(out.bytesWritten, 5);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "done\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn patch_generate_round_trips_through_patch_apply() {
+        let dir = tmp_dir("ecc-kernel-test-patch-generate");
+        init_git_repo(&dir).unwrap();
+        let patch = dir.join(".git").join("change.patch");
+        let generate = |staged: bool| {
+            patch_generate(PatchGenerateIn {
+                worktreePath: dir.display().to_string(),
+                base: None,
+                staged,
+                paths: Vec::new(),
+                contextLines: None,
+                outputPath: patch.display().to_string(),
+            })
+            .unwrap()
+        };
+
+        let out = generate(false);
+        assert!(!out.hasChanges);
+        assert!(out.touchedFiles.is_empty());
+
+        fs::write(dir.join("base.txt"), "base\nedited\n").unwrap();
+        fs::write(dir.join("staged.txt"), "staged\n").unwrap();
+        git(&dir, &["add", "staged.txt"]).unwrap();
+
+        // Working tree vs HEAD includes the staged file; index vs HEAD does not see the edit.
+        let out = generate(false);
+        assert!(out.hasChanges);
+        assert_eq!(
+            out.touchedFiles,
+            vec!["base.txt".to_string(), "staged.txt".to_string()]
+        );
+        let out = generate(true);
+        assert_eq!(out.touchedFiles, vec!["staged.txt".to_string()]);
+
+        // Generate against HEAD for everything, reset, and re-apply.
+        git(&dir, &["add", "-A"]).unwrap();
+        let out = generate(true);
+        assert_eq!(out.touchedFiles.len(), 2);
+        git(&dir, &["reset", "--hard", "-q"]).unwrap();
+        assert!(!dir.join("staged.txt").exists());
+
+        patch_apply(PatchApplyIn {
+            worktreePath: dir.display().to_string(),
+            patchPath: out.patchPath,
+            allowedPathPrefixes: vec!["base.txt".to_string(), "staged.txt".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("base.txt")).unwrap(),
+            "base\nedited\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("staged.txt")).unwrap(),
+            "staged\n"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn patch_validate_reports_ownership_and_apply_results_together() {
+        let dir = tmp_dir("ecc-kernel-test-patch-validate");
+        init_git_repo(&dir).unwrap();
+        let patch = dir.join(".git").join("bad.patch");
+        // Wrong context for base.txt, and an unowned path.
+        fs::write(
+            &patch,
+            "diff --git a/base.txt b/base.txt\n--- a/base.txt\n+++ b/base.txt\n@@ -1 +1 @@\n-nope\n+changed\n",
+        )
+        .unwrap();
+        let validate = |prefixes: &[&str]| {
+            patch_validate(PatchValidateIn {
+                worktreePath: dir.display().to_string(),
+                patchPath: patch.display().to_string(),
+                allowedPathPrefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let out = validate(&["src/"]);
+        assert!(!out.ownershipOk);
+        assert!(!out.applyOk);
+        assert_eq!(out.touchedFiles, vec!["base.txt".to_string()]);
+        assert_eq!(
+            out.violations,
+            vec!["unauthorized path: base.txt".to_string()]
+        );
+        assert!(out.applyError.is_some());
+
+        fs::write(
+            &patch,
+            "diff --git a/base.txt b/base.txt\n--- a/base.txt\n+++ b/base.txt\n@@ -1 +1 @@\n-base\n+changed\n",
+        )
+        .unwrap();
+        let out = validate(&["base.txt"]);
+        assert!(out.ownershipOk && out.applyOk);
+        assert!(out.violations.is_empty() && out.applyError.is_none());
+        // Validation never touches the worktree.
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "base\n");
+
+        // Options match patch.apply: a `-p0` patch only validates with stripLeadingComponents 0.
+        fs::write(
+            &patch,
+            "diff --git base.txt base.txt\n--- base.txt\n+++ base.txt\n@@ -1 +1 @@\n-base\n+changed\n",
+        )
+        .unwrap();
+        let stripped = |strip: u32| {
+            patch_validate(PatchValidateIn {
+                worktreePath: dir.display().to_string(),
+                patchPath: patch.display().to_string(),
+                allowedPathPrefixes: vec!["base.txt".to_string()],
+                stripLeadingComponents: Some(strip),
+                ..Default::default()
+            })
+        };
+        let out = stripped(0).unwrap();
+        assert!(out.ownershipOk && out.applyOk, "{:?}", out.applyError);
+        assert!(!stripped(1).unwrap().applyOk);
+        assert!(patch_validate(PatchValidateIn {
+            worktreePath: dir.display().to_string(),
+            patchPath: patch.display().to_string(),
+            whitespace: Some("bogus".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn patch_apply_three_way_reports_conflicts() {
+        let dir = tmp_dir("ecc-kernel-test-patch-3way");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("f.txt"), "a\nb\nc\n").unwrap();
+        git(&dir, &["add", "f.txt"]).unwrap();
+        git(&dir, &["commit", "-q", "-m", "f"]).unwrap();
+        fs::write(dir.join("f.txt"), "a\nB\nc\n").unwrap();
+        let patch = dir.join(".git").join("f.patch");
+        fs::write(&patch, git(&dir, &["diff"]).unwrap().stdout + "\n").unwrap();
+        git(&dir, &["checkout", "-q", "f.txt"]).unwrap();
+        fs::write(dir.join("f.txt"), "a\nX\nc\n").unwrap();
+        git(&dir, &["commit", "-q", "-am", "diverge"]).unwrap();
+
+        let apply = |three_way: bool| {
+            patch_apply(PatchApplyIn {
+                worktreePath: dir.display().to_string(),
+                patchPath: patch.display().to_string(),
+                allowedPathPrefixes: vec!["f.txt".to_string()],
+                threeWay: three_way,
+                ..Default::default()
+            })
+        };
+
+        assert!(apply(false).is_err());
+        let out = apply(true).unwrap();
+        assert_eq!(touched_paths(out.touchedFiles), vec!["f.txt".to_string()]);
+        assert_eq!(out.conflicts, vec!["f.txt".to_string()]);
+        assert!(fs::read_to_string(dir.join("f.txt"))
+            .unwrap()
+            .contains("<<<<<<<"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn patch_apply_flags_binary_files_and_honours_allow_binary() {
+        let dir = tmp_dir("ecc-kernel-test-patch-binary");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("logo.bin"), [0u8, 1, 2, 3, 0, 255]).unwrap();
+        git(&dir, &["add", "logo.bin"]).unwrap();
+        git(&dir, &["commit", "-q", "-m", "bin"]).unwrap();
+        fs::write(dir.join("logo.bin"), [0u8, 9, 8, 7, 0, 254, 253]).unwrap();
+        fs::write(dir.join("base.txt"), "base\nmore\n").unwrap();
+        let diff = git(&dir, &["diff", "--binary"]).unwrap();
+        assert!(diff.stdout.contains("GIT binary patch"));
+        let patch = dir.join(".git").join("binary.patch");
+        // The helper trims output; binary hunks need their trailing blank line back.
+        fs::write(&patch, format!("{}\n\n", diff.stdout)).unwrap();
+        git(&dir, &["checkout", "--", "."]).unwrap();
+        let apply = |allow_binary: bool| {
+            patch_apply(PatchApplyIn {
+                worktreePath: dir.display().to_string(),
+                patchPath: patch.display().to_string(),
+                allowedPathPrefixes: vec!["base.txt".to_string(), "logo.bin".to_string()],
+                allowBinary: allow_binary,
+                ..Default::default()
+            })
+        };
+
+        let err = apply(false).err().unwrap();
+        assert_eq!(err.kind(), "invalidInput");
+        assert!(err.to_string().contains("logo.bin"), "{err}");
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "base\n");
+
+        let out = apply(true).unwrap();
+        let flags: Vec<(&str, bool)> = out
+            .touchedFiles
+            .iter()
+            .map(|t| (t.path.as_str(), t.isBinary))
+            .collect();
+        assert_eq!(flags, vec![("base.txt", false), ("logo.bin", true)]);
+        assert_eq!(
+            fs::read(dir.join("logo.bin")).unwrap(),
+            vec![0u8, 9, 8, 7, 0, 254, 253]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parse_apply_conflicts_handles_both_message_formats() {
+        let text = "Applied patch to 'a b.txt' with conflicts.\nU a b.txt\nApplied patch c.txt with conflicts.\nApplied patch to 'd.txt' cleanly.\n";
+        assert_eq!(
+            parse_apply_conflicts(text),
+            vec!["a b.txt".to_string(), "c.txt".to_string()]
+        );
+    }
+
+    #[test]
+    fn patch_apply_whitespace_fix_tolerates_trailing_spaces() {
+        let dir = tmp_dir("ecc-kernel-test-patch-whitespace");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("f.txt"), "a  \nb\nc\n").unwrap();
+        git(&dir, &["add", "f.txt"]).unwrap();
+        git(&dir, &["commit", "-q", "-m", "f"]).unwrap();
+        // Context generated from a copy whose trailing spaces were stripped.
+        let patch = dir.join(".git").join("ws.patch");
+        fs::write(
+            &patch,
+            "diff --git a/f.txt b/f.txt\n--- a/f.txt\n+++ b/f.txt\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n",
+        )
+        .unwrap();
+        let apply = |whitespace: Option<&str>| {
+            patch_apply(PatchApplyIn {
+                worktreePath: dir.display().to_string(),
+                patchPath: patch.display().to_string(),
+                allowedPathPrefixes: vec!["f.txt".to_string()],
+                whitespace: whitespace.map(str::to_string),
+                ..Default::default()
+            })
+        };
+
+        assert!(apply(None).is_err());
+        let err = apply(Some("tidy")).err().unwrap();
+        assert!(err.to_string().contains("invalid whitespace action"));
+        apply(Some("fix")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("f.txt")).unwrap(), "a\nB\nc\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn patch_apply_honours_strip_leading_components() {
+        let dir = tmp_dir("ecc-kernel-test-patch-strip");
+        init_git_repo(&dir).unwrap();
+        let patch = dir.join(".git").join("strip.patch");
+        let apply = |strip: u32, prefixes: &[&str]| {
+            patch_apply(PatchApplyIn {
+                worktreePath: dir.display().to_string(),
+                patchPath: patch.display().to_string(),
+                allowedPathPrefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+                stripLeadingComponents: Some(strip),
+                ..Default::default()
+            })
+        };
+
+        // p0: paths carry no a/ b/ prefix at all.
+        fs::write(
+            &patch,
+            "diff --git base.txt base.txt\n--- base.txt\n+++ base.txt\n@@ -1 +1 @@\n-base\n+p0\n",
+        )
+        .unwrap();
+        assert!(apply(0, &["other"]).is_err());
+        let out = apply(0, &["base.txt"]).unwrap();
+        assert_eq!(
+            touched_paths(out.touchedFiles),
+            vec!["base.txt".to_string()]
+        );
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "p0\n");
+
+        // p2: an extra leading directory on top of a/ b/.
+        fs::write(
+            &patch,
+            "diff --git a/x/base.txt b/x/base.txt\n--- a/x/base.txt\n+++ b/x/base.txt\n@@ -1 +1 @@\n-p0\n+p2\n",
+        )
+        .unwrap();
+        let out = apply(2, &["base.txt"]).unwrap();
+        assert_eq!(
+            touched_paths(out.touchedFiles),
+            vec!["base.txt".to_string()]
+        );
+        assert_eq!(fs::read_to_string(dir.join("base.txt")).unwrap(), "p2\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn touched_files_from_unified_diff_reports_change_status() {
+        let dir = tmp_dir("ecc-kernel-test-touched-status");
+        fs::create_dir_all(&dir).unwrap();
+        let patch = dir.join("status.patch");
+        fs::write(
+            &patch,
+            "diff --git a/new.txt b/new.txt\nnew file mode 100644\nindex 0000000..e69de29\n\
+diff --git a/gone.txt b/gone.txt\ndeleted file mode 100644\nindex e69de29..0000000\n\
+diff --git a/mod.txt b/mod.txt\n--- a/mod.txt\n+++ b/mod.txt\n@@ -1 +1 @@\n-a\n+new file mode 1\n\
+diff --git a/old.txt b/moved.txt\nsimilarity index 100%\nrename from old.txt\nrename to moved.txt\n\
+diff --git a/src.txt b/dup.txt\nsimilarity index 100%\ncopy from src.txt\ncopy to dup.txt\n\
+diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n",
+        )
+        .unwrap();
+
+        let touched = touched_files_from_unified_diff(&patch, 1).unwrap();
+        let statuses: Vec<(String, FileChangeStatus)> =
+            touched.into_iter().map(|t| (t.path, t.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("new.txt".to_string(), FileChangeStatus::Added),
+                ("gone.txt".to_string(), FileChangeStatus::Deleted),
+                ("mod.txt".to_string(), FileChangeStatus::Modified),
+                (
+                    "moved.txt".to_string(),
+                    FileChangeStatus::Renamed {
+                        from: "old.txt".to_string()
+                    }
+                ),
+                (
+                    "dup.txt".to_string(),
+                    FileChangeStatus::Copied {
+                        from: "src.txt".to_string()
+                    }
+                ),
+                ("run.sh".to_string(), FileChangeStatus::ModeChange),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ensure_owned_checks_both_sides_of_a_rename() {
+        let dir = tmp_dir("ecc-kernel-test-touched-rename");
+        fs::create_dir_all(&dir).unwrap();
+        let patch = dir.join("rename.patch");
+        fs::write(
+            &patch,
+            "diff --git a/secret/old.txt b/src/new.txt\nsimilarity index 100%\n\
+rename from secret/old.txt\nrename to src/new.txt\n",
+        )
+        .unwrap();
+
+        let touched = touched_files_from_unified_diff(&patch, 1).unwrap();
+        assert_eq!(touched.len(), 1);
+        assert_eq!(touched[0].path, "src/new.txt");
+        assert_eq!(touched[0].origPath.as_deref(), Some("secret/old.txt"));
+
+        let err = ensure_owned(&touched, &["src/".to_string()]).err().unwrap();
+        assert!(
+            matches!(&err, KernelError::OwnershipViolation(v) if v == &["unauthorized path: secret/old.txt".to_string()]),
+            "{err}"
+        );
+        assert!(ensure_owned(&touched, &["src/".to_string(), "secret/".to_string()]).is_ok());
+        let excluded = ["src/", "secret/", "!secret/"].map(String::from);
+        let err = ensure_owned(&touched, &excluded).err().unwrap();
+        assert!(
+            err.to_string().contains("excluded path: secret/old.txt"),
+            "{err}"
+        );
+
+        fs::write(
+            &patch,
+            "diff --git a/../outside.txt b/src/new.txt\nrename from ../outside.txt\nrename to src/new.txt\n",
+        )
+        .unwrap();
+        let touched = touched_files_from_unified_diff(&patch, 1).unwrap();
+        let err = ensure_owned(&touched, &["src/".to_string()]).err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("invalid path in patch: ../outside.txt"),
+            "{err}"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ensure_owned_supports_single_segment_globs() {
+        let touched = |path: &str| TouchedFile {
+            path: path.to_string(),
+            status: FileChangeStatus::Modified,
+            ..Default::default()
+        };
+        let allowed = vec!["src/*/tests/".to_string(), "docs".to_string()];
+
+        assert!(ensure_owned(&[touched("src/foo/tests/bar.rs")], &allowed).is_ok());
+        assert!(ensure_owned(&[touched("src/foo/tests")], &allowed).is_ok());
+        assert!(ensure_owned(&[touched("src/foo/bar.rs")], &allowed).is_err());
+        assert!(ensure_owned(&[touched("src/a/b/tests/x.rs")], &allowed).is_err());
+        // Plain entries keep their prefix semantics.
+        assert!(ensure_owned(&[touched("docs/guide.md")], &allowed).is_ok());
+        assert!(ensure_owned(&[touched("docs")], &allowed).is_ok());
+        assert!(ensure_owned(&[touched("docsx/guide.md")], &allowed).is_err());
+    }
+
+    #[test]
+    fn ensure_owned_applies_negation_after_positive_rules() {
+        let touched = |path: &str| TouchedFile {
+            path: path.to_string(),
+            status: FileChangeStatus::Modified,
+            ..Default::default()
+        };
+        let allowed = vec![
+            "src/".to_string(),
+            "!src/generated/".to_string(),
+            "src/generated/keep/".to_string(),
+            "!src/*/fixtures".to_string(),
+        ];
+
+        assert!(ensure_owned(&[touched("src/lib.rs")], &allowed).is_ok());
+        let err = ensure_owned(&[touched("src/generated/api.rs")], &allowed)
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("excluded path: src/generated/api.rs"));
+        // A negation wins even over a more specific positive entry.
+        assert!(ensure_owned(&[touched("src/generated/keep/a.rs")], &allowed).is_err());
+        assert!(ensure_owned(&[touched("src/foo/fixtures/a.json")], &allowed).is_err());
+        assert!(ensure_owned(&[touched("lib/a.rs")], &allowed).is_err());
+
+        let err = ensure_owned(&[touched("src/lib.rs")], &["!src/generated/".to_string()])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("only negation"));
+    }
+
+    #[test]
+    fn kernel_errors_carry_a_kind() {
+        let err = validate_fs_path("/tmp/../etc/passwd").err().unwrap();
+        assert_eq!(err.kind(), "pathTraversal");
+        assert_eq!(
+            serde_json::to_value(err.to_response()).unwrap(),
+            serde_json::json!({
+                "ok": false,
+                "error": {
+                    "kind": "pathTraversal",
+                    "message": "path must not contain '..': /tmp/../etc/passwd",
+                    "details": {},
+                }
+            })
+        );
+
+        let touched = TouchedFile {
+            path: "secret.txt".to_string(),
+            status: FileChangeStatus::Modified,
+            ..Default::default()
+        };
+        let err = ensure_owned(&[touched], &["src/".to_string()])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, KernelError::OwnershipViolation(v) if v == &["unauthorized path: secret.txt".to_string()]),
+            "{err}"
+        );
+        assert_eq!(
+            err.to_string(),
+            "patch ownership check failed:\n- unauthorized path: secret.txt"
+        );
+
+        let dir = tmp_dir("ecc-kernel-test-kernel-error");
+        fs::create_dir_all(&dir).unwrap();
+        let err = run_git_checked(&git_args(&dir, &["rev-parse", "HEAD"]), "git rev-parse")
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, KernelError::GitFailed { command, exit_code, .. } if command == "git rev-parse" && *exit_code != 0),
+            "{err}"
+        );
+
+        let err = fs_read(FsReadIn {
+            path: dir.join("missing.txt").display().to_string(),
+            encoding: "utf8".to_string(),
+            maxBytes: None,
+        })
+        .err()
+        .unwrap();
+        assert!(
+            matches!(&err, KernelError::Io(e) if e.kind() == io::ErrorKind::NotFound),
+            "{err}"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn responses_are_wrapped_in_an_envelope() {
+        let ok = KernelResponse {
+            ok: true,
+            result: Some(RepoInfoOut {
+                version: 1,
+                repoRoot: None,
+                branch: String::new(),
+                sha: String::new(),
+                clean: false,
+            }),
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&ok).unwrap(),
+            serde_json::json!({
+                "ok": true,
+                "result": { "version": 1, "repoRoot": null, "branch": "", "sha": "", "clean": false },
+            })
+        );
+
+        let err = KernelError::GitFailed {
+            command: "git push".to_string(),
+            exit_code: 128,
+            stderr: "rejected".to_string(),
+        };
+        let value = serde_json::to_value(err.to_response()).unwrap();
+        assert_eq!(value["ok"], false);
+        assert_eq!(value["error"]["kind"], "gitFailed");
+        assert_eq!(value["error"]["message"], "rejected");
+        assert_eq!(value["error"]["details"]["exitCode"], 128);
+        assert!(value.get("result").is_none());
+    }
+
+    #[test]
+    fn batch_dispatches_operations_in_order() {
+        let dir = tmp_dir("ecc-kernel-test-batch");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("out.txt").display().to_string();
+        let ops = |stop: bool| BatchIn {
+            operations: vec![
+                BatchOp {
+                    command: "fs.write".to_string(),
+                    input: serde_json::json!({ "path": file, "content": "hi", "overwrite": true }),
+                },
+                BatchOp {
+                    command: "fs.read".to_string(),
+                    input: serde_json::json!({ "path": "relative.txt" }),
+                },
+                BatchOp {
+                    command: "fs.read".to_string(),
+                    input: serde_json::json!({ "path": file }),
+                },
+            ],
+            stopOnFirstError: stop,
+        };
+
+        let out = batch(ops(true)).unwrap();
+        let oks: Vec<bool> = out.results.iter().map(|r| r.ok).collect();
+        assert_eq!(oks, vec![true, false, false]);
+        assert_eq!(out.results[0].output.as_ref().unwrap()["bytesWritten"], 2);
+        assert!(out.results[1].error.as_ref().unwrap().contains("absolute"));
+        assert!(out.results[2]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("skipped"));
+
+        let out = batch(ops(false)).unwrap();
+        assert!(out.results[2].ok);
+        assert_eq!(out.results[2].output.as_ref().unwrap()["content"], "hi");
+
+        let out = batch(BatchIn {
+            operations: vec![
+                BatchOp {
+                    command: "batch".to_string(),
+                    input: serde_json::json!({ "operations": [] }),
+                },
+                BatchOp {
+                    command: "no.such".to_string(),
+                    input: serde_json::Value::Null,
+                },
+            ],
+            stopOnFirstError: false,
+        })
+        .unwrap();
+        assert!(out.results.iter().all(|r| !r.ok));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cli_io_flags_switch_to_files() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let cli = parse_cli_args(&args(&[
+            "--input-file",
+            "in.json",
+            "fs.read",
+            "--output-file=out.json",
+        ]))
+        .unwrap();
+        assert_eq!(cli.command.as_deref(), Some("fs.read"));
+        assert_eq!(cli.input, IoMode::File(PathBuf::from("in.json")));
+        assert_eq!(cli.output, IoMode::File(PathBuf::from("out.json")));
+        let cli = parse_cli_args(&args(&["repo.info"])).unwrap();
+        assert_eq!((cli.input, cli.output), (IoMode::Stdio, IoMode::Stdio));
+        assert!(parse_cli_args(&args(&["fs.read", "--input-file"])).is_err());
+        assert!(parse_cli_args(&args(&["fs.read", "extra"])).is_err());
+
+        let dir = tmp_dir("ecc-kernel-test-io-mode");
+        fs::create_dir_all(&dir).unwrap();
+        let input = IoMode::File(dir.join("in.json"));
+        let output = IoMode::File(dir.join("out.json"));
+        fs::write(dir.join("in.json"), "  {\"a\": 1}\n").unwrap();
+        let value: serde_json::Value = read_input_json(&input).unwrap();
+        write_output_json(&output, &value).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("out.json")).unwrap(),
+            r#"{"ok":true,"result":{"a":1}}"#
+        );
+
+        fs::write(dir.join("in.json"), "").unwrap();
+        let err = read_input_json::<serde_json::Value>(&input).err().unwrap();
+        assert!(
+            err.to_string().starts_with("missing JSON input on "),
+            "{err}"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_skips_mutating_commands_only() {
+        let args: Vec<String> = vec!["fs.write".to_string(), "--dry-run".to_string()];
+        assert!(parse_cli_args(&args).unwrap().dryRun);
+
+        let dir = tmp_dir("ecc-kernel-test-dry-run");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("existing.txt"), "hi\n").unwrap();
+        let target = dir.join("new.txt");
+        let write_input = serde_json::json!({
+            "path": target.display().to_string(),
+            "content": "x",
+        });
+
+        DRY_RUN.with(|d| d.set(true));
+        let out = dispatch("fs.write", write_input.clone()).unwrap();
+        let read = dispatch(
+            "fs.read",
+            serde_json::json!({ "path": dir.join("existing.txt").display().to_string() }),
+        );
+        let batch = dispatch(
+            "batch",
+            serde_json::json!({ "operations": [{ "command": "fs.write", "input": write_input }] }),
+        );
+        let credential = dispatch(
+            "git.credential",
+            serde_json::json!({
+                "operation": "approve",
+                "protocol": "https",
+                "host": "example.com",
+                "password": "hunter2",
+            }),
+        )
+        .unwrap();
+        let commit = dispatch(
+            "git.commit_all",
+            serde_json::json!({ "repoRoot": dir.display().to_string(), "message": "m" }),
+        )
+        .unwrap();
+        let mutating: Vec<_> = COMMANDS
+            .iter()
+            .filter(|c| **c != "batch" && !READ_ONLY_COMMANDS.contains(c))
+            .map(|c| (*c, dispatch(c, serde_json::json!({})).unwrap()))
+            .collect();
+        DRY_RUN.with(|d| d.set(false));
+
+        for (cmd, placeholder) in mutating {
+            let schema = command_schema(cmd).unwrap();
+            let output = jsonschema::validator_for(&schema["output"]).unwrap();
+            assert!(output.is_valid(&placeholder), "{cmd}: {placeholder}");
+        }
+
+        assert_eq!(out["dryRun"], true);
+        assert_eq!(out["bytesWritten"], 0);
+        assert_eq!(out["created"], false);
+        assert!(!credential.to_string().contains("hunter2"));
+        assert_eq!(commit["sha"], DRY_RUN_PLACEHOLDER_SHA);
+        assert_eq!(commit["previousSha"], serde_json::Value::Null);
+        assert_eq!(commit["treeSha"], DRY_RUN_PLACEHOLDER_SHA);
+        assert!(!target.exists());
+        assert_eq!(read.unwrap()["content"], "hi\n");
+        assert_eq!(batch.unwrap()["results"][0]["output"]["dryRun"], true);
+        assert!(!target.exists());
+        assert!(READ_ONLY_COMMANDS.iter().all(|c| COMMANDS.contains(c)));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn config_file_supplies_defaults_below_env_and_flags() {
+        let dir = tmp_dir("ecc-kernel-test-config-file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kernel.json");
+        fs::write(
+            &path,
+            r#"{
+                "gitBinary": "/opt/git/bin/git",
+                "defaultTimeoutSecs": 30,
+                "maxOutputBytes": 1024,
+                "allowedRoots": ["/work"]
+            }"#,
+        )
+        .unwrap();
+        let args: Vec<String> = ["--config-file", path.to_str().unwrap(), "repo.info"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let cli = parse_cli_args(&args).unwrap();
+        assert_eq!(cli.configFile.as_deref(), Some(path.as_path()));
+        assert_eq!(cli.command.as_deref(), Some("repo.info"));
+
+        let config = load_kernel_config(&path).unwrap();
+        assert_eq!(config.gitBinary.as_deref(), Some("/opt/git/bin/git"));
+        assert_eq!(config.defaultTimeoutSecs, Some(30));
+        assert_eq!(config.maxOutputBytes, Some(1024));
+        assert_eq!(config.allowedRoots, vec!["/work".to_string()]);
+        assert!(config.auditLogPath.is_none());
+
+        assert_eq!(
+            env_or_config(None, &config.gitBinary),
+            Some("/opt/git/bin/git".into())
+        );
+        assert_eq!(
+            env_or_config(Some("".into()), &config.gitBinary),
+            Some("/opt/git/bin/git".into())
+        );
+        assert_eq!(
+            env_or_config(Some("/usr/local/bin/git".into()), &config.gitBinary),
+            Some("/usr/local/bin/git".into())
+        );
+        assert_eq!(env_or_config(None, &None), None);
+
+        fs::write(&path, r#"{ "gitBinray": "typo" }"#).unwrap();
+        assert_eq!(
+            load_kernel_config(&path).err().unwrap().kind(),
+            "invalidInput"
+        );
+        assert_eq!(
+            load_kernel_config(&dir.join("missing.json"))
+                .err()
+                .unwrap()
+                .kind(),
+            "io"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn pretty_flag_indents_json_output() {
+        let args: Vec<String> = vec!["--pretty".to_string(), "repo.info".to_string()];
+        assert!(parse_cli_args(&args).unwrap().pretty);
+
+        let value = serde_json::json!({ "ok": true, "result": { "a": [1, 2] } });
+        OUTPUT_CONFIG.with(|c| c.set(OutputConfig { pretty: false }));
+        assert!(!render_json(&value).unwrap().contains('\n'));
+        OUTPUT_CONFIG.with(|c| c.set(OutputConfig { pretty: true }));
+        assert!(render_json(&value).unwrap().contains('\n'));
+        OUTPUT_CONFIG.with(|c| c.set(OutputConfig::default()));
+    }
+
+    #[test]
+    fn version_info_reports_git_version_or_error() {
+        let fake = |program: &str, args: &[String]| {
+            assert_eq!(program, "/opt/git/bin/git");
+            assert_eq!(args, ["--version".to_string()]);
+            Ok(CmdOut {
+                ok: true,
+                status: 0,
+                stdout: "git version 2.45.1\n".to_string(),
+                stderr: String::new(),
+            })
+        };
+        let out = version_info(Some(PathBuf::from("/opt/git/bin/git")), fake);
+        assert_eq!(out.kernelVersion, env!("CARGO_PKG_VERSION"));
+        assert_eq!(out.gitVersion.as_deref(), Some("2.45.1"));
+        assert_eq!(out.gitPath.as_deref(), Some("/opt/git/bin/git"));
+        assert!(out.error.is_none());
+
+        let out = version_info(None, |_: &str, _: &[String]| unreachable!());
+        assert!(out.gitVersion.is_none() && out.gitPath.is_none());
+        assert!(out.error.unwrap().contains("git not found"));
+
+        let out = version_info(
+            Some(PathBuf::from("/missing/git")),
+            |p: &str, a: &[String]| run_cmd(p, a, None),
+        );
+        assert!(out.gitVersion.is_none());
+        assert!(out.error.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn git_binary_honours_ecc_git_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        assert_eq!(git_binary_from(None), "git");
+        assert_eq!(git_binary_from(Some("".into())), "git");
+
+        let dir = tmp_dir("ecc-kernel-test-git-binary");
+        fs::create_dir_all(&dir).unwrap();
+        let mock = dir.join("mock-git");
+        fs::write(&mock, "#!/bin/sh\necho \"mock-git $*\"\n").unwrap();
+        fs::set_permissions(&mock, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let program = git_binary_from(Some(mock.clone().into_os_string()));
+        assert_eq!(program, mock.display().to_string());
+        let out = run_cmd(&program, &["--version".to_string()], None).unwrap();
+        assert_eq!(out.stdout, "mock-git --version");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeouts_kill_the_whole_process_tree() {
+        let started = std::time::Instant::now();
+        // The backgrounded sleep keeps stdout open; only a tree kill lets the readers finish.
+        let err = run_cmd_with_timeout(
+            "sh",
+            &["-c".to_string(), "sleep 30 & wait".to_string()],
+            None,
+            None,
+            &[],
+            Some(std::time::Duration::from_secs(1)),
+        )
+        .err()
+        .unwrap();
+        assert!(
+            matches!(
+                &err,
+                KernelError::Timeout {
+                    timeout_secs: 1,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert_eq!(err.kind(), "timeout");
+
+        let dir = tmp_dir("ecc-kernel-test-verify-timeout");
+        let summary = verify_run(VerifyRunIn {
+            worktreePath: std::env::temp_dir().display().to_string(),
+            outDir: dir.display().to_string(),
+            commands: vec![
+                VerifyCmdIn {
+                    name: "hang".to_string(),
+                    command: "echo started; sleep 30".to_string(),
+                    timeoutSecs: Some(1),
+                    ..Default::default()
+                },
+                VerifyCmdIn {
+                    name: "fast".to_string(),
+                    command: "true".to_string(),
+                    ..Default::default()
+                },
+            ],
+            timeoutSecs: Some(60),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!summary.ok);
+        let hang = &summary.commands[0];
+        assert!(hang.timedOut && !hang.ok);
+        assert_eq!(hang.exitCode, -1);
+        let out = fs::read_to_string(&hang.outputPath).unwrap();
+        assert!(out.starts_with("started\n"), "{out}");
+        assert!(out.ends_with("\n[TIMEOUT after 1s]\n"), "{out}");
+        assert!(summary.commands[1].ok && !summary.commands[1].timedOut);
+        assert!(started.elapsed() < std::time::Duration::from_secs(20));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_injects_per_command_env() {
+        let dir = tmp_dir("ecc-kernel-test-verify-env");
+        let run = |inherit_env: bool| {
+            verify_run(VerifyRunIn {
+                worktreePath: std::env::temp_dir().display().to_string(),
+                outDir: dir.display().to_string(),
+                commands: vec![VerifyCmdIn {
+                    name: "echo".to_string(),
+                    command: "echo \"$MY_VAR|${CARGO_PKG_NAME:-unset}\"".to_string(),
+                    env: BTreeMap::from([("MY_VAR".to_string(), "hello".to_string())]),
+                    ..Default::default()
+                }],
+                inheritEnv: inherit_env,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let summary = run(true);
+        assert!(summary.ok);
+        let out = fs::read_to_string(&summary.commands[0].outputPath).unwrap();
+        assert!(out.starts_with("hello|"), "{out}");
+
+        // cargo sets CARGO_PKG_NAME for the test process; a hermetic run must not see it.
+        let summary = run(false);
+        let out = fs::read_to_string(&summary.commands[0].outputPath).unwrap();
+        assert_eq!(out.trim(), "hello|unset");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_parallel_runs_commands_concurrently() {
+        let dir = tmp_dir("ecc-kernel-test-verify-parallel");
+        let run = |parallel: bool, max_concurrency: Option<u32>| {
+            let started = std::time::Instant::now();
+            let summary = verify_run(VerifyRunIn {
+                worktreePath: std::env::temp_dir().display().to_string(),
+                outDir: dir.display().to_string(),
+                commands: ["a", "b"]
+                    .iter()
+                    .map(|name| VerifyCmdIn {
+                        name: name.to_string(),
+                        command: format!("sleep 1; echo {name}"),
+                        ..Default::default()
+                    })
+                    .collect(),
+                parallel,
+                maxConcurrency: max_concurrency,
+                ..Default::default()
+            })
+            .unwrap();
+            assert!(summary.ok);
+            let names: Vec<&str> = summary.commands.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, ["a", "b"]);
+            started.elapsed()
+        };
+
+        let sequential = run(false, None);
+        let parallel = run(true, None);
+        let bounded = run(true, Some(1));
+        assert!(sequential >= std::time::Duration::from_secs(2));
+        assert!(
+            parallel < std::time::Duration::from_millis(1900),
+            "{parallel:?}"
+        );
+        assert!(bounded >= std::time::Duration::from_secs(2));
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "b\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_fail_fast_skips_and_cancels_remaining_commands() {
+        let dir = tmp_dir("ecc-kernel-test-verify-failfast");
+        let run = |parallel: bool, commands: &[(&str, &str)]| {
+            verify_run(VerifyRunIn {
+                worktreePath: std::env::temp_dir().display().to_string(),
+                outDir: dir.display().to_string(),
+                commands: commands
+                    .iter()
+                    .map(|(name, command)| VerifyCmdIn {
+                        name: name.to_string(),
+                        command: command.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                parallel,
+                failFast: true,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let summary = run(false, &[("a", "exit 3"), ("b", "true"), ("c", "true")]);
+        assert!(!summary.ok);
+        let codes: Vec<i32> = summary.commands.iter().map(|c| c.exitCode).collect();
+        assert_eq!(codes, [3, -2, -2]);
+        assert_eq!(summary.commands[1].outputPath, "");
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["a.txt", "summary.json"]);
+
+        let started = std::time::Instant::now();
+        let summary = run(true, &[("fail", "sleep 0.2; exit 1"), ("hang", "sleep 30")]);
+        assert!(started.elapsed() < std::time::Duration::from_secs(20));
+        assert_eq!(summary.commands[0].exitCode, 1);
+        assert_eq!(summary.commands[1].exitCode, -2);
+        assert!(!summary.commands[1].outputPath.is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_caps_output_bytes() {
+        let dir = tmp_dir("ecc-kernel-test-verify-cap");
+        let spam_cmd = "head -c 5000 /dev/zero | tr '\\0' a; \
+                    head -c 5000 /dev/zero | tr '\\0' b >&2";
+        let summary = verify_run(VerifyRunIn {
+            worktreePath: std::env::temp_dir().display().to_string(),
+            outDir: dir.display().to_string(),
+            commands: [("spam", Some(1000)), ("quiet", Some(1000))]
+                .iter()
+                .map(|(name, cap)| VerifyCmdIn {
+                    name: name.to_string(),
+                    command: if *name == "spam" {
+                        spam_cmd.to_string()
+                    } else {
+                        "printf ok".to_string()
+                    },
+                    maxOutputBytes: *cap,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(summary.ok);
+
+        let spam = &summary.commands[0];
+        assert!(spam.truncated);
+        let footer = "\n[OUTPUT TRUNCATED at 1000 bytes]\n";
+        let out = fs::read_to_string(&spam.outputPath).unwrap();
+        assert_eq!(out.len(), 1000 + footer.len());
+        assert!(out.ends_with(footer));
+
+        assert!(!summary.commands[1].truncated);
+        assert_eq!(
+            fs::read_to_string(&summary.commands[1].outputPath).unwrap(),
+            "ok"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    const JUNIT_FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- <testcase name="commented-out"/> -->
+<testsuites>
+  <testsuite name="suite" tests="5">
+    <testcase classname="math" name="adds"/>
+    <testcase classname="math" name="divides &amp; rounds" time="0.1">
+      <failure message="expected 2 > 1">assertion failed</failure>
+    </testcase>
+    <testcase classname="io" name="reads">
+      <error type="Panic"><![CDATA[<testcase name="fake"/>]]></error>
+    </testcase>
+    <testcase classname="io" name="writes"><skipped/></testcase>
+    <testcase name="bare"></testcase>
+  </testsuite>
+</testsuites>
+"#;
+
+    #[test]
+    fn parse_junit_counts_outcomes() {
+        assert_eq!(
+            parse_junit(JUNIT_FIXTURE),
+            JUnitSummary {
+                total: 5,
+                passed: 2,
+                failed: 1,
+                errored: 1,
+                skipped: 1,
+                failedTests: vec!["math.divides & rounds".to_string(), "io.reads".to_string()],
+            }
+        );
+        assert_eq!(parse_junit(""), JUnitSummary::default());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_attaches_junit_results() {
+        let dir = tmp_dir("ecc-kernel-test-verify-junit");
+        let worktree = tmp_dir("ecc-kernel-test-verify-junit-wt");
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(worktree.join("report.xml"), JUNIT_FIXTURE).unwrap();
+        let summary = verify_run(VerifyRunIn {
+            worktreePath: worktree.display().to_string(),
+            outDir: dir.display().to_string(),
+            commands: ["report.xml", "missing.xml"]
+                .iter()
+                .map(|report| VerifyCmdIn {
+                    name: report.to_string(),
+                    command: "true".to_string(),
+                    junitOutputPath: Some(report.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+        .unwrap();
+        // Test failures in the report do not change the command's own result.
+        assert!(summary.ok);
+        assert_eq!(
+            summary.commands[0].testResults.as_ref().map(|r| r.failed),
+            Some(1)
+        );
+        assert!(summary.commands[1].testResults.is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&worktree);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_retries_failed_commands() {
+        let dir = tmp_dir("ecc-kernel-test-verify-retry");
+        let worktree = tmp_dir("ecc-kernel-test-verify-retry-wt");
+        fs::create_dir_all(&worktree).unwrap();
+        let flaky = "n=$(cat count 2>/dev/null || echo 0); n=$((n+1)); echo $n > count; \
+                 echo attempt $n; [ $n -ge 3 ]";
+        let run = |retry_count: Option<u32>| {
+            let _ = fs::remove_file(worktree.join("count"));
+            verify_run(VerifyRunIn {
+                worktreePath: worktree.display().to_string(),
+                outDir: dir.display().to_string(),
+                commands: vec![VerifyCmdIn {
+                    name: "flaky".to_string(),
+                    command: flaky.to_string(),
+                    retryCount: retry_count,
+                    retryDelayMs: Some(10),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let summary = run(Some(5));
+        assert!(summary.ok);
+        assert_eq!(summary.commands[0].attempts, 3);
+        let out = fs::read_to_string(&summary.commands[0].outputPath).unwrap();
+        assert_eq!(out, "attempt 3\n");
+
+        let summary = run(Some(1));
+        assert!(!summary.ok);
+        assert_eq!(summary.commands[0].attempts, 2);
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&worktree);
+    }
+
+    #[test]
+    fn verify_clean_removes_old_and_partial_runs() {
+        let base = tmp_dir("ecc-kernel-test-verify-clean");
+        let write_run = |name: &str, ran_at: Option<&str>| {
+            let dir = base.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("build.txt"), "12345").unwrap();
+            if let Some(ran_at) = ran_at {
+                fs::write(
+                    dir.join("summary.json"),
+                    format!(r#"{{"version":1,"ranAt":"{ran_at}","commands":[],"ok":true}}"#),
+                )
+                .unwrap();
+            }
+        };
+        write_run("run-old", Some("2000-01-01T00:00:00Z"));
+        write_run("run-new", Some(&now_iso()));
+        write_run("run-partial", None);
+        write_run("run-fresh-partial", None);
+        write_run("keep-old", Some("2000-01-01T00:00:00Z"));
+        write_run("run-repo", Some("2000-01-01T00:00:00Z"));
+        fs::create_dir_all(base.join("run-repo").join("src").join(".git")).unwrap();
+        write_run("run-notes", None);
+        fs::write(base.join("run-notes").join("notes.md"), "mine\n").unwrap();
+        // Partial runs are aged by the directory's mtime.
+        let day_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(86_400);
+        for name in ["run-partial", "run-notes"] {
+            File::open(base.join(name))
+                .unwrap()
+                .set_modified(day_ago)
+                .unwrap();
+        }
+
+        let out = verify_clean(VerifyCleanIn {
+            outDirPattern: base.join("run-*").display().to_string(),
+            olderThanSecs: Some(3600),
+        })
+        .unwrap();
+        let removed: Vec<String> = out
+            .removedDirs
+            .iter()
+            .map(|d| {
+                Path::new(d)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(removed, ["run-old", "run-partial"]);
+        assert!(out.bytesFreed >= 10);
+        assert!(base.join("run-new").is_dir());
+        assert!(base.join("run-fresh-partial").is_dir());
+        assert!(base.join("keep-old").is_dir());
+        assert_eq!(out.skippedDirs.len(), 2);
+        assert!(base.join("run-repo").join("src").join(".git").is_dir());
+        assert!(base.join("run-notes").join("notes.md").is_file());
+
+        for pattern in [
+            "/*".to_string(),
+            base.join("run-old").display().to_string(),
+            base.join(".git").join("run-*").display().to_string(),
+        ] {
+            let err = verify_clean(VerifyCleanIn {
+                outDirPattern: pattern,
+                olderThanSecs: None,
+            });
+            assert!(matches!(err, Err(KernelError::InvalidInput(_))));
+        }
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn verify_compare_reports_status_changes() {
+        let dir = tmp_dir("ecc-kernel-test-verify-compare");
+        fs::create_dir_all(&dir).unwrap();
+        let write = |file: &str, commands: &[(&str, i32)]| {
+            let commands: Vec<serde_json::Value> = commands
+                .iter()
+                .map(|(name, code)| {
+                    serde_json::json!({
+                        "name": name, "command": "x", "ok": *code == 0,
+                        "exitCode": code, "outputPath": "",
+                    })
+                })
+                .collect();
+            let path = dir.join(file);
+            let summary = serde_json::json!({
+                "version": 1, "ranAt": now_iso(), "commands": commands, "ok": false,
+            });
+            fs::write(&path, summary.to_string()).unwrap();
+            path.display().to_string()
+        };
+        let baseline = write(
+            "baseline.json",
+            &[
+                ("build", 0),
+                ("lint", 1),
+                ("test", 0),
+                ("fmt", 2),
+                ("gone", 0),
+            ],
+        );
+        let current = write(
+            "current.json",
+            &[
+                ("build", 0),
+                ("lint", 0),
+                ("test", 101),
+                ("fmt", 1),
+                ("added", 1),
+            ],
+        );
+
+        let out = verify_compare(VerifyCompareIn {
+            baselineSummaryPath: baseline,
+            currentSummaryPath: current,
+        })
+        .unwrap();
+        assert_eq!(out.newFailures, ["added", "gone", "test"]);
+        assert_eq!(out.newPasses, ["lint"]);
+        assert_eq!(out.unchanged, ["build", "fmt"]);
+        assert_eq!(
+            out.exitCodeChanges
+                .iter()
+                .map(|c| (c.name.as_str(), c.baseline, c.current))
+                .collect::<Vec<_>>(),
+            [("fmt", 2, 1), ("lint", 1, 0), ("test", 0, 101)]
+        );
+
+        let err = verify_compare(VerifyCompareIn {
+            baselineSummaryPath: dir.join("missing.json").display().to_string(),
+            currentSummaryPath: dir.join("current.json").display().to_string(),
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "io");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verify_run_writes_markdown_and_html_summaries() {
+        let dir = tmp_dir("ecc-kernel-test-verify-formats");
+        let run = |formats: &[&str]| {
+            verify_run(VerifyRunIn {
+                worktreePath: std::env::temp_dir().display().to_string(),
+                outDir: dir.display().to_string(),
+                commands: [("build", "true"), ("a|b <test>", "exit 2")]
+                    .iter()
+                    .map(|(name, command)| VerifyCmdIn {
+                        name: name.to_string(),
+                        command: command.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                summaryFormats: formats.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+
+        let summary = run(&["markdown", "html"]).unwrap();
+        assert_eq!(
+            summary.summaryPaths.keys().collect::<Vec<_>>(),
+            ["html", "json", "markdown"]
+        );
+        let md = fs::read_to_string(&summary.summaryPaths["markdown"]).unwrap();
+        assert!(md.contains(
+            "| Name | Status | Exit Code |\n| --- | --- | --- |\n\
+         | build | ✅ pass | 0 |\n\
+         | a\\|b <test> | **❌ fail** | 2 |\n"
+        ));
+        let html = fs::read_to_string(dir.join("summary.html")).unwrap();
+        assert!(html.contains("<td>a|b &lt;test&gt;</td><td>❌ fail</td><td>2</td>"));
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("summary.json")).unwrap()).unwrap();
+        assert_eq!(
+            json["summaryPaths"]["markdown"],
+            summary.summaryPaths["markdown"]
+        );
+
+        assert_eq!(run(&["pdf"]).err().unwrap().kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn verify_safe_name_and_output_path() {
+        let safe = |name: &str| {
+            verify_safe_name(VerifySafeNameIn {
+                name: name.to_string(),
+            })
+            .unwrap()
+            .safeName
+        };
+        assert_eq!(safe("Cargo Test (unit)"), "cargo-test-unit");
+        assert_eq!(safe("größe prüfen"), "gr-e-pr-fen");
+        assert_eq!(safe("日本語"), "command");
+        assert_eq!(safe("  "), "command");
+        assert_eq!(safe("--lint--"), "lint");
+        assert_eq!(safe("-a_b.c-"), "a_b.c");
+
+        let out = verify_output_path_cmd(VerifyOutputPathIn {
+            outDir: "/tmp/evidence".to_string(),
+            name: "Unit Tests".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            PathBuf::from(out.outputPath),
+            Path::new("/tmp/evidence").join("unit-tests.txt")
+        );
+    }
+
+    #[test]
+    fn commit_all_applies_author_and_committer_identity() {
+        let dir = tmp_dir("ecc-kernel-test-commit-identity");
+        init_git_repo(&dir).unwrap();
+        let identity = |name: &str, email: &str, date: Option<&str>| GitIdentity {
+            name: name.to_string(),
+            email: email.to_string(),
+            date: date.map(str::to_string),
+        };
+
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        commit_all(CommitAllIn {
+            repoRoot: dir.display().to_string(),
+            message: "identity".to_string(),
+            author: Some(identity(
+                "Ada",
+                "ada@example.com",
+                Some("2020-01-02T03:04:05+01:00"),
+            )),
+            committer: Some(identity("Bot", "bot@example.com", None)),
+            ..Default::default()
+        })
+        .unwrap();
+        let log = git(&dir, &["log", "-1", "--format=%an|%ae|%aI|%cn|%ce"]).unwrap();
+        assert_eq!(
+            log.stdout,
+            "Ada|ada@example.com|2020-01-02T03:04:05+01:00|Bot|bot@example.com"
+        );
+
+        fs::write(dir.join("b.txt"), "b\n").unwrap();
+        let err = commit_all(CommitAllIn {
+            repoRoot: dir.display().to_string(),
+            message: "bad date".to_string(),
+            author: Some(identity("Ada", "ada@example.com", Some("yesterday"))),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_all_allow_empty_and_no_verify() {
+        let dir = tmp_dir("ecc-kernel-test-commit-empty");
+        init_git_repo(&dir).unwrap();
+        let commit = |allow_empty: bool, no_verify: bool| {
+            commit_all(CommitAllIn {
+                repoRoot: dir.display().to_string(),
+                message: "empty".to_string(),
+                allowEmpty: allow_empty,
+                noVerify: no_verify,
+                ..Default::default()
+            })
+        };
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+
+        let err = commit(false, false).err().unwrap();
+        assert_eq!(err.kind(), "gitFailed");
+        let out = commit(true, false).unwrap();
+        assert_eq!(out.sha.len(), 40);
+        assert_ne!(out.sha, head);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let hook = dir.join(".git/hooks/pre-commit");
+            fs::write(&hook, "#!/bin/sh\nexit 1\n").unwrap();
+            fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+            fs::write(dir.join("a.txt"), "a\n").unwrap();
+            assert_eq!(commit(false, false).err().unwrap().kind(), "gitFailed");
+            assert!(commit(false, true).is_ok());
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_all_amends_the_last_commit() {
+        let dir = tmp_dir("ecc-kernel-test-commit-amend");
+        let commit = |dir: &Path, amend: bool| {
+            commit_all(CommitAllIn {
+                repoRoot: dir.display().to_string(),
+                message: "amended".to_string(),
+                amend,
+                ..Default::default()
+            })
+        };
+
+        // Unborn branch: nothing to amend.
+        fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init"]).unwrap();
+        assert_eq!(commit(&dir, true).err().unwrap().kind(), "notFound");
+        let _ = fs::remove_dir_all(&dir);
+
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        let out = commit(&dir, true).unwrap();
+        assert_eq!(out.previousSha.as_deref(), Some(head.as_str()));
+        assert_ne!(out.sha, head);
+        let log = git(&dir, &["log", "--oneline"]).unwrap();
+        assert_eq!(log.stdout.lines().count(), 1);
+        let files = git(&dir, &["show", "--name-only", "--format=", "HEAD"]).unwrap();
+        assert_eq!(files.stdout, "a.txt\nbase.txt");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_all_appends_trailers() {
+        let dir = tmp_dir("ecc-kernel-test-commit-trailers");
+        init_git_repo(&dir).unwrap();
+        let trailer = |token: &str, value: &str| CommitTrailer {
+            token: token.to_string(),
+            value: value.to_string(),
+        };
+        let commit = |trailers: Vec<CommitTrailer>| {
+            commit_all(CommitAllIn {
+                repoRoot: dir.display().to_string(),
+                message: "Add a\n\nBody text.\n".to_string(),
+                allowEmpty: true,
+                trailers,
+                ..Default::default()
+            })
+        };
+
+        commit(vec![
+            trailer("Co-authored-by", "Alice <alice@example.com>"),
+            trailer("Reviewed-by", "Bob <bob@example.com>"),
+        ])
+        .unwrap();
+        let body = git(&dir, &["log", "-1", "--format=%B"]).unwrap();
+        assert_eq!(
+        body.stdout,
+        "Add a\n\nBody text.\n\nCo-authored-by: Alice <alice@example.com>\nReviewed-by: Bob <bob@example.com>"
+    );
+        let parsed = git(
+            &dir,
+            &[
+                "log",
+                "-1",
+                "--format=%(trailers:key=Reviewed-by,valueonly)",
+            ],
+        )
+        .unwrap();
+        assert_eq!(parsed.stdout, "Bob <bob@example.com>");
+
+        for bad in ["Signed off by", "Signed-off-by:", ""] {
+            let err = commit(vec![trailer(bad, "x")]).err().unwrap();
+            assert_eq!(err.kind(), "invalidInput", "{bad:?}");
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_all_returns_tree_sha() {
+        let dir = tmp_dir("ecc-kernel-test-commit-tree");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        let out = commit_all(CommitAllIn {
+            repoRoot: dir.display().to_string(),
+            message: "tree".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(out.sha.len(), 40);
+        assert_eq!(out.treeSha.len(), 40);
+        assert!(out.treeSha.chars().all(|c| c.is_ascii_hexdigit()));
+        let raw = git(&dir, &["cat-file", "-p", "HEAD"]).unwrap();
+        let tree_line = raw.stdout.lines().find(|l| l.starts_with("tree ")).unwrap();
+        assert_eq!(tree_line, format!("tree {}", out.treeSha));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_all_stages_only_selected_paths() {
+        let dir = tmp_dir("ecc-kernel-test-commit-paths");
+        init_git_repo(&dir).unwrap();
+        let commit = |paths: Option<Vec<&str>>| {
+            commit_all(CommitAllIn {
+                repoRoot: dir.display().to_string(),
+                message: "partial".to_string(),
+                paths: paths.map(|p| p.iter().map(|s| s.to_string()).collect()),
+                ..Default::default()
+            })
+        };
+        fs::write(dir.join("base.txt"), "changed\n").unwrap();
+        fs::write(dir.join("other.txt"), "other\n").unwrap();
+        git(&dir, &["add", "other.txt"]).unwrap();
+        git(&dir, &["commit", "-m", "other"]).unwrap();
+        fs::write(dir.join("other.txt"), "other changed\n").unwrap();
+
+        commit(Some(vec!["base.txt"])).unwrap();
+        let unstaged = git(&dir, &["diff", "--name-only"]).unwrap();
+        assert_eq!(unstaged.stdout, "other.txt");
+        let committed = git(&dir, &["show", "--name-only", "--format=", "HEAD"]).unwrap();
+        assert_eq!(committed.stdout, "base.txt");
+
+        // An empty list commits only what is already staged.
+        assert_eq!(commit(Some(vec![])).err().unwrap().kind(), "gitFailed");
+        git(&dir, &["add", "other.txt"]).unwrap();
+        commit(Some(vec![])).unwrap();
+        assert_eq!(git(&dir, &["status", "--porcelain"]).unwrap().stdout, "");
+
+        let err = commit(Some(vec!["../escape.txt"])).err().unwrap();
+        assert_eq!(err.kind(), "pathTraversal");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_ensure_branches_from_remote_tracking_branch() {
+        let origin = tmp_dir("ecc-kernel-test-wt-remote-origin");
+        init_git_repo(&origin).unwrap();
+        let base = git(&origin, &["rev-parse", "HEAD"]).unwrap().stdout;
+        git(&origin, &["checkout", "-b", "feature"]).unwrap();
+        fs::write(origin.join("feature.txt"), "feature\n").unwrap();
+        git(&origin, &["add", "-A"]).unwrap();
+        git(&origin, &["commit", "-m", "feature"]).unwrap();
+        let feature = git(&origin, &["rev-parse", "HEAD"]).unwrap().stdout;
+        git(&origin, &["checkout", "-"]).unwrap();
+
+        let clone = tmp_dir("ecc-kernel-test-wt-remote-clone");
+        let out = git(
+            &std::env::temp_dir(),
+            &[
+                "clone",
+                "-q",
+                &origin.display().to_string(),
+                &clone.display().to_string(),
+            ],
+        )
+        .unwrap();
+        assert!(out.ok, "{}", out.stderr);
+
+        let wt = tmp_dir("ecc-kernel-test-wt-remote-wt");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: clone.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "feature".to_string(),
+            baseSha: base.clone(),
+            remoteName: Some("origin".to_string()),
+            trackRemote: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(git(&wt, &["rev-parse", "HEAD"]).unwrap().stdout, feature);
+        let upstream = git(&clone, &["rev-parse", "--abbrev-ref", "feature@{upstream}"]).unwrap();
+        assert_eq!(upstream.stdout, "origin/feature");
+
+        // Unknown on the remote: falls back to branching at baseSha, without tracking.
+        let wt2 = tmp_dir("ecc-kernel-test-wt-remote-wt2");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: clone.display().to_string(),
+            worktreePath: wt2.display().to_string(),
+            branch: "local-only".to_string(),
+            baseSha: base.clone(),
+            remoteName: Some("origin".to_string()),
+            trackRemote: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(git(&wt2, &["rev-parse", "HEAD"]).unwrap().stdout, base);
+        assert!(
+            !git(
+                &clone,
+                &["rev-parse", "--abbrev-ref", "local-only@{upstream}"]
+            )
+            .unwrap()
+            .ok
+        );
+
+        for d in [&origin, &clone, &wt, &wt2] {
+            let _ = fs::remove_dir_all(d);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn worktree_ensure_resolves_symlinks_into_repo_root() {
+        let dir = tmp_dir("ecc-kernel-test-wt-symlink");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let link = tmp_dir("ecc-kernel-test-wt-symlink-link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        let wt = link.join("wt");
+
+        // Lexically the path is outside the repo; only resolution catches it.
+        assert!(assert_external_worktree_path(&dir, &wt, false).is_ok());
+        assert_eq!(
+            canonical_worktree_path(&wt, true).unwrap(),
+            fs::canonicalize(&dir).unwrap().join("wt")
+        );
+        assert_eq!(canonical_worktree_path(&wt, false).unwrap(), wt);
+
+        let err = worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "feature".to_string(),
+            baseSha: head,
+            resolveSymlinks: true,
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "pathTraversal");
+        assert!(!dir.join("wt").exists());
+
+        let _ = fs::remove_file(&link);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_ensure_detached_head() {
+        let dir = tmp_dir("ecc-kernel-test-wt-detach");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-wt-detach-wt");
+        let ensure = |branch: &str| {
+            worktree_ensure(WorktreeEnsureIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: wt.display().to_string(),
+                branch: branch.to_string(),
+                baseSha: head.clone(),
+                detach: true,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(ensure("wip").err().unwrap().kind(), "invalidInput");
+        let out = ensure("").unwrap();
+        assert_eq!(out.head, head);
+        assert_eq!(git(&wt, &["branch", "--show-current"]).unwrap().stdout, "");
+        // Idempotent: the existing worktree is reused.
+        assert_eq!(ensure("").unwrap().head, head);
+
+        let _ = fs::remove_dir_all(&wt);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_ensure_rejects_option_like_branch_and_base() {
+        let dir = tmp_dir("ecc-kernel-test-wt-option");
+        init_git_repo(&dir).unwrap();
+        assert!(git(&dir, &["branch", "keep"]).unwrap().ok);
+        let wt = tmp_dir("ecc-kernel-test-wt-option-wt");
+        let ensure = |branch: &str, base: &str| {
+            worktree_ensure(WorktreeEnsureIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: wt.display().to_string(),
+                branch: branch.to_string(),
+                baseSha: base.to_string(),
+                ..Default::default()
+            })
+        };
+
+        // `git branch -D keep` would delete the existing branch.
+        assert_eq!(ensure("-D", "keep").err().unwrap().kind(), "invalidInput");
+        assert_eq!(
+            ensure("wip", "--orphan").err().unwrap().kind(),
+            "invalidInput"
+        );
+        assert!(branch_exists(&dir, "keep"));
+        assert!(!wt.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_ensure_applies_sparse_patterns() {
+        let dir = tmp_dir("ecc-kernel-test-wt-sparse");
+        init_git_repo(&dir).unwrap();
+        for d in ["app", "lib", "docs"] {
+            fs::create_dir_all(dir.join(d)).unwrap();
+            fs::write(dir.join(d).join("file.txt"), d).unwrap();
+        }
+        git(&dir, &["add", "-A"]).unwrap();
+        git(&dir, &["commit", "-m", "dirs"]).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-wt-sparse-wt");
+        let ensure = |patterns: &[&str]| {
+            worktree_ensure(WorktreeEnsureIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: wt.display().to_string(),
+                branch: "sparse".to_string(),
+                baseSha: head.clone(),
+                sparsePatterns: Some(patterns.iter().map(|p| p.to_string()).collect()),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        assert!(ensure(&["app"]).sparsePatternsApplied);
+        assert!(wt.join("app/file.txt").exists());
+        assert!(!wt.join("lib/file.txt").exists());
+        assert!(!wt.join("docs/file.txt").exists());
+        // Top-level files stay in cone mode.
+        assert!(wt.join("base.txt").exists());
+
+        assert!(!ensure(&["app/"]).sparsePatternsApplied);
+        assert!(ensure(&["app", "lib"]).sparsePatternsApplied);
+        assert!(wt.join("lib/file.txt").exists());
+        assert!(!wt.join("docs/file.txt").exists());
+
+        let _ = fs::remove_dir_all(&wt);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn worktree_repair_fixes_moved_worktree() {
+        let dir = tmp_dir("ecc-kernel-test-wt-repair");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-wt-repair-old");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "moved".to_string(),
+            baseSha: head,
+            ..Default::default()
+        })
+        .unwrap();
+        let moved = tmp_dir("ecc-kernel-test-wt-repair-new");
+        fs::rename(&wt, &moved).unwrap();
+        let list = |dir: &Path| {
+            worktree_list(WorktreeListIn {
+                repoRoot: dir.display().to_string(),
+            })
+            .unwrap()
+            .worktrees
+        };
+        assert!(list(&dir)[1].isPrunable);
+
+        // Without the new location git cannot find it.
+        let out = worktree_repair(WorktreeRepairIn {
+            repoRoot: dir.display().to_string(),
+            worktreePaths: Vec::new(),
+        })
+        .unwrap();
+        assert!(out.repairedPaths.is_empty());
+        assert_eq!(out.failedPaths.len(), 1);
+
+        let missing = tmp_dir("ecc-kernel-test-wt-repair-missing")
+            .display()
+            .to_string();
+        // A path that does not help leaves the stale registration reported as well.
+        let out = worktree_repair(WorktreeRepairIn {
+            repoRoot: dir.display().to_string(),
+            worktreePaths: vec![missing.clone()],
+        })
+        .unwrap();
+        assert!(out.repairedPaths.is_empty());
+        assert_eq!(out.failedPaths.len(), 2);
+        assert_eq!(out.failedPaths[0], missing);
+
+        let out = worktree_repair(WorktreeRepairIn {
+            repoRoot: dir.display().to_string(),
+            worktreePaths: vec![moved.display().to_string(), missing.clone()],
+        })
+        .unwrap();
+        assert_eq!(out.repairedPaths, [moved.display().to_string()]);
+        assert_eq!(out.failedPaths, vec![missing.clone()]);
+
+        // Nothing is stale any more, so only the bad path is reported.
+        let out = worktree_repair(WorktreeRepairIn {
+            repoRoot: dir.display().to_string(),
+            worktreePaths: vec![missing.clone()],
+        })
+        .unwrap();
+        assert_eq!(out.failedPaths, [missing]);
+        let option = worktree_repair(WorktreeRepairIn {
+            repoRoot: dir.display().to_string(),
+            worktreePaths: vec!["--help".to_string()],
+        });
+        assert_eq!(option.err().unwrap().kind(), "invalidInput");
+
+        let wts = list(&dir);
+        assert!(!wts[1].isPrunable);
+        assert_eq!(canon(&wts[1].path), canon(&moved.display().to_string()));
+        assert_eq!(
+            git(&moved, &["branch", "--show-current"]).unwrap().stdout,
+            "moved"
+        );
+
+        let _ = fs::remove_dir_all(&moved);
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    fn init_git_repo(repo: &Path) -> Result<(), String> {
-        fs::create_dir_all(repo).map_err(|e| format!("mkdir failed: {e}"))?;
-        let out = git(repo, &["init"])?;
-        if !out.ok {
-            return Err(out.stderr);
+    #[test]
+    fn worktree_inspect_reports_single_worktree() {
+        let dir = tmp_dir("ecc-kernel-test-wt-inspect");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-wt-inspect-wt");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            baseSha: head.clone(),
+            detach: true,
+            ..Default::default()
+        })
+        .unwrap();
+        git(
+            &dir,
+            &[
+                "worktree",
+                "lock",
+                "--reason",
+                "in use by ci",
+                &wt.display().to_string(),
+            ],
+        )
+        .unwrap();
+        let inspect = |path: &Path| {
+            worktree_inspect(WorktreeInspectIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: path.display().to_string(),
+            })
+        };
+
+        let out = inspect(&wt).unwrap();
+        assert_eq!(canon(&out.path), canon(&wt.display().to_string()));
+        assert_eq!(out.head, head);
+        assert!(out.branch.is_none() && out.isDetached);
+        assert!(out.isLocked);
+        assert_eq!(out.lockReason.as_deref(), Some("in use by ci"));
+        assert!(!out.isBare && !out.isMissing);
+
+        let main = inspect(&dir).unwrap();
+        assert!(main.branch.is_some() && !main.isDetached && !main.isLocked);
+
+        fs::remove_dir_all(&wt).unwrap();
+        assert!(inspect(&wt).unwrap().isMissing);
+
+        let other = tmp_dir("ecc-kernel-test-wt-inspect-none");
+        assert_eq!(inspect(&other).err().unwrap().kind(), "notFound");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Local submodule URLs use the `file` transport, which git disables by default.
+    fn allow_file_protocol_env() -> Vec<(String, String)> {
+        [
+            ("GIT_CONFIG_COUNT", "1"),
+            ("GIT_CONFIG_KEY_0", "protocol.file.allow"),
+            ("GIT_CONFIG_VALUE_0", "always"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    /// A repo whose `sub` submodule points at a second local repo; returns (super, sub).
+    fn init_repo_with_submodule(prefix: &str) -> (PathBuf, PathBuf) {
+        let sub = tmp_dir(&format!("{prefix}-sub"));
+        init_git_repo(&sub).unwrap();
+        let dir = tmp_dir(prefix);
+        init_git_repo(&dir).unwrap();
+        let out = git(
+            &dir,
+            &[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "add",
+                &sub.display().to_string(),
+                "sub",
+            ],
+        )
+        .unwrap();
+        assert!(out.ok, "{}", out.stderr);
+        git(&dir, &["commit", "-m", "add submodule"]).unwrap();
+        (dir, sub)
+    }
+
+    #[test]
+    fn git_submodule_update_initialises_worktree_submodules() {
+        let (dir, sub) = init_repo_with_submodule("ecc-kernel-test-submodule-update");
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-submodule-update-wt");
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "with-sub".to_string(),
+            baseSha: head,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!wt.join("sub/base.txt").exists());
+
+        let out = run_git_submodule_update(
+            GitSubmoduleUpdateIn {
+                repoRoot: wt.display().to_string(),
+                init: true,
+                recursive: true,
+                remote: false,
+                paths: Vec::new(),
+            },
+            &allow_file_protocol_env(),
+        )
+        .unwrap();
+        assert_eq!(out.updated, ["sub"]);
+        assert!(wt.join("sub/base.txt").exists());
+
+        for d in [&wt, &dir, &sub] {
+            let _ = fs::remove_dir_all(d);
         }
+    }
 
-        // Ensure commits work in CI.
-        let _ = git(repo, &["config", "user.email", "ecc@example.com"])?;
-        let _ = git(repo, &["config", "user.name", "ECC"])?;
+    #[test]
+    fn git_submodule_list_reports_status() {
+        let (dir, sub) = init_repo_with_submodule("ecc-kernel-test-submodule-list");
+        let sub_head = git(&sub, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let clone = tmp_dir("ecc-kernel-test-submodule-list-clone");
+        git(
+            &dir,
+            &[
+                "clone",
+                "-q",
+                &dir.display().to_string(),
+                &clone.display().to_string(),
+            ],
+        )
+        .unwrap();
+        let list = |repo: &Path| {
+            git_submodule_list(GitSubmoduleListIn {
+                repoRoot: repo.display().to_string(),
+            })
+            .unwrap()
+            .submodules
+        };
 
-        fs::write(repo.join("base.txt"), "base\n").map_err(|e| format!("write failed: {e}"))?;
-        let out_add = git(repo, &["add", "-A"])?;
-        if !out_add.ok {
-            return Err(out_add.stderr);
+        let subs = list(&clone);
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].name, "sub");
+        assert_eq!(subs[0].path, "sub");
+        assert_eq!(subs[0].url, sub.display().to_string());
+        assert_eq!(subs[0].status, SubmoduleStatus::Uninitialized);
+        assert!(subs[0].head.is_none());
+
+        let subs = list(&dir);
+        assert_eq!(subs[0].status, SubmoduleStatus::Initialized);
+        assert_eq!(subs[0].head.as_deref(), Some(sub_head.as_str()));
+
+        fs::write(dir.join("sub/new.txt"), "new\n").unwrap();
+        git(&dir.join("sub"), &["add", "-A"]).unwrap();
+        let out = git(
+            &dir.join("sub"),
+            &[
+                "-c",
+                "user.name=ECC",
+                "-c",
+                "user.email=ecc@example.com",
+                "commit",
+                "-m",
+                "moved on",
+            ],
+        )
+        .unwrap();
+        assert!(out.ok, "{}", out.stderr);
+        assert_eq!(list(&dir)[0].status, SubmoduleStatus::Dirty);
+
+        assert!(list(&sub).is_empty());
+
+        for d in [&clone, &dir, &sub] {
+            let _ = fs::remove_dir_all(d);
         }
-        let out_commit = git(repo, &["commit", "-m", "init"])?;
-        if !out_commit.ok {
-            return Err(out_commit.stderr);
+    }
+
+    #[test]
+    fn git_format_patch_exports_commits() {
+        let dir = tmp_dir("ecc-kernel-test-format-patch");
+        init_git_repo(&dir).unwrap();
+        for name in ["one", "two"] {
+            fs::write(dir.join(format!("{name}.txt")), name).unwrap();
+            git(&dir, &["add", "-A"]).unwrap();
+            git(&dir, &["commit", "-m", &format!("add {name}")]).unwrap();
         }
-        Ok(())
+        let out_dir = tmp_dir("ecc-kernel-test-format-patch-out");
+        let out = git_format_patch(GitFormatPatchIn {
+            repoRoot: dir.display().to_string(),
+            range: "HEAD~2..HEAD".to_string(),
+            outputDir: out_dir.display().to_string(),
+            numberLines: true,
+            coverLetter: true,
+            thread: false,
+        })
+        .unwrap();
+        let names: Vec<String> = out
+            .patchFiles
+            .iter()
+            .map(|p| {
+                Path::new(p)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "0000-cover-letter.patch",
+                "0001-add-one.patch",
+                "0002-add-two.patch"
+            ]
+        );
+        let patch = fs::read_to_string(&out.patchFiles[2]).unwrap();
+        assert!(patch.contains("Subject: [PATCH 2/2] add two"), "{patch}");
+
+        let err = git_format_patch(GitFormatPatchIn {
+            repoRoot: dir.display().to_string(),
+            range: "--stdout".to_string(),
+            outputDir: out_dir.display().to_string(),
+            numberLines: false,
+            coverLetter: false,
+            thread: false,
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "invalidInput");
+
+        let _ = fs::remove_dir_all(&out_dir);
+        let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn protocol_version_has_required_fields() {
-        let out = protocol_version(ProtocolVersionIn {}).unwrap();
-        assert_eq!(out.version, 1);
-        assert_eq!(out.protocol, PROTOCOL_VERSION);
-        assert!(!out.kernelVersion.trim().is_empty());
+    fn git_am_applies_format_patch_series() {
+        let src = tmp_dir("ecc-kernel-test-am-src");
+        init_git_repo(&src).unwrap();
+        let dst = tmp_dir("ecc-kernel-test-am-dst");
+        git(
+            &src,
+            &[
+                "clone",
+                "-q",
+                &src.display().to_string(),
+                &dst.display().to_string(),
+            ],
+        )
+        .unwrap();
+        git(&dst, &["config", "user.email", "ecc@example.com"]).unwrap();
+        git(&dst, &["config", "user.name", "ECC"]).unwrap();
+        for (name, body) in [("one.txt", "one\n"), ("base.txt", "patched\n")] {
+            fs::write(src.join(name), body).unwrap();
+            git(&src, &["add", "-A"]).unwrap();
+            git(&src, &["commit", "-m", &format!("change {name}")]).unwrap();
+        }
+        let patches = tmp_dir("ecc-kernel-test-am-patches");
+        let patch_files = git_format_patch(GitFormatPatchIn {
+            repoRoot: src.display().to_string(),
+            range: "HEAD~2..HEAD".to_string(),
+            outputDir: patches.display().to_string(),
+            numberLines: false,
+            coverLetter: false,
+            thread: false,
+        })
+        .unwrap()
+        .patchFiles;
+        let am = |patch_paths: Vec<String>, abort: bool| {
+            git_am(GitAmIn {
+                repoRoot: dst.display().to_string(),
+                patchPaths: patch_paths,
+                threeWay: false,
+                signoff: true,
+                keepSubject: false,
+                abort,
+                continue_: false,
+                skip: false,
+            })
+            .unwrap()
+        };
 
-        let cmds: std::collections::BTreeSet<String> = out.commands.into_iter().collect();
-        for c in COMMANDS.iter() {
-            assert!(cmds.contains(&c.to_string()), "missing command: {c}");
+        // A conflicting local change stops the series on the second patch.
+        fs::write(dst.join("base.txt"), "local\n").unwrap();
+        git(&dst, &["commit", "-am", "local"]).unwrap();
+        let out = am(patch_files.clone(), false);
+        assert!(out.inProgress);
+        assert_eq!(out.appliedCommits.len(), 1);
+        assert_eq!(out.conflictFiles, ["base.txt"]);
+        let out = am(Vec::new(), true);
+        assert!(!out.inProgress);
+
+        git(&dst, &["reset", "--hard", "HEAD~1"]).unwrap();
+        let out = am(patch_files, false);
+        assert!(!out.inProgress && out.conflictFiles.is_empty());
+        assert_eq!(out.appliedCommits.len(), 2);
+        let head = git(&dst, &["rev-parse", "HEAD"]).unwrap().stdout;
+        assert_eq!(out.appliedCommits[1], head);
+        let body = git(&dst, &["log", "-1", "--format=%B"]).unwrap().stdout;
+        assert!(
+            body.contains("Signed-off-by: ECC <ecc@example.com>"),
+            "{body}"
+        );
+
+        for d in [&patches, &dst, &src] {
+            let _ = fs::remove_dir_all(d);
         }
     }
 
     #[test]
-    fn repo_info_returns_repo_details_and_cleanliness() {
-        let dir = tmp_dir("ecc-kernel-test-repo");
-        let res = init_git_repo(&dir);
-        assert!(res.is_ok(), "init_git_repo failed: {:?}", res.err());
+    fn git_remote_add_and_list() {
+        let upstream = tmp_dir("ecc-kernel-test-remote-upstream");
+        init_git_repo(&upstream).unwrap();
+        let dir = tmp_dir("ecc-kernel-test-remote");
+        init_git_repo(&dir).unwrap();
+        let url = format!("file://{}", upstream.display());
+        let add = |name: &str, url: &str| {
+            git_remote_add(GitRemoteAddIn {
+                repoRoot: dir.display().to_string(),
+                name: name.to_string(),
+                url: url.to_string(),
+                fetch: true,
+            })
+        };
 
-        let info = repo_info(RepoInfoIn {
-            cwd: dir.display().to_string(),
+        assert!(add("upstream", &url).unwrap().ok);
+        let out = git_remote_list(GitRemoteListIn {
+            repoRoot: dir.display().to_string(),
         })
         .unwrap();
+        assert_eq!(out.remotes.len(), 1);
+        assert_eq!(out.remotes[0].name, "upstream");
+        assert_eq!(out.remotes[0].fetchUrl, url);
+        assert_eq!(out.remotes[0].pushUrl, url);
+        // --fetch populated the remote-tracking refs.
+        let tracking = git(&dir, &["branch", "-r"]).unwrap().stdout;
+        assert!(tracking.contains("upstream/"), "{tracking}");
 
-        assert_eq!(info.version, 1);
-        let got_root = info.repoRoot.clone().unwrap();
-        let expected_abs = fs::canonicalize(&dir).unwrap();
-        let got_abs = fs::canonicalize(std::path::Path::new(&got_root)).unwrap();
-        assert_eq!(got_abs, expected_abs);
-        assert!(!info.branch.trim().is_empty());
-        assert_eq!(info.sha.len(), 40);
-        assert_eq!(info.clean, true);
+        for (name, url) in [
+            ("bad name", url.as_str()),
+            ("x", "https://x;rm -rf ~"),
+            ("y", "$(id)"),
+            ("z", "--upload-pack=x"),
+        ] {
+            assert_eq!(
+                add(name, url).err().unwrap().kind(),
+                "invalidInput",
+                "{name} {url}"
+            );
+        }
+        assert_eq!(add("upstream", &url).err().unwrap().kind(), "gitFailed");
 
-        // Modifying a tracked file should mark repo as dirty.
-        fs::write(dir.join("base.txt"), "changed\n").unwrap();
-        let info2 = repo_info(RepoInfoIn {
-            cwd: dir.display().to_string(),
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&upstream);
+    }
+
+    #[test]
+    fn git_reflog_shows_pre_reset_sha() {
+        let dir = tmp_dir("ecc-kernel-test-reflog");
+        init_git_repo(&dir).unwrap();
+        let first = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        fs::write(dir.join("base.txt"), "second\n").unwrap();
+        assert!(
+            git(&dir, &["commit", "-q", "-am", "second message"])
+                .unwrap()
+                .ok
+        );
+        let second = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        git_reset(GitResetIn {
+            repoRoot: dir.display().to_string(),
+            target: first.clone(),
+            mode: Some("hard".to_string()),
+            paths: Vec::new(),
         })
         .unwrap();
-        assert_eq!(info2.clean, false);
+        let reflog = |ref_: Option<&str>, max: Option<u32>| {
+            git_reflog(GitReflogIn {
+                repoRoot: dir.display().to_string(),
+                ref_: ref_.map(|r| r.to_string()),
+                maxCount: max,
+            })
+        };
+
+        let out = reflog(None, None).unwrap();
+        assert_eq!(out.entries.len(), 3);
+        assert_eq!(out.entries[0].sha, first);
+        assert_eq!(out.entries[0].previousSha, second);
+        assert!(out.entries[0].subject.starts_with("reset: moving to"));
+        assert_eq!(out.entries[1].sha, second);
+        assert_eq!(out.entries[1].subject, "commit: second message");
+        assert_eq!(out.entries[1].author, "ECC");
+        assert!(out.entries[1].date.contains('T'), "{}", out.entries[1].date);
+        assert_eq!(out.entries[2].previousSha, "");
+
+        let limited = reflog(Some("HEAD"), Some(1)).unwrap();
+        assert_eq!(limited.entries.len(), 1);
+        assert_eq!(limited.entries[0].previousSha, second);
+
+        assert_eq!(reflog(None, Some(0)).err().unwrap().kind(), "invalidInput");
+        assert_eq!(
+            reflog(Some("--all"), None).err().unwrap().kind(),
+            "invalidInput"
+        );
 
         let _ = fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn repo_info_outside_repo_is_null() {
-        let dir = tmp_dir("ecc-kernel-test-norepo");
+    fn git_archive_exports_tar_gz_and_zip() {
+        let dir = tmp_dir("ecc-kernel-test-archive");
+        init_git_repo(&dir).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "// lib\n").unwrap();
+        assert!(git(&dir, &["add", "-A"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "src"]).unwrap().ok);
+        let out_dir = tmp_dir("ecc-kernel-test-archive-out");
+        let archive = |format: &str, name: &str, paths: Vec<String>| {
+            git_archive(GitArchiveIn {
+                repoRoot: dir.display().to_string(),
+                treeish: "HEAD".to_string(),
+                format: format.to_string(),
+                outputPath: out_dir.join(name).display().to_string(),
+                prefix: Some("release".to_string()),
+                paths,
+            })
+        };
+
+        let out = archive("tar.gz", "a.tar.gz", Vec::new()).unwrap();
+        assert_eq!(out.size, fs::metadata(&out.outputPath).unwrap().len());
+        assert!(out.size > 0);
+        let extract = out_dir.join("x");
+        fs::create_dir_all(&extract).unwrap();
+        let tar = Command::new("tar")
+            .arg("-xzf")
+            .arg(&out.outputPath)
+            .arg("-C")
+            .arg(&extract)
+            .status()
+            .unwrap();
+        assert!(tar.success());
+        assert_eq!(
+            fs::read_to_string(extract.join("release/base.txt")).unwrap(),
+            "base\n"
+        );
+        assert!(extract.join("release/src/lib.rs").is_file());
+        assert!(!extract.join("release/.git").exists());
+
+        let zip = archive("zip", "a.zip", vec!["src".to_string()]).unwrap();
+        let bytes = fs::read(&zip.outputPath).unwrap();
+        assert!(bytes.starts_with(b"PK\x03\x04"));
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains("release/src/lib.rs"));
+        assert!(!text.contains("release/base.txt"));
+
+        assert_eq!(
+            archive("7z", "a.7z", Vec::new()).err().unwrap().kind(),
+            "invalidInput"
+        );
+        let missing = archive("tar", "missing.tar", vec!["nope".to_string()]);
+        assert_eq!(missing.err().unwrap().kind(), "gitFailed");
+        assert!(!out_dir.join("missing.tar").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&out_dir);
+    }
+
+    #[test]
+    fn git_count_objects_counts_loose_objects() {
+        let dir = tmp_dir("ecc-kernel-test-count-objects");
+        init_git_repo(&dir).unwrap();
+        let count = |verbose: bool| {
+            git_count_objects(GitCountObjectsIn {
+                repoRoot: dir.display().to_string(),
+                verbose,
+            })
+            .unwrap()
+        };
+
+        // init_git_repo writes one blob, one tree and one commit.
+        assert_eq!(count(false).count, 3);
+        fs::write(dir.join("extra.txt"), "extra\n").unwrap();
+        assert!(git(&dir, &["hash-object", "-w", "extra.txt"]).unwrap().ok);
+        let out = count(false);
+        assert_eq!(out.count, 4);
+        assert_eq!(out.inPack, 0);
+
+        assert!(git(&dir, &["repack", "-q", "-a", "-d"]).unwrap().ok);
+        let verbose = count(true);
+        assert_eq!(verbose.packs, 1);
+        assert_eq!(verbose.inPack, 3);
+        // The unreachable blob stays loose.
+        assert_eq!(verbose.count, 1);
+        assert!(verbose.sizePack > 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_gc_runs_on_empty_and_populated_repos() {
+        let dir = tmp_dir("ecc-kernel-test-gc");
         fs::create_dir_all(&dir).unwrap();
+        assert!(git(&dir, &["init", "-q"]).unwrap().ok);
+        let gc = |auto: bool, prune: Option<&str>| {
+            git_gc(GitGcIn {
+                repoRoot: dir.display().to_string(),
+                auto,
+                aggressive: false,
+                prune: prune.map(|p| p.to_string()),
+            })
+        };
 
-        let info = repo_info(RepoInfoIn {
-            cwd: dir.display().to_string(),
+        assert!(gc(false, None).unwrap().ok);
+        assert!(gc(true, None).unwrap().ok);
+
+        let _ = fs::remove_dir_all(&dir);
+        init_git_repo(&dir).unwrap();
+        assert!(gc(false, Some("now")).unwrap().ok);
+        let counts = git(&dir, &["count-objects", "-v"]).unwrap().stdout;
+        assert!(counts.contains("packs: 1"), "{counts}");
+
+        assert_eq!(
+            gc(false, Some("--all")).err().unwrap().kind(),
+            "invalidInput"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_init_supports_branch_commit_flow() {
+        let dir = tmp_dir("ecc-kernel-test-init");
+        let wt = tmp_dir("ecc-kernel-test-init-wt");
+        let out = git_init(GitInitIn {
+            path: dir.display().to_string(),
+            bare: false,
+            initialBranch: Some("trunk".to_string()),
+            template: None,
         })
         .unwrap();
-        assert_eq!(info.version, 1);
-        assert!(info.repoRoot.is_none());
+        assert!(!out.bare);
+        assert_eq!(out.path, dir.display().to_string());
+        assert_eq!(
+            git(&dir, &["symbolic-ref", "HEAD"]).unwrap().stdout,
+            "refs/heads/trunk"
+        );
+
+        let identity = || GitIdentity {
+            name: "ECC".to_string(),
+            email: "ecc@example.com".to_string(),
+            date: None,
+        };
+        let commit = |repo: &Path, message: &str| {
+            commit_all(CommitAllIn {
+                repoRoot: repo.display().to_string(),
+                message: message.to_string(),
+                author: Some(identity()),
+                committer: Some(identity()),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        fs::write(dir.join("README.md"), "hello\n").unwrap();
+        let root = commit(&dir, "root");
+        assert!(root.previousSha.is_none());
+
+        worktree_ensure(WorktreeEnsureIn {
+            repoRoot: dir.display().to_string(),
+            worktreePath: wt.display().to_string(),
+            branch: "task".to_string(),
+            baseSha: root.sha.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        fs::write(wt.join("task.txt"), "done\n").unwrap();
+        let task = commit(&wt, "task");
+        assert_eq!(task.previousSha.as_deref(), Some(root.sha.as_str()));
+        assert_eq!(git(&dir, &["rev-parse", "task"]).unwrap().stdout, task.sha);
+
+        let bare = tmp_dir("ecc-kernel-test-init-bare");
+        let init_bare = |branch: &str| {
+            git_init(GitInitIn {
+                path: bare.display().to_string(),
+                bare: true,
+                initialBranch: Some(branch.to_string()),
+                template: None,
+            })
+        };
+        assert!(init_bare("main").unwrap().bare);
+        assert_eq!(
+            git(&bare, &["rev-parse", "--is-bare-repository"])
+                .unwrap()
+                .stdout,
+            "true"
+        );
+        assert_eq!(init_bare("bad..name").err().unwrap().kind(), "invalidInput");
+
+        let _ = git(
+            &dir,
+            &["worktree", "remove", "--force", &wt.display().to_string()],
+        );
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&wt);
+        let _ = fs::remove_dir_all(&bare);
+    }
+
+    #[test]
+    fn git_bisect_converges_on_first_bad_commit() {
+        let dir = tmp_dir("ecc-kernel-test-bisect");
+        init_git_repo(&dir).unwrap();
+        let first = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let branch = git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+            .unwrap()
+            .stdout;
+        let mut bad_commit = String::new();
+        for i in 1..5 {
+            let content = if i >= 3 { "bug\n" } else { "ok\n" };
+            fs::write(dir.join("state.txt"), content).unwrap();
+            fs::write(dir.join("n.txt"), format!("{i}\n")).unwrap();
+            assert!(git(&dir, &["add", "-A"]).unwrap().ok);
+            assert!(
+                git(&dir, &["commit", "-q", "-m", &format!("c{i}")])
+                    .unwrap()
+                    .ok
+            );
+            if i == 3 {
+                bad_commit = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+            }
+        }
+        let bisect = |operation: &str, commit: Option<&str>| {
+            git_bisect(GitBisectIn {
+                repoRoot: dir.display().to_string(),
+                operation: operation.to_string(),
+                commit: commit.map(str::to_string),
+            })
+        };
+
+        bisect("start", None).unwrap();
+        bisect("bad", Some("HEAD")).unwrap();
+        let mut out = bisect("good", Some(&first)).unwrap();
+        assert!(out.stepsRemaining.is_some());
+        let mut steps = 0;
+        while !out.done {
+            let current = out.currentCommit.clone().unwrap();
+            assert_eq!(git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout, current);
+            let verdict = match fs::read_to_string(dir.join("state.txt")) {
+                Ok(s) if s == "bug\n" => "bad",
+                _ => "good",
+            };
+            out = bisect(verdict, None).unwrap();
+            steps += 1;
+            assert!(steps < 5, "bisect did not converge");
+        }
+        assert_eq!(out.firstBadCommit.as_deref(), Some(bad_commit.as_str()));
+
+        let log = bisect("log", None).unwrap();
+        assert!(log.done);
+        assert_eq!(log.firstBadCommit.as_deref(), Some(bad_commit.as_str()));
+
+        let reset = bisect("reset", None).unwrap();
+        assert!(!reset.done);
+        assert_eq!(
+            git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+                .unwrap()
+                .stdout,
+            branch
+        );
+
+        assert_eq!(bisect("run", None).err().unwrap().kind(), "invalidInput");
+        assert_eq!(
+            bisect("good", Some("--term-good")).err().unwrap().kind(),
+            "invalidInput"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_worktree_head_reports_attached_and_detached_heads() {
+        let dir = tmp_dir("ecc-kernel-test-wt-head");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let wt = tmp_dir("ecc-kernel-test-wt-head-wt");
+        let other = tmp_dir("ecc-kernel-test-wt-head-other");
+        init_git_repo(&other).unwrap();
+        assert!(
+            git(
+                &dir,
+                &[
+                    "worktree",
+                    "add",
+                    "-q",
+                    "-b",
+                    "feature",
+                    &wt.display().to_string()
+                ]
+            )
+            .unwrap()
+            .ok
+        );
+        let query = |path: &Path| {
+            git_worktree_head(GitWorktreeHeadIn {
+                repoRoot: dir.display().to_string(),
+                worktreePath: path.display().to_string(),
+            })
+        };
+
+        let attached = query(&wt).unwrap();
+        assert_eq!(attached.sha, head);
+        assert_eq!(attached.branch.as_deref(), Some("feature"));
+        assert!(!attached.isDetached);
+
+        assert!(git(&wt, &["checkout", "-q", "--detach"]).unwrap().ok);
+        let detached = query(&wt).unwrap();
+        assert_eq!(detached.sha, head);
+        assert!(detached.branch.is_none());
+        assert!(detached.isDetached);
+
+        assert_eq!(query(&other).err().unwrap().kind(), "notFound");
+        assert_eq!(
+            query(&dir.join("missing")).err().unwrap().kind(),
+            "notFound"
+        );
+
+        let _ = git(
+            &dir,
+            &["worktree", "remove", "--force", &wt.display().to_string()],
+        );
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&wt);
+        let _ = fs::remove_dir_all(&other);
+    }
+
+    #[test]
+    fn git_diff_cached_reports_only_staged_files() {
+        let dir = tmp_dir("ecc-kernel-test-diff-cached");
+        init_git_repo(&dir).unwrap();
+        fs::write(dir.join("other.txt"), "other\n").unwrap();
+        assert!(git(&dir, &["add", "other.txt"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "other"]).unwrap().ok);
+        let diff = |stat: bool, name_only: bool| {
+            git_diff_cached(GitDiffCachedIn {
+                repoRoot: dir.display().to_string(),
+                paths: Vec::new(),
+                stat,
+                nameOnly: name_only,
+            })
+        };
+
+        fs::write(dir.join("base.txt"), "base\nstaged\n").unwrap();
+        fs::write(dir.join("other.txt"), "other\nunstaged\n").unwrap();
+        assert!(diff(false, false).unwrap().isEmpty);
+        assert!(git(&dir, &["add", "base.txt"]).unwrap().ok);
+
+        let out = diff(false, false).unwrap();
+        assert!(!out.isEmpty);
+        let patch = out.patch.unwrap();
+        assert!(patch.contains("+staged"));
+        assert!(!patch.contains("other.txt"));
+        let names = diff(false, true).unwrap().files.unwrap();
+        assert_eq!(names, vec!["base.txt".to_string()]);
+        let stats = diff(true, false).unwrap().stats.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].path, "base.txt");
+        assert_eq!(stats[0].insertions, 1);
+
+        let scoped = git_diff_cached(GitDiffCachedIn {
+            repoRoot: dir.display().to_string(),
+            paths: vec!["other.txt".to_string()],
+            stat: false,
+            nameOnly: false,
+        })
+        .unwrap();
+        assert!(scoped.isEmpty);
+        assert!(diff(true, true).is_err());
+
+        // A hunk ending in blank context lines must survive intact for `git apply`.
+        fs::write(dir.join("blank.txt"), "a\nb\nc\n\n").unwrap();
+        assert!(git(&dir, &["add", "blank.txt"]).unwrap().ok);
+        assert!(git(&dir, &["commit", "-q", "-m", "blank"]).unwrap().ok);
+        fs::write(dir.join("blank.txt"), "A\nb\nc\n\n").unwrap();
+        assert!(git(&dir, &["add", "blank.txt"]).unwrap().ok);
+        let patch = git_diff_cached(GitDiffCachedIn {
+            repoRoot: dir.display().to_string(),
+            paths: vec!["blank.txt".to_string()],
+            stat: false,
+            nameOnly: false,
+        })
+        .unwrap()
+        .patch
+        .unwrap();
+        assert!(patch.ends_with(" c\n \n"), "{patch:?}");
+        let patch_path = dir.join("blank.diff");
+        fs::write(&patch_path, &patch).unwrap();
+        let check = git(
+            &dir,
+            &[
+                "apply",
+                "--check",
+                "-R",
+                "--cached",
+                patch_path.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        assert!(check.ok, "{}", check.stderr);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_symbolic_ref_reads_and_sets_head() {
+        let dir = tmp_dir("ecc-kernel-test-symref");
+        init_git_repo(&dir).unwrap();
+        let branch = git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+            .unwrap()
+            .stdout;
+        let symref = |repo: &Path, value: Option<&str>, delete: bool, short: bool| {
+            git_symbolic_ref(GitSymbolicRefIn {
+                repoRoot: repo.display().to_string(),
+                name: "HEAD".to_string(),
+                value: value.map(str::to_string),
+                delete,
+                short,
+            })
+        };
+
+        assert_eq!(
+            symref(&dir, None, false, true).unwrap().value,
+            Some(branch.clone())
+        );
+        assert_eq!(
+            symref(&dir, None, false, false).unwrap().value,
+            Some(format!("refs/heads/{branch}"))
+        );
+        assert!(git(&dir, &["checkout", "-q", "--detach"]).unwrap().ok);
+        assert_eq!(symref(&dir, None, false, false).unwrap().value, None);
+
+        let bare = tmp_dir("ecc-kernel-test-symref-bare");
+        fs::create_dir_all(&bare).unwrap();
+        assert!(git(&bare, &["init", "-q", "--bare"]).unwrap().ok);
+        let set = symref(&bare, Some("refs/heads/trunk"), false, true).unwrap();
+        assert_eq!(set.value.as_deref(), Some("trunk"));
+        assert_eq!(
+            git(&bare, &["symbolic-ref", "HEAD"]).unwrap().stdout,
+            "refs/heads/trunk"
+        );
+
+        assert_eq!(
+            symref(&bare, Some("trunk"), false, false)
+                .err()
+                .unwrap()
+                .kind(),
+            "invalidInput"
+        );
+        assert_eq!(
+            symref(&bare, Some("refs/heads/x"), true, false)
+                .err()
+                .unwrap()
+                .kind(),
+            "invalidInput"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&bare);
+    }
+
+    #[test]
+    fn git_notes_add_show_list_and_remove() {
+        let dir = tmp_dir("ecc-kernel-test-notes");
+        init_git_repo(&dir).unwrap();
+        let head = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let notes = |operation: &str, message: Option<&str>, notes_ref: Option<&str>| {
+            git_notes(GitNotesIn {
+                repoRoot: dir.display().to_string(),
+                operation: operation.to_string(),
+                commit: None,
+                message: message.map(str::to_string),
+                ref_: notes_ref.map(str::to_string),
+            })
+        };
+
+        assert_eq!(notes("show", None, None).unwrap().note, None);
+        let added = notes("add", Some("ci: passed"), None).unwrap();
+        assert_eq!(added.note.as_deref(), Some("ci: passed"));
+        assert_eq!(
+            notes("show", None, None).unwrap().note.as_deref(),
+            Some("ci: passed")
+        );
+        let appended = notes("append", Some("coverage: 91%"), None).unwrap();
+        assert_eq!(
+            appended.note.as_deref(),
+            Some("ci: passed\n\ncoverage: 91%")
+        );
+
+        let list = notes("list", None, None).unwrap();
+        assert_eq!(list.entries.len(), 1);
+        assert_eq!(list.entries[0].objectSha, head);
+        assert_eq!(list.entries[0].notesSha.len(), 40);
+
+        // Namespaced notes are independent of the default ref.
+        assert_eq!(notes("show", None, Some("ci")).unwrap().note, None);
+        notes("add", Some("ci only"), Some("ci")).unwrap();
+        assert!(
+            git(&dir, &["rev-parse", "--verify", "refs/notes/ci"])
+                .unwrap()
+                .ok
+        );
+
+        notes("remove", None, None).unwrap();
+        assert!(notes("list", None, None).unwrap().entries.is_empty());
+        assert_eq!(
+            notes("show", None, Some("ci")).unwrap().note.as_deref(),
+            Some("ci only")
+        );
+
+        assert_eq!(
+            notes("add", None, None).err().unwrap().kind(),
+            "invalidInput"
+        );
+        assert_eq!(
+            notes("prune", None, None).err().unwrap().kind(),
+            "invalidInput"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn git_bundle_create_verify_and_list_heads() {
+        let dir = tmp_dir("ecc-kernel-test-bundle");
+        init_git_repo(&dir).unwrap();
+        let branch = git(&dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+            .unwrap()
+            .stdout;
+        let first = git(&dir, &["rev-parse", "HEAD"]).unwrap().stdout;
+        let out_dir = tmp_dir("ecc-kernel-test-bundle-out");
+        fs::create_dir_all(&out_dir).unwrap();
+        let empty = tmp_dir("ecc-kernel-test-bundle-empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(git(&empty, &["init", "-q"]).unwrap().ok);
+        let bundle = |repo: &Path, operation: &str, name: &str, prerequisites: Vec<String>| {
+            git_bundle(GitBundleIn {
+                repoRoot: repo.display().to_string(),
+                operation: operation.to_string(),
+                bundlePath: out_dir.join(name).display().to_string(),
+                refs: vec![branch.clone()],
+                prerequisites,
+            })
+        };
+
+        let full = bundle(&dir, "create", "full.bundle", Vec::new()).unwrap();
+        assert_eq!(full.heads, vec![format!("refs/heads/{branch}")]);
+        let verified = bundle(&empty, "verify", "full.bundle", Vec::new()).unwrap();
+        assert!(verified.ok);
+        assert_eq!(verified.heads, full.heads);
+        assert!(verified.prerequisites.is_empty());
+
+        fs::write(dir.join("base.txt"), "second\n").unwrap();
+        assert!(git(&dir, &["commit", "-q", "-am", "second"]).unwrap().ok);
+        let thin = bundle(&dir, "create", "thin.bundle", vec![first.clone()]).unwrap();
+        assert_eq!(thin.prerequisites, vec![first.clone()]);
+        assert!(
+            bundle(&dir, "verify", "thin.bundle", Vec::new())
+                .unwrap()
+                .ok
+        );
+        let missing = bundle(&empty, "verify", "thin.bundle", Vec::new()).unwrap();
+        assert!(!missing.ok);
+        assert_eq!(missing.prerequisites, vec![first]);
+        assert_eq!(missing.heads, thin.heads);
+
+        let heads = bundle(&empty, "list_heads", "thin.bundle", Vec::new()).unwrap();
+        assert_eq!(heads.heads, vec![format!("refs/heads/{branch}")]);
+        assert_eq!(
+            bundle(&empty, "verify", "nope.bundle", Vec::new())
+                .err()
+                .unwrap()
+                .kind(),
+            "notFound"
+        );
+        assert_eq!(
+            bundle(&dir, "unbundle", "full.bundle", Vec::new())
+                .err()
+                .unwrap()
+                .kind(),
+            "invalidInput"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&out_dir);
+        let _ = fs::remove_dir_all(&empty);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn git_credential_round_trips_through_a_helper() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tmp_dir("ecc-kernel-test-credential");
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("helper.log");
+        let helper = dir.join("helper.sh");
+        fs::write(
+            &helper,
+            format!(
+                "#!/bin/sh\necho \"op=$1\" >> '{log}'\ncat >> '{log}'\n\
+if [ \"$1\" = get ]; then printf 'username=mock\\npassword=s3cret\\n'; fi\n",
+                log = log.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        let config = dir.join("gitconfig");
+        fs::write(
+            &config,
+            format!("[credential]\n\thelper = {}\n", helper.display()),
+        )
+        .unwrap();
+        let env = [
+            (
+                "GIT_CONFIG_GLOBAL".to_string(),
+                config.display().to_string(),
+            ),
+            ("GIT_CONFIG_NOSYSTEM".to_string(), "1".to_string()),
+        ];
+        let credential = |operation: &str, password: Option<&str>| {
+            run_git_credential(
+                GitCredentialIn {
+                    repoRoot: None,
+                    operation: operation.to_string(),
+                    protocol: "https".to_string(),
+                    host: "example.com".to_string(),
+                    username: password.map(|_| "mock".to_string()),
+                    password: password.map(str::to_string),
+                },
+                &env,
+            )
+        };
+
+        let filled = credential("fill", None).unwrap();
+        assert_eq!(filled.username.as_deref(), Some("mock"));
+        assert_eq!(filled.password.as_deref(), Some("s3cret"));
+        credential("approve", Some("s3cret")).unwrap();
+        credential("reject", Some("s3cret")).unwrap();
+        let calls = fs::read_to_string(&log).unwrap();
+        assert!(
+            calls.contains("op=get\nprotocol=https\nhost=example.com\n"),
+            "{calls}"
+        );
+        assert!(calls.contains("op=store\n"), "{calls}");
+        assert!(calls.contains("op=erase\n"), "{calls}");
+
+        let err = credential("approve", Some("x\nhost=evil.example"))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "invalidInput");
+        assert!(!err.to_string().contains("evil"));
+        assert_eq!(
+            credential("store", None).err().unwrap().kind(),
+            "invalidInput"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn audit_log_keeps_concurrent_entries_on_separate_lines() {
+        let dir = tmp_dir("ecc-kernel-test-audit");
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("audit.jsonl");
+        let input = serde_json::json!({
+            "repoRoot": "/repo",
+            "password": "hunter2",
+            "commands": [{ "name": "t", "env": { "TOKEN": "abc" } }],
+            "notes": "x".repeat(256 * 1024),
+        });
+
+        std::thread::scope(|s| {
+            for command in ["git.status", "verify.run"] {
+                let (log, input) = (&log, &input);
+                s.spawn(move || {
+                    let entry = AuditEntry {
+                        timestamp: now_iso(),
+                        command: command.to_string(),
+                        inputSummary: audit_input_summary(input),
+                        ok: true,
+                        errorKind: None,
+                        durationMs: 1,
+                        pid: std::process::id(),
+                    };
+                    write_audit_entry(log, &entry).unwrap();
+                });
+            }
+        });
+
+        let text = fs::read_to_string(&log).unwrap();
+        let entries: Vec<AuditEntry> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        let mut commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        commands.sort();
+        assert_eq!(commands, vec!["git.status", "verify.run"]);
+        let summary = &entries[0].inputSummary;
+        assert_eq!(summary["repoRoot"], "/repo");
+        assert_eq!(summary["password"], "[redacted]");
+        assert_eq!(summary["commands"][0]["env"], "[redacted]");
+        assert!(!text.contains("hunter2"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn audit_log_records_commands_rejected_by_allowed_roots() {
+        let dir = tmp_dir("ecc-kernel-test-audit-roots");
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("audit.jsonl");
+        let roots = vec![dir.display().to_string()];
+        let input = serde_json::json!({ "path": "/etc/passwd" });
+
+        let err = run_audited("fs.read", input, &roots, Some(&log)).unwrap_err();
+        assert_eq!(err.kind(), "pathTraversal");
+        let ok = serde_json::json!({ "path": log.display().to_string() });
+        assert!(run_audited("fs.stat", ok, &roots, Some(&log)).is_ok());
+
+        let entries: Vec<AuditEntry> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "fs.read");
+        assert!(!entries[0].ok);
+        assert_eq!(entries[0].errorKind.as_deref(), Some("pathTraversal"));
+        assert_eq!(entries[0].inputSummary["path"], "/etc/passwd");
+        assert!(entries[1].ok);
+        assert_eq!(entries[1].errorKind, None);
 
         let _ = fs::remove_dir_all(&dir);
     }
+
+    #[test]
+    fn allowed_roots_reject_paths_outside_every_root() {
+        let roots = vec!["/work".to_string(), "/tmp/ecc/".to_string()];
+        let check = |input: serde_json::Value| check_allowed_roots(&input, &roots);
+
+        assert!(check(serde_json::json!({ "repoRoot": "/work/repo", "paths": ["../x"] })).is_ok());
+        assert!(check(serde_json::json!({ "src": "/tmp/ecc/a", "dst": "/work/b" })).is_ok());
+        assert!(check(serde_json::json!({ "repoRoot": "/etc" })).is_err());
+        assert!(check(serde_json::json!({ "repoRoot": "/work/../etc" })).is_err());
+        assert!(check(serde_json::json!({ "repoRoot": "/workshop" })).is_err());
+        assert!(check(
+            serde_json::json!({ "worktreePaths": ["/work/a", "/var/b"], "repoRoot": "/work" })
+        )
+        .is_err());
+        let err = check(serde_json::json!({
+            "operations": [{ "command": "fs.read", "input": { "path": "/etc/passwd" } }]
+        }))
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "pathTraversal");
+        assert!(
+            err.to_string()
+                .contains("path is outside allowedRoots: /etc/passwd"),
+            "{err}"
+        );
+        // Relative values resolve against the enclosing worktree.
+        let verify = |junit: &str| {
+            check(serde_json::json!({
+                "worktreePath": "/work/wt",
+                "outDir": "/work/out",
+                "commands": [{ "name": "t", "command": "true", "junitOutputPath": junit }]
+            }))
+        };
+        assert!(verify("target/junit.xml").is_ok());
+        assert!(verify("../../../etc/junit.xml").is_err());
+
+        // verify.clean's pattern and local remotes are paths too; network URLs and names are not.
+        assert!(check(serde_json::json!({ "outDirPattern": "/etc/verify-*" })).is_err());
+        assert!(check(serde_json::json!({ "repoRoot": "/work", "url": "/etc/repo" })).is_err());
+        assert!(check(serde_json::json!({ "url": "file:///etc/repo" })).is_err());
+        assert!(check(serde_json::json!({ "repoRoot": "/work", "remote": "../../etc" })).is_err());
+        assert!(check(serde_json::json!({ "url": "https://example.com/r.git" })).is_ok());
+        assert!(check(serde_json::json!({ "url": "git@example.com:r.git" })).is_ok());
+        assert!(check(serde_json::json!({ "remote": "origin" })).is_ok());
+
+        assert!(check_allowed_roots(&serde_json::json!({ "repoRoot": "/etc" }), &[]).is_ok());
+    }
+
+    #[test]
+    fn allowed_roots_cover_every_path_like_input_field() {
+        fn field_names(schema: &serde_json::Value, names: &mut BTreeSet<String>) {
+            match schema {
+                serde_json::Value::Object(map) => {
+                    if let Some(props) = map.get("properties").and_then(|p| p.as_object()) {
+                        names.extend(props.keys().cloned());
+                    }
+                    map.values().for_each(|v| field_names(v, names));
+                }
+                serde_json::Value::Array(items) => items.iter().for_each(|v| field_names(v, names)),
+                _ => {}
+            }
+        }
+        let mut names = BTreeSet::new();
+        for cmd in COMMANDS {
+            field_names(&command_schema(cmd).unwrap()["input"], &mut names);
+        }
+        let path_like = |name: &str| {
+            let lower = name.to_ascii_lowercase();
+            ["path", "paths", "dir", "root", "dirpattern"]
+                .iter()
+                .any(|suffix| lower.ends_with(suffix))
+                || ["cwd", "src", "dst", "url", "remote", "template"].contains(&name)
+        };
+        let missing: Vec<_> = names
+            .iter()
+            .filter(|n| path_like(n) && !PATH_KEYS.contains(&n.as_str()))
+            .collect();
+        assert!(missing.is_empty(), "not in PATH_KEYS: {missing:?}");
+    }
+
+    #[test]
+    fn allowed_roots_env_overrides_config() {
+        let config = vec!["/from/config".to_string()];
+        assert_eq!(allowed_roots_from(None, &config), config);
+        assert_eq!(allowed_roots_from(Some("".into()), &config), config);
+        let env = std::env::join_paths(["/a", "/b/c"]).unwrap();
+        assert_eq!(
+            allowed_roots_from(Some(env), &config),
+            vec!["/a".to_string(), "/b/c".to_string()]
+        );
+        assert!(allowed_roots_from(None, &[]).is_empty());
+    }
+
+    #[test]
+    fn completion_scripts_list_every_command_and_flag() {
+        for shell in COMPLETION_SHELLS {
+            let script = completion_script(shell).unwrap();
+            for cmd in COMMANDS {
+                assert!(script.contains(cmd), "{shell} is missing {cmd}");
+            }
+            for (flag, _, _) in GLOBAL_FLAGS {
+                let name = if *shell == "fish" {
+                    flag.trim_start_matches("--")
+                } else {
+                    flag
+                };
+                assert!(script.contains(name), "{shell} is missing {flag}");
+            }
+        }
+        assert!(completion_script("powershell").is_none());
+
+        let args: Vec<String> = ["completion", "--shell", "zsh"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let cli = parse_cli_args(&args).unwrap();
+        assert_eq!(cli.command.as_deref(), Some("completion"));
+        assert_eq!(cli.shell.as_deref(), Some("zsh"));
+
+        if let Ok(status) = Command::new("bash")
+            .arg("-n")
+            .arg("-c")
+            .arg(completion_script("bash").unwrap())
+            .status()
+        {
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn json_schema_covers_every_command_and_validates_inputs() {
+        for cmd in COMMANDS {
+            assert!(command_schema(cmd).is_some(), "missing schema for {cmd}");
+        }
+        assert!(command_schema("git.nope").is_none());
+
+        let schema = command_schema("git.bundle").unwrap();
+        let input = jsonschema::validator_for(&schema["input"]).unwrap();
+        assert!(input.is_valid(&serde_json::json!({
+            "repoRoot": "/repo",
+            "operation": "create",
+            "bundlePath": "/tmp/repo.bundle",
+            "refs": ["main"],
+        })));
+        assert!(!input.is_valid(&serde_json::json!({ "repoRoot": "/repo", "operation": "verify" })));
+        assert!(!input.is_valid(&serde_json::json!({
+            "repoRoot": "/repo",
+            "operation": "verify",
+            "bundlePath": 7,
+        })));
+        assert_eq!(
+            schema["input"]["properties"]["operation"]["description"],
+            "`create`, `verify` or `list_heads`."
+        );
+
+        let output = jsonschema::validator_for(&schema["output"]).unwrap();
+        let sample = GitBundleOut {
+            ok: true,
+            heads: vec!["refs/heads/main".to_string()],
+            prerequisites: Vec::new(),
+        };
+        assert!(output.is_valid(&serde_json::to_value(sample).unwrap()));
+
+        let args: Vec<String> = ["--json-schema", "git.log"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            parse_cli_args(&args).unwrap().jsonSchema.as_deref(),
+            Some("git.log")
+        );
+    }
 }
@@ -82,7 +82,8 @@ function applyPatch({ worktreePath, patchPath, allowedPathPrefixes }) {
     allowedPathPrefixes: Array.isArray(allowedPathPrefixes) ? allowedPathPrefixes : []
   });
   if (kernelOut && Array.isArray(kernelOut.touchedFiles)) {
-    return { touchedFiles: kernelOut.touchedFiles };
+    // Newer kernels report `{ path, invalid, status }` objects; older ones plain paths.
+    return { touchedFiles: kernelOut.touchedFiles.map(t => (typeof t === 'string' ? t : t.path)) };
   }
 
   const patchText = fs.readFileSync(patchPath, 'utf8');
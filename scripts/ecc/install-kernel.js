@@ -270,6 +270,11 @@ function validateProtocol(filePath) {
   } catch (_err) {
     throw new Error('kernel protocol.version returned non-JSON output');
   }
+  // Responses are wrapped as `{ ok, result }` since protocol 2.
+  if (!obj || typeof obj !== 'object' || obj.ok !== true) {
+    throw new Error('kernel protocol.version returned no result envelope');
+  }
+  obj = obj.result;
 
   const errors = validateProtocolVersionOutput(obj, { expectedProtocol: EXPECTED_PROTOCOL });
   if (errors.length) {
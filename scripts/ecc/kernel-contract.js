@@ -6,7 +6,7 @@ function isInt(v) {
   return Number.isInteger(v);
 }
 
-const EXPECTED_PROTOCOL = 1;
+const EXPECTED_PROTOCOL = 2;
 
 // Commands that the Node engine expects the Rust kernel to support.
 const REQUIRED_COMMANDS = [
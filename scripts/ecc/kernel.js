@@ -58,6 +58,24 @@ function tryKernelFromPath() {
   return 'ecc-kernel';
 }
 
+// Kernel stdout is an envelope: `{ ok: true, result }` or `{ ok: false, error: { kind, message, details } }`.
+function parseEnvelope(stdout) {
+  let parsed;
+  try {
+    parsed = JSON.parse(stdout);
+  } catch (_err) {
+    return null;
+  }
+  if (!parsed || typeof parsed !== 'object' || typeof parsed.ok !== 'boolean') return null;
+  return parsed;
+}
+
+function envelopeErrorMessage(envelope) {
+  const err = envelope && envelope.error ? envelope.error : {};
+  const kind = err.kind ? String(err.kind) : 'error';
+  return `${kind}: ${err.message ? String(err.message) : 'unknown error'}`;
+}
+
 function runKernelJson(bin, command, inputObj) {
   const res = spawnSync(bin, [command], {
     encoding: 'utf8',
@@ -69,18 +87,17 @@ function runKernelJson(bin, command, inputObj) {
   const stdout = (res.stdout || '').trim();
   const stderr = (res.stderr || '').trim();
 
+  const envelope = stdout ? parseEnvelope(stdout) : null;
   if (res.status !== 0) {
+    if (envelope && !envelope.ok) return { ok: false, error: `exit ${res.status} (${envelopeErrorMessage(envelope)})` };
     const detail = stderr ? `stderr: ${stderr}` : (stdout ? `stdout: ${stdout}` : '');
     return { ok: false, error: `exit ${res.status}${detail ? ` (${detail})` : ''}` };
   }
 
   if (!stdout) return { ok: false, error: 'empty stdout' };
-  try {
-    return { ok: true, value: JSON.parse(stdout) };
-  } catch (err) {
-    const msg = err && err.message ? err.message : String(err);
-    return { ok: false, error: `non-JSON stdout (${msg})` };
-  }
+  if (!envelope) return { ok: false, error: 'non-JSON or unwrapped stdout' };
+  if (!envelope.ok) return { ok: false, error: envelopeErrorMessage(envelope) };
+  return { ok: true, value: envelope.result };
 }
 
 function probeKernel(bin) {
@@ -228,11 +245,12 @@ function runKernel(command, inputObj) {
   }
   const stdout = (res.stdout || '').trim();
   const stderr = (res.stderr || '').trim();
-  if (res.status !== 0) {
+  const envelope = stdout ? parseEnvelope(stdout) : null;
+  if (res.status !== 0 || (envelope && !envelope.ok)) {
     const msg = [
       `ecc-kernel ${command} failed (exit ${res.status})`,
-      stderr ? `stderr:\n${stderr}` : null,
-      stdout ? `stdout:\n${stdout}` : null
+      envelope && !envelope.ok ? envelopeErrorMessage(envelope) : (stdout ? `stdout:\n${stdout}` : null),
+      stderr ? `stderr:\n${stderr}` : null
     ]
       .filter(Boolean)
       .join('\n\n');
@@ -240,12 +258,10 @@ function runKernel(command, inputObj) {
   }
 
   if (!stdout) return {};
-  try {
-    return JSON.parse(stdout);
-  } catch (err) {
-    const detail = err && err.message ? err.message : String(err);
-    throw new Error(`ecc-kernel returned non-JSON output (${detail}). Raw:\n${stdout.slice(0, 2000)}`);
+  if (!envelope) {
+    throw new Error(`ecc-kernel returned non-JSON or unwrapped output. Raw:\n${stdout.slice(0, 2000)}`);
   }
+  return envelope.result === undefined ? {} : envelope.result;
 }
 
 module.exports = {